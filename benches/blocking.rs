@@ -0,0 +1,64 @@
+//! Measure the blocking enqueue/dequeue paths of `SyncVectoredQueue`, whose lock and condition
+//! variable come from std or from parking_lot.
+//!
+//! Run with `cargo bench --bench blocking --features sync`, and again with
+//! `--features sync,parking_lot` to compare.
+
+use std::{hint::black_box, thread, time::Instant};
+
+use vectored_queue::SyncVectoredQueue;
+
+const ITEMS: usize = 200_000;
+
+fn run(producers: usize) {
+    // a small capacity makes producers block on a full queue
+    let queue = SyncVectoredQueue::with_capacity(16);
+    let per_producer = ITEMS / producers;
+    let start = Instant::now();
+    let mut latencies = thread::scope(|s| {
+        let handles: Vec<_> = (0..producers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut latencies = Vec::with_capacity(per_producer);
+                    for _ in 0..per_producer {
+                        let start = Instant::now();
+                        queue.enqueue([0u8; 64]).unwrap();
+                        latencies.push(start.elapsed());
+                    }
+                    latencies
+                })
+            })
+            .collect();
+        let mut dequeued = 0;
+        while dequeued < per_producer * producers {
+            dequeued += black_box(queue.dequeue_vectored().unwrap()).len();
+        }
+        let latencies: Vec<_> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        latencies
+    });
+    let elapsed = start.elapsed();
+    latencies.sort_unstable();
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+    let rate = latencies.len() as f64 / elapsed.as_secs_f64() / 1e6;
+    println!(
+        "{producers:>2} producers: {rate:.2}M items/s, enqueue p50 {:?}, p99 {:?}, p99.9 {:?}",
+        percentile(0.5),
+        percentile(0.99),
+        percentile(0.999),
+    );
+}
+
+fn main() {
+    let locks = if cfg!(feature = "parking_lot") {
+        "parking_lot"
+    } else {
+        "std"
+    };
+    println!("{locks} locks");
+    for producers in [1, 4, 16] {
+        run(producers);
+    }
+}
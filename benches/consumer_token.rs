@@ -0,0 +1,41 @@
+//! Compare dequeuing with the shared claim, detecting conflicts, and with a consumer token.
+//!
+//! Batches are kept small to highlight the per-dequeue cost. Run with
+//! `cargo bench --bench consumer_token`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use vectored_queue::VectoredQueue;
+
+const BATCH: usize = 4;
+const DURATION: Duration = Duration::from_secs(1);
+
+fn run(name: &str, with_token: bool) {
+    let queue = VectoredQueue::with_capacity(BATCH);
+    let mut token = with_token.then(|| queue.consumer_token().unwrap());
+    let start = Instant::now();
+    let mut dequeues = 0u64;
+    while start.elapsed() < DURATION {
+        for _ in 0..1024 {
+            for i in 0..BATCH {
+                queue.try_enqueue([i as u8; 8]).unwrap();
+            }
+            let res = match &mut token {
+                Some(token) => queue.try_dequeue_token(token),
+                None => queue.try_dequeue_vectored(),
+            };
+            black_box(res.unwrap().vectored().unwrap().total_size());
+            dequeues += 1;
+        }
+    }
+    let rate = dequeues as f64 / start.elapsed().as_secs_f64() / 1e6;
+    println!("{name:>8}: {rate:.2}M dequeues/s");
+}
+
+fn main() {
+    run("shared", false);
+    run("token", true);
+}
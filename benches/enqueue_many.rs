@@ -0,0 +1,64 @@
+//! Compare `try_enqueue_many` with a `try_enqueue` loop under producer contention.
+//!
+//! Run with `cargo bench --bench enqueue_many`.
+
+use std::{
+    hint::black_box,
+    iter,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use vectored_queue::VectoredQueue;
+
+const PRODUCERS: usize = 4;
+const BATCH: usize = 200;
+const DURATION: Duration = Duration::from_secs(1);
+
+fn run(name: &str, enqueue: impl Fn(&VectoredQueue<[u8; 64]>) -> usize + Sync) {
+    let queue = VectoredQueue::with_capacity(1024);
+    let stop = AtomicBool::new(false);
+    let (enqueued, dequeued) = thread::scope(|s| {
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut enqueued = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        enqueued += enqueue(&queue);
+                    }
+                    enqueued
+                })
+            })
+            .collect();
+        let start = Instant::now();
+        let mut dequeued = 0;
+        while start.elapsed() < DURATION {
+            if let Some(vectored) = queue.try_dequeue_vectored().unwrap().vectored() {
+                dequeued += black_box(vectored).len();
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        let enqueued: usize = producers.into_iter().map(|p| p.join().unwrap()).sum();
+        (enqueued, dequeued)
+    });
+    let rate = enqueued as f64 / DURATION.as_secs_f64() / 1e6;
+    println!("{name:>16}: {rate:.2}M items/s enqueued, {dequeued} dequeued");
+}
+
+fn main() {
+    run("try_enqueue loop", |queue| {
+        let mut count = 0;
+        for item in iter::repeat_n([0; 64], BATCH) {
+            if queue.try_enqueue(item).is_err() {
+                break;
+            }
+            count += 1;
+        }
+        count
+    });
+    run("try_enqueue_many", |queue| {
+        let mut items = iter::repeat_n([0; 64], BATCH).peekable();
+        queue.try_enqueue_many(&mut items).unwrap()
+    });
+}
@@ -0,0 +1,52 @@
+//! Compare gathering small heap-allocated items from their own allocations with gathering
+//! them from the inline arena.
+//!
+//! Run with `cargo bench --bench inline_small`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use vectored_queue::VectoredQueue;
+
+const CAPACITY: usize = 1024;
+const ITEM_SIZE: usize = 48;
+const DURATION: Duration = Duration::from_secs(1);
+
+fn run(name: &str, inline_threshold: usize) {
+    let queue = VectoredQueue::builder()
+        .capacity(CAPACITY)
+        .inline_threshold(inline_threshold)
+        .build()
+        .unwrap();
+    // items allocated interleaved with other allocations, as in a real producer
+    let mut noise = Vec::new();
+    let mut gathered = vec![0; CAPACITY * ITEM_SIZE];
+    let start = Instant::now();
+    let mut items = 0;
+    while start.elapsed() < DURATION {
+        for i in 0..CAPACITY {
+            noise.push(vec![0u8; i % 128]);
+            let item: Box<[u8]> = vec![i as u8; ITEM_SIZE].into();
+            queue.try_enqueue(item).unwrap();
+        }
+        noise.clear();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        // stands for the gathering of `writev`
+        let mut offset = 0;
+        for slice in vectored.iter() {
+            gathered[offset..offset + slice.len()].copy_from_slice(slice);
+            offset += slice.len();
+        }
+        black_box(&gathered);
+        items += vectored.len();
+    }
+    let rate = items as f64 / start.elapsed().as_secs_f64() / 1e6;
+    println!("{name:>8}: {rate:.2}M items/s");
+}
+
+fn main() {
+    run("heap", 0);
+    run("inline", 64);
+}
@@ -0,0 +1,65 @@
+//! Measure the enqueue-to-dequeue round trip of `SyncVectoredQueue`, with blocking consumers
+//! woken by the condition variable, and with busy-spinning consumers.
+//!
+//! Run with `cargo bench --bench spin_latency --features sync`; spinning is only meaningful
+//! with a core per thread.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use vectored_queue::{SpinDecision, SyncVectoredQueue};
+
+const ROUND_TRIPS: usize = 20_000;
+
+fn dequeue(queue: &SyncVectoredQueue<[u8; 8]>, spin: bool) {
+    if spin {
+        // yield from time to time, so that it also runs without a core per thread
+        let idle = |n| match n % 16 {
+            0 => SpinDecision::Yield,
+            _ => SpinDecision::Spin,
+        };
+        while queue.dequeue_vectored_spin(idle).unwrap().is_none() {}
+    } else {
+        queue.dequeue_vectored().unwrap();
+    }
+}
+
+fn run(name: &str, spin: bool) {
+    let (ping, pong) = (
+        SyncVectoredQueue::with_capacity(1),
+        SyncVectoredQueue::with_capacity(1),
+    );
+    ping.set_busy_spin(spin);
+    pong.set_busy_spin(spin);
+    let mut latencies = thread::scope(|s| {
+        s.spawn(|| {
+            for _ in 0..ROUND_TRIPS {
+                dequeue(&ping, spin);
+                pong.enqueue([0; 8]).unwrap();
+            }
+        });
+        let mut latencies = Vec::<Duration>::with_capacity(ROUND_TRIPS);
+        for _ in 0..ROUND_TRIPS {
+            let start = Instant::now();
+            ping.enqueue([0; 8]).unwrap();
+            dequeue(&pong, spin);
+            latencies.push(start.elapsed());
+        }
+        latencies
+    });
+    latencies.sort_unstable();
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+    println!(
+        "{name:>8}: round trip p50 {:?}, p99 {:?}, p99.9 {:?}",
+        percentile(0.5),
+        percentile(0.99),
+        percentile(0.999),
+    );
+}
+
+fn main() {
+    run("condvar", false);
+    run("spin", true);
+}
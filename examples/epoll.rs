@@ -0,0 +1,89 @@
+//! Consumer multiplexing the queue and a UDP socket in an `epoll` loop.
+#[cfg(target_os = "linux")]
+fn main() -> std::io::Result<()> {
+    use std::{
+        io,
+        net::UdpSocket,
+        os::fd::{AsRawFd, RawFd},
+        sync::Arc,
+        thread,
+    };
+
+    use vectored_queue::SyncVectoredQueue;
+
+    const QUEUE: u64 = 0;
+    const SOCKET: u64 = 1;
+
+    fn register(epoll: RawFd, fd: RawFd, token: u64) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token,
+        };
+        match unsafe { libc::epoll_ctl(epoll, libc::EPOLL_CTL_ADD, fd, &mut event) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    let queue = Arc::new(SyncVectoredQueue::with_capacity(64));
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    let addr = socket.local_addr()?;
+    let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    if epoll == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    register(epoll, queue.readiness_fd().as_raw_fd(), QUEUE)?;
+    register(epoll, socket.as_raw_fd(), SOCKET)?;
+    let producer = thread::spawn({
+        let queue = queue.clone();
+        move || {
+            for i in 0..3 {
+                queue
+                    .enqueue(format!("message {i}\n").into_bytes())
+                    .unwrap();
+            }
+            UdpSocket::bind("127.0.0.1:0")?.send_to(b"datagram\n", addr)?;
+            queue.close();
+            io::Result::Ok(())
+        }
+    });
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 2];
+    let (mut closed, mut received) = (false, false);
+    while !closed || !received {
+        let n = unsafe { libc::epoll_wait(epoll, events.as_mut_ptr(), 2, -1) };
+        if n == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        for event in &events[..n as usize] {
+            match event.u64 {
+                // readability may be spurious, so the dequeuing may be empty
+                QUEUE => match queue.try_dequeue_vectored() {
+                    Ok(res) => {
+                        for slice in res.vectored().iter().flat_map(|v| v.iter()) {
+                            io::Write::write_all(&mut io::stdout(), slice)?;
+                        }
+                    }
+                    // the closed queue stays readable, so it is deregistered
+                    Err(_) => {
+                        closed = true;
+                        let fd = queue.readiness_fd().as_raw_fd();
+                        let null = std::ptr::null_mut();
+                        unsafe { libc::epoll_ctl(epoll, libc::EPOLL_CTL_DEL, fd, null) };
+                    }
+                },
+                _ => {
+                    let mut buf = [0; 64];
+                    let n = socket.recv(&mut buf)?;
+                    print!("{}", String::from_utf8_lossy(&buf[..n]));
+                    received = true;
+                }
+            }
+        }
+    }
+    producer.join().unwrap()?;
+    unsafe { libc::close(epoll) };
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
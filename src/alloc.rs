@@ -0,0 +1,86 @@
+// Allocator API of the queue storage: the `allocator-api2` one with the feature, otherwise a
+// minimal one only implemented by the global allocator, backed by the std collections.
+
+#[cfg(feature = "allocator-api2")]
+pub(crate) use allocator_api2::{
+    alloc::{Allocator, Global},
+    boxed::Box,
+    vec::Vec,
+};
+#[cfg(not(feature = "allocator-api2"))]
+pub(crate) use global::{Allocator, Box, Global, Vec};
+
+#[cfg(not(feature = "allocator-api2"))]
+mod global {
+    use std::ops::{Deref, DerefMut};
+
+    /// The global memory allocator, the only one without the `allocator-api2` feature.
+    #[derive(Debug, Default, Copy, Clone)]
+    pub struct Global;
+
+    /// Allocator of the queue storage, sealed without the `allocator-api2` feature.
+    pub trait Allocator: sealed::Sealed {}
+
+    impl Allocator for Global {}
+
+    mod sealed {
+        pub trait Sealed {}
+
+        impl Sealed for super::Global {}
+    }
+
+    // The allocator is always the global one, so it is only carried for the signatures
+    pub struct Vec<T, A: Allocator = Global>(std::vec::Vec<T>, A);
+
+    impl<T, A: Allocator> Vec<T, A> {
+        pub fn new_in(alloc: A) -> Self {
+            Self(std::vec::Vec::new(), alloc)
+        }
+
+        pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+            Self(std::vec::Vec::with_capacity(capacity), alloc)
+        }
+
+        pub fn allocator(&self) -> &A {
+            &self.1
+        }
+
+        pub fn into_boxed_slice(self) -> Box<[T], A> {
+            Box(self.0.into_boxed_slice(), self.1)
+        }
+    }
+
+    impl<T, A: Allocator> Deref for Vec<T, A> {
+        type Target = std::vec::Vec<T>;
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl<T, A: Allocator> DerefMut for Vec<T, A> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.0
+        }
+    }
+
+    impl<T, A: Allocator> IntoIterator for Vec<T, A> {
+        type Item = T;
+        type IntoIter = std::vec::IntoIter<T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.0.into_iter()
+        }
+    }
+
+    pub struct Box<T: ?Sized, A: Allocator = Global>(std::boxed::Box<T>, A);
+
+    impl<T: ?Sized, A: Allocator> Box<T, A> {
+        pub fn into_raw_with_allocator(boxed: Self) -> (*mut T, A) {
+            (std::boxed::Box::into_raw(boxed.0), boxed.1)
+        }
+
+        // The pointer must come from `into_raw_with_allocator`
+        pub unsafe fn from_raw_in(raw: *mut T, alloc: A) -> Self {
+            Self(unsafe { std::boxed::Box::from_raw(raw) }, alloc)
+        }
+    }
+}
@@ -1,17 +1,77 @@
-use std::task::Poll;
+use std::{
+    fmt,
+    future::Future,
+    io::{self, IoSlice},
+    iter::Peekable,
+    mem,
+    pin::{pin, Pin},
+    ptr,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
 
-use futures::task::AtomicWaker;
+use futures::{future::FusedFuture, stream::FusedStream, task::AtomicWaker, Stream, StreamExt};
+use tokio::{
+    io::AsyncWrite,
+    sync::futures::Notified,
+    time::{Instant, Sleep},
+};
 
+#[cfg(feature = "sync")]
+use crate::sync::SyncVectoredQueue;
 use crate::{
-    error::{DequeueError, EnqueueError, TryEnqueueError},
-    queue::{TryDequeueResult, VectoredQueue},
-    vectored::Vectored,
+    alloc::{Allocator, Global},
+    error::{DequeueError, EnqueueError, TransferError, TryEnqueueError},
+    notify::{NotifyThreshold, Threshold},
+    primitives::{lock, Mutex},
+    queue::{HighPriorityGuard, TryDequeueResult, VectoredQueue},
+    vectored::{DequeueTxn, Vectored},
 };
 
-pub struct AsyncVectoredQueue<T> {
-    queue: VectoredQueue<T>,
+pub struct AsyncVectoredQueue<T, A: Allocator + Clone = Global> {
+    queue: VectoredQueue<T, A>,
     waker: AtomicWaker,
     notify: tokio::sync::Notify,
+    threshold: Threshold,
+    // wakes the consumer every max delay of the notification threshold
+    delay: Mutex<Option<Pin<Box<Sleep>>>>,
+}
+
+impl<T> From<Vec<T>> for AsyncVectoredQueue<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn from(items: Vec<T>) -> Self {
+        Self::from_queue(items.into())
+    }
+}
+
+impl<T> FromIterator<T> for AsyncVectoredQueue<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_queue(iter.into_iter().collect())
+    }
+}
+
+/// Convert a blocking queue, e.g. when moving its connection onto an async runtime, keeping
+/// its items and its notify threshold.
+#[cfg(feature = "sync")]
+impl<T, A: Allocator + Clone> From<SyncVectoredQueue<T, A>> for AsyncVectoredQueue<T, A> {
+    fn from(queue: SyncVectoredQueue<T, A>) -> Self {
+        let (queue, threshold) = queue.into_parts();
+        Self::from_parts(queue, threshold)
+    }
+}
+
+/// Convert into a blocking queue, keeping its items and its notify threshold.
+#[cfg(feature = "sync")]
+impl<T, A: Allocator + Clone> From<AsyncVectoredQueue<T, A>> for SyncVectoredQueue<T, A> {
+    fn from(queue: AsyncVectoredQueue<T, A>) -> Self {
+        SyncVectoredQueue::from_parts(queue.queue, queue.threshold)
+    }
 }
 
 impl<T> Default for AsyncVectoredQueue<T> {
@@ -26,90 +86,1390 @@ impl<T> AsyncVectoredQueue<T> {
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_queue(VectoredQueue::with_capacity(capacity))
+    }
+
+    /// See [`VectoredQueue::with_byte_capacity`].
+    pub fn with_byte_capacity(capacity: usize, max_bytes: usize) -> Self {
+        Self::from_queue(VectoredQueue::with_byte_capacity(capacity, max_bytes))
+    }
+
+    /// See [`VectoredQueue::with_max_capacity`].
+    pub fn with_max_capacity(initial: usize, max: usize) -> Self {
+        Self::from_queue(VectoredQueue::with_max_capacity(initial, max))
+    }
+
+    /// See [`VectoredQueue::with_framer`].
+    pub fn with_framer<H: AsRef<[u8]>>(
+        capacity: usize,
+        framer: impl Fn(&[u8]) -> H + Send + Sync + 'static,
+    ) -> Self {
+        Self::from_queue(VectoredQueue::with_framer(capacity, framer))
+    }
+}
+
+impl<T, A: Allocator + Clone> AsyncVectoredQueue<T, A> {
+    #[cfg(feature = "allocator-api2")]
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_in(0, alloc)
+    }
+
+    #[cfg(feature = "allocator-api2")]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::from_queue(VectoredQueue::with_capacity_in(capacity, alloc))
+    }
+
+    /// Wrap a queue, keeping its items and its configuration.
+    ///
+    /// The queue carries no notification state, so the
+    /// [notify threshold](Self::set_notify_threshold) is unset.
+    pub fn from_queue(queue: VectoredQueue<T, A>) -> Self {
+        Self::from_parts(queue, Threshold::default())
+    }
+
+    fn from_parts(queue: VectoredQueue<T, A>, threshold: Threshold) -> Self {
         Self {
-            queue: VectoredQueue::with_capacity(capacity),
+            queue,
             waker: AtomicWaker::default(),
             notify: tokio::sync::Notify::new(),
+            threshold,
+            delay: Mutex::new(None),
         }
     }
 
+    /// Unwrap the queue, keeping its items; the notify threshold is dropped.
+    pub fn into_inner(self) -> VectoredQueue<T, A> {
+        self.queue
+    }
+
+    /// The wrapped queue, e.g. to inspect it.
+    ///
+    /// Enqueuing through it doesn't wake a waiting consumer, and dequeuing doesn't wake
+    /// waiting producers.
+    pub fn as_queue(&self) -> &VectoredQueue<T, A> {
+        &self.queue
+    }
+
     pub fn capacity(&self) -> usize {
         self.queue.capacity()
     }
 
+    /// See [`VectoredQueue::byte_capacity`].
+    pub fn byte_capacity(&self) -> usize {
+        self.queue.byte_capacity()
+    }
+
+    /// See [`VectoredQueue::set_byte_capacity`]; producers waiting for space are woken.
+    pub fn set_byte_capacity(&self, max_bytes: usize) {
+        self.queue.set_byte_capacity(max_bytes);
+        self.notify.notify_waiters();
+    }
+
     pub fn set_capacity(&self, capacity: usize) {
         self.queue.set_capacity(capacity);
     }
 
+    /// See [`VectoredQueue::max_capacity`].
+    pub fn max_capacity(&self) -> usize {
+        self.queue.max_capacity()
+    }
+
+    /// See [`VectoredQueue::set_max_capacity`].
+    pub fn set_max_capacity(&self, max: usize) {
+        self.queue.set_max_capacity(max);
+    }
+
     pub fn len(&self) -> usize {
         self.queue.len()
     }
 
+    /// See [`VectoredQueue::pending_bytes`].
+    pub fn pending_bytes(&self) -> usize {
+        self.queue.pending_bytes()
+    }
+
+    /// See [`VectoredQueue::peek_len`].
+    pub fn peek_len(&self) -> usize {
+        self.queue.peek_len()
+    }
+
+    /// See [`VectoredQueue::peek_total_size`].
+    pub fn peek_total_size(&self) -> usize {
+        self.queue.peek_total_size()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.queue.is_empty()
     }
 
+    pub fn mark_high_priority(&self) -> HighPriorityGuard<'_> {
+        self.queue.mark_high_priority()
+    }
+
     pub fn close(&self) {
         self.queue.close();
+        self.wake_closed();
+    }
+
+    pub fn close_enqueue(&self) {
+        self.queue.close_enqueue();
+        self.wake_closed();
+    }
+
+    pub fn close_dequeue(&self) {
+        self.queue.close_dequeue();
+        self.wake_closed();
+    }
+
+    fn wake_closed(&self) {
         self.notify.notify_waiters();
+        self.waker.wake();
     }
 
     pub fn is_closed(&self) -> bool {
         self.queue.is_closed()
     }
 
+    pub fn is_dequeue_closed(&self) -> bool {
+        self.queue.is_dequeue_closed()
+    }
+
     pub fn reopen(&self) {
         self.queue.reopen();
     }
+
+    pub fn reopen_enqueue(&self) {
+        self.queue.reopen_enqueue();
+    }
+
+    pub fn reopen_dequeue(&self) {
+        self.queue.reopen_dequeue();
+    }
+
+    /// Only wake the consumer once the pending items or bytes reach `threshold`, or when an
+    /// item is enqueued after the oldest pending one has waited `max_delay`, instead of on
+    /// every enqueuing. Closing always wakes it.
+    ///
+    /// Items below the threshold are still dequeued, as waiting consumers arm a timer to check
+    /// the queue at least every `max_delay`; they must then be polled within a Tokio runtime
+    /// with the time driver enabled. [`kick`](Self::kick) wakes them immediately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_delay` is zero.
+    pub fn set_notify_threshold(&self, threshold: NotifyThreshold, max_delay: Duration) {
+        self.threshold.set(threshold, max_delay);
+    }
+
+    /// Wake the consumer on every enqueuing again.
+    pub fn clear_notify_threshold(&self) {
+        self.threshold.clear();
+    }
+
+    /// Wake the consumer, whatever the [notification threshold](Self::set_notify_threshold).
+    pub fn kick(&self) {
+        self.waker.wake();
+    }
+
+    fn wake_dequeue(&self) {
+        if self.threshold.is_reached(&self.queue) {
+            self.kick();
+        }
+    }
+
+    /// Split the queue into a cloneable sender and a unique receiver.
+    ///
+    /// The queue is closed when all senders, or the receiver, are dropped.
+    pub fn split(self) -> (AsyncSender<T, A>, AsyncReceiver<T, A>) {
+        let queue = Arc::new(self);
+        (
+            AsyncSender(Arc::new(SenderGuard(queue.clone()))),
+            AsyncReceiver(queue),
+        )
+    }
 }
 
-impl<T> AsyncVectoredQueue<T>
+impl<T, A: Allocator + Clone> AsyncVectoredQueue<T, A>
 where
     T: AsRef<[u8]>,
 {
+    /// See [`VectoredQueue::drain_closed`].
+    pub fn drain_closed(&self) -> Result<Vec<T>, DequeueError> {
+        self.queue.drain_closed()
+    }
+
+    /// See [`VectoredQueue::purge`]; producers waiting for space are woken.
+    pub fn purge(&self) -> bool {
+        let purged = self.queue.purge();
+        if purged {
+            self.notify.notify_waiters();
+        }
+        purged
+    }
+
     pub fn try_enqueue(&self, bytes: T) -> Result<(), TryEnqueueError<T>> {
         self.queue.try_enqueue(bytes)?;
-        self.waker.wake();
+        self.wake_dequeue();
         Ok(())
     }
 
-    pub async fn enqueue(&self, mut bytes: T) -> Result<(), EnqueueError<T>> {
-        loop {
-            let notified = self.notify.notified();
-            match self.try_enqueue(bytes) {
-                Ok(_) => return Ok(()),
-                Err(TryEnqueueError::Closed(bytes)) => return Err(EnqueueError(bytes)),
-                Err(TryEnqueueError::Full(b)) => bytes = b,
-            };
-            notified.await;
+    /// See [`VectoredQueue::try_enqueue_tagged`].
+    pub fn try_enqueue_tagged(&self, bytes: T, tag: u64) -> Result<(), TryEnqueueError<T>> {
+        self.queue.try_enqueue_tagged(bytes, tag)?;
+        self.wake_dequeue();
+        Ok(())
+    }
+
+    /// See [`VectoredQueue::try_enqueue_many`].
+    pub fn try_enqueue_many<I: Iterator<Item = T>>(
+        &self,
+        items: &mut Peekable<I>,
+    ) -> Result<usize, EnqueueError<()>> {
+        let count = self.queue.try_enqueue_many(items)?;
+        if count > 0 {
+            self.wake_dequeue();
+        }
+        Ok(count)
+    }
+
+    /// See [`VectoredQueue::try_enqueue_batch`].
+    pub fn try_enqueue_batch(&self, items: Vec<T>) -> Result<(), TryEnqueueError<Vec<T>>> {
+        let notify = !items.is_empty();
+        self.queue.try_enqueue_batch(items)?;
+        if notify {
+            self.wake_dequeue();
+        }
+        Ok(())
+    }
+
+    pub fn enqueue(&self, bytes: T) -> EnqueueFuture<'_, T, A> {
+        EnqueueFuture {
+            queue: self,
+            bytes: Some(bytes),
+            notified: self.notify.notified(),
         }
     }
 
+    /// Enqueue the stream items until it is exhausted, waiting for space before pulling the
+    /// next item, and return the number of enqueued items.
+    ///
+    /// The first item rejected because the queue is closed is returned in the error.
+    pub async fn enqueue_from_stream<S>(&self, stream: &mut S) -> Result<usize, EnqueueError<T>>
+    where
+        S: Stream<Item = T> + Unpin,
+    {
+        let mut count = 0;
+        while let Some(bytes) = stream.next().await {
+            self.enqueue(bytes).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
     pub fn enqueue_unbounded(&self, bytes: T) -> Result<(), EnqueueError<T>> {
         self.queue.enqueue_unbounded(bytes)?;
-        self.waker.wake();
+        self.wake_dequeue();
         Ok(())
     }
 
-    pub fn try_dequeue_vectored(&self) -> Result<TryDequeueResult<T>, DequeueError> {
-        let res = self.queue.try_dequeue_vectored()?;
-        if matches!(res, TryDequeueResult::Vectored(_)) {
-            self.notify.notify_waiters();
+    pub fn try_dequeue_vectored(&self) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.queue
+            .try_dequeue_vectored_notify(&|| self.notify.notify_waiters())
+    }
+
+    fn poll_dequeue_vectored(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Vectored<'_, T, A>, DequeueError>> {
+        if let Some(vectored) = self.try_dequeue_vectored()?.vectored() {
+            return Poll::Ready(Ok(vectored));
+        }
+        self.waker.register(cx.waker());
+        if let Some(vectored) = self.try_dequeue_vectored()?.vectored() {
+            return Poll::Ready(Ok(vectored));
+        }
+        self.poll_delay(cx);
+        Poll::Pending
+    }
+
+    // Producers don't wake the consumer for items below the notification threshold, so the
+    // waiting consumer is woken by a timer every max delay to check them.
+    fn poll_delay(&self, cx: &mut Context<'_>) {
+        let Some(max_delay) = self.threshold.max_delay() else {
+            return;
+        };
+        let mut delay = lock(&self.delay);
+        let delay = delay.get_or_insert_with(|| Box::pin(tokio::time::sleep(max_delay)));
+        if delay.as_mut().poll(cx).is_ready() {
+            // the elapsed delay woke the consumer, which found nothing, or has since dequeued
+            delay.as_mut().reset(Instant::now() + max_delay);
+            let _ = delay.as_mut().poll(cx);
         }
-        Ok(res)
     }
 
-    pub async fn dequeue_vectored(&self) -> Result<Vectored<T>, DequeueError> {
+    /// Dequeue a batch, waiting for it to be enqueued.
+    ///
+    /// Insertions in progress are awaited, as their producers wake the consumer once
+    /// inserted, instead of returning a pending result.
+    pub fn dequeue_vectored(&self) -> DequeueFuture<'_, T, A> {
+        DequeueFuture {
+            queue: self,
+            terminated: false,
+        }
+    }
+
+    /// Dequeue the enqueued items, waiting at most `timeout` for them, `None` if it elapses.
+    ///
+    /// It must be called within a Tokio runtime with the time driver enabled.
+    pub async fn dequeue_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<Vectored<'_, T, A>>, DequeueError> {
+        match tokio::time::timeout(timeout, self.dequeue_vectored()).await {
+            Ok(res) => res.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Stream of the dequeued batches, ending once the queue is closed and drained.
+    ///
+    /// Batches borrow the queue, not the stream, but the next one is only yielded once the
+    /// previous one is dropped, as with [`dequeue_vectored`](Self::dequeue_vectored).
+    pub fn stream(&self) -> VectoredStream<'_, T, A> {
+        VectoredStream {
+            queue: self,
+            terminated: false,
+        }
+    }
+
+    /// Stream of the dequeued batches moved out of the queue, ending once the queue is closed
+    /// and drained, e.g. to be used with stream combinators.
+    ///
+    /// Unlike [`stream`](Self::stream), batches are owned, so they can be held across the
+    /// yields; see [`split`](Self::split) for a stream owning the queue.
+    pub fn batches(&self) -> BatchStream<'_, T, A> {
+        BatchStream {
+            queue: self,
+            terminated: false,
+        }
+    }
+
+    /// See [`VectoredQueue::transfer_to`]; enqueuing waits until the destination has space,
+    /// so the error is never [`TransferError::Full`].
+    pub async fn transfer_to<B: Allocator + Clone>(
+        &self,
+        dst: &AsyncVectoredQueue<T, B>,
+    ) -> Result<usize, TransferError<T>> {
+        assert!(!ptr::addr_eq(self, dst), "cannot transfer into itself");
+        let mut transferred = 0;
+        let notify_enqueue = || self.notify.notify_waiters();
+        while let Some(items) = self.queue.dequeue_transfer(&notify_enqueue, transferred)? {
+            let mut items = items.into_iter();
+            while let Some(bytes) = items.next() {
+                if let Err(EnqueueError(bytes)) = dst.enqueue(bytes).await {
+                    let items = [bytes].into_iter().chain(items).collect();
+                    return Err(TransferError::Closed { transferred, items });
+                }
+                transferred += 1;
+            }
+        }
+        Ok(transferred)
+    }
+
+    /// Dequeue the first of the queues having items, returning its index with the batch.
+    ///
+    /// Queues are checked in order, so the first ones have strict priority and may starve
+    /// the others. Closed queues are skipped, until they are all closed. Each queue must not
+    /// be dequeued concurrently, as a single task can wait on it.
+    pub async fn dequeue_select<'a, const N: usize>(
+        queues: [&'a Self; N],
+    ) -> Result<(usize, Vectored<'a, T, A>), DequeueError> {
         futures::future::poll_fn(|cx| {
-            if let Some(vectored) = self.try_dequeue_vectored()?.vectored() {
-                return Poll::Ready(Ok(vectored));
+            let mut closed = 0;
+            for (i, queue) in queues.iter().enumerate() {
+                match queue.poll_dequeue_vectored(cx) {
+                    Poll::Ready(Ok(vectored)) => return Poll::Ready(Ok((i, vectored))),
+                    Poll::Ready(Err(DequeueError::Closed)) => closed += 1,
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => {}
+                }
+            }
+            if closed == N {
+                return Poll::Ready(Err(DequeueError::Closed));
             }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// See [`VectoredQueue::try_dequeue_with`].
+    pub fn try_dequeue_with<R>(
+        &self,
+        f: impl FnOnce(&mut Vectored<'_, T, A>) -> R,
+    ) -> Result<Option<R>, DequeueError> {
+        Ok(self
+            .try_dequeue_vectored()?
+            .vectored()
+            .map(|mut vectored| f(&mut vectored)))
+    }
+
+    /// Asynchronous version of [`try_dequeue_with`](Self::try_dequeue_with).
+    pub async fn dequeue_with<R>(
+        &self,
+        f: impl FnOnce(&mut Vectored<'_, T, A>) -> R,
+    ) -> Result<R, DequeueError> {
+        Ok(f(&mut self.dequeue_vectored().await?))
+    }
+
+    fn poll_dequeue_at_least(
+        &self,
+        cx: &mut Context<'_>,
+        min: usize,
+    ) -> Poll<Result<Vectored<'_, T, A>, DequeueError>> {
+        // a full queue cannot receive more items until dequeued, so waiting longer would
+        // deadlock producers
+        let ready = || self.len() >= min || self.queue.is_full() || self.is_closed();
+        if !ready() {
             self.waker.register(cx.waker());
-            if let Some(vectored) = self.try_dequeue_vectored()?.vectored() {
-                return Poll::Ready(Ok(vectored));
+            if !ready() {
+                self.poll_delay(cx);
+                return Poll::Pending;
+            }
+        }
+        self.poll_dequeue_vectored(cx)
+    }
+
+    /// Wait until at least `min` items are enqueued, then dequeue all of them.
+    ///
+    /// Less items are dequeued if the queue is full before, e.g. if `min` exceeds its capacity,
+    /// or if it is closed.
+    pub async fn dequeue_at_least(&self, min: usize) -> Result<Vectored<'_, T, A>, DequeueError> {
+        futures::future::poll_fn(|cx| self.poll_dequeue_at_least(cx, min)).await
+    }
+
+    /// Same as [`dequeue_at_least`](Self::dequeue_at_least), but stop waiting for `min` items
+    /// when `deadline` completes, e.g. `tokio::time::sleep(timeout)`, and dequeue the available
+    /// ones, if any.
+    pub async fn dequeue_at_least_until(
+        &self,
+        min: usize,
+        deadline: impl Future<Output = ()>,
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        let mut deadline = pin!(deadline);
+        futures::future::poll_fn(|cx| {
+            if let Poll::Ready(res) = self.poll_dequeue_at_least(cx, min) {
+                return Poll::Ready(res.map(TryDequeueResult::Vectored));
+            }
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(self.try_dequeue_vectored());
             }
             Poll::Pending
         })
         .await
     }
+
+    /// Dequeue a batch and write its [full slices](Vectored::full_slices) to `writer`, returning
+    /// the number of written bytes.
+    ///
+    /// The batch is released once completely written, or on error, in which case its unwritten
+    /// bytes are lost. Items left in a closed queue are still written, the following call then
+    /// fails with an [`io::ErrorKind::Other`] error wrapping [`DequeueError::Closed`].
+    pub async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut vectored = self.dequeue_vectored().await.map_err(io::Error::other)?;
+        let mut written = 0;
+        while vectored.full_size() > 0 {
+            let slices = vectored.full_slices();
+            let res = futures::future::poll_fn(|cx| {
+                Pin::new(&mut *writer).poll_write_vectored(cx, slices)
+            })
+            .await;
+            match res {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    vectored.advance_full(n);
+                    written += n;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(written)
+    }
+
+    /// Dequeue a batch and write its [full slices](Vectored::full_slices) to `writer`, then flush
+    /// it, returning the number of written bytes, or `Ok(0)` once the queue is closed and
+    /// drained, which shutdown loops can stop on; a batch of empty items also writes no byte.
+    ///
+    /// Contrary to [`write_to`](Self::write_to), the batch is only released once written and
+    /// flushed, as with a committed [`DequeueTxn`](crate::DequeueTxn). If the future is
+    /// cancelled or fails, the batch is left in the queue and dequeued whole by the next call,
+    /// including the bytes already written, which the caller must account for, e.g. by
+    /// dropping the connection.
+    pub async fn dequeue_write_all<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+    ) -> io::Result<usize> {
+        let txn = match self.dequeue_vectored().await {
+            Ok(vectored) => DequeueTxn {
+                vectored: mem::ManuallyDrop::new(vectored),
+            },
+            Err(DequeueError::Closed) => return Ok(0),
+            Err(err) => return Err(io::Error::other(err)),
+        };
+        // the queue slices are left untouched, for the batch to be dequeued whole again
+        let mut slices = txn.vectored.full_slices().to_vec();
+        let mut remaining = &mut slices[..];
+        // skip the empty slices
+        IoSlice::advance_slices(&mut remaining, 0);
+        let mut written = 0;
+        while !remaining.is_empty() {
+            let res = futures::future::poll_fn(|cx| {
+                Pin::new(&mut *writer).poll_write_vectored(cx, remaining)
+            })
+            .await;
+            match res {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    IoSlice::advance_slices(&mut remaining, n);
+                    written += n;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        futures::future::poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await?;
+        txn.commit();
+        Ok(written)
+    }
+
+    /// Wait until the queue is not empty or closed, without dequeuing.
+    pub async fn wait_nonempty(&self) {
+        let ready = || !self.is_empty() || self.is_closed();
+        futures::future::poll_fn(|cx| {
+            if ready() {
+                return Poll::Ready(());
+            }
+            self.waker.register(cx.waker());
+            if ready() {
+                return Poll::Ready(());
+            }
+            self.poll_delay(cx);
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Create an [`AsyncVectoredQueue`] with the given capacity, split into a sender and a
+/// receiver, see [`AsyncVectoredQueue::split`].
+pub fn async_channel<T>(capacity: usize) -> (AsyncSender<T>, AsyncReceiver<T>) {
+    AsyncVectoredQueue::with_capacity(capacity).split()
+}
+
+struct SenderGuard<T, A: Allocator + Clone>(Arc<AsyncVectoredQueue<T, A>>);
+
+impl<T, A: Allocator + Clone> Drop for SenderGuard<T, A> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+pub struct AsyncSender<T, A: Allocator + Clone = Global>(Arc<SenderGuard<T, A>>);
+
+impl<T, A: Allocator + Clone> Clone for AsyncSender<T, A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T, A: Allocator + Clone> AsyncSender<T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn try_enqueue(&self, bytes: T) -> Result<(), TryEnqueueError<T>> {
+        self.0 .0.try_enqueue(bytes)
+    }
+
+    pub fn enqueue(&self, bytes: T) -> EnqueueFuture<'_, T, A> {
+        self.0 .0.enqueue(bytes)
+    }
+
+    /// See [`AsyncVectoredQueue::enqueue_from_stream`].
+    pub async fn enqueue_from_stream<S>(&self, stream: &mut S) -> Result<usize, EnqueueError<T>>
+    where
+        S: Stream<Item = T> + Unpin,
+    {
+        self.0 .0.enqueue_from_stream(stream).await
+    }
+
+    pub fn enqueue_unbounded(&self, bytes: T) -> Result<(), EnqueueError<T>> {
+        self.0 .0.enqueue_unbounded(bytes)
+    }
+
+    /// See [`AsyncVectoredQueue::kick`].
+    pub fn kick(&self) {
+        self.0 .0.kick();
+    }
+}
+
+pub struct AsyncReceiver<T, A: Allocator + Clone = Global>(Arc<AsyncVectoredQueue<T, A>>);
+
+impl<T, A: Allocator + Clone> Drop for AsyncReceiver<T, A> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+impl<T, A: Allocator + Clone> AsyncReceiver<T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn try_dequeue(&self) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.0.try_dequeue_vectored()
+    }
+
+    pub fn dequeue(&self) -> DequeueFuture<'_, T, A> {
+        self.0.dequeue_vectored()
+    }
+}
+
+impl<T, A: Allocator + Clone> Stream for AsyncReceiver<T, A>
+where
+    T: AsRef<[u8]>,
+{
+    type Item = Vec<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.0.poll_dequeue_vectored(cx) {
+            Poll::Ready(Ok(vectored)) => Poll::Ready(Some(vectored.into_vec())),
+            Poll::Ready(Err(DequeueError::Closed | DequeueError::Poisoned)) => Poll::Ready(None),
+            // the receiver is unique and mutably borrowed
+            Poll::Ready(Err(DequeueError::Conflict)) => unreachable!(),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Future returned by [`AsyncVectoredQueue::dequeue_vectored`].
+///
+/// It panics if polled after completion, which can be checked with
+/// [`is_terminated`](FusedFuture::is_terminated).
+pub struct DequeueFuture<'a, T, A: Allocator + Clone = Global> {
+    queue: &'a AsyncVectoredQueue<T, A>,
+    terminated: bool,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for DequeueFuture<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DequeueFuture")
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> Future for DequeueFuture<'a, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    type Output = Result<Vectored<'a, T, A>, DequeueError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        assert!(!self.terminated, "`DequeueFuture` polled after completion");
+        let queue = self.queue;
+        let res = ready!(queue.poll_dequeue_vectored(cx));
+        self.terminated = true;
+        Poll::Ready(res)
+    }
+}
+
+impl<T, A: Allocator + Clone> FusedFuture for DequeueFuture<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// Stream returned by [`AsyncVectoredQueue::stream`].
+pub struct VectoredStream<'a, T, A: Allocator + Clone = Global> {
+    queue: &'a AsyncVectoredQueue<T, A>,
+    terminated: bool,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for VectoredStream<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VectoredStream")
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> Stream for VectoredStream<'a, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    type Item = Result<Vectored<'a, T, A>, DequeueError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let queue = self.queue;
+        match ready!(queue.poll_dequeue_vectored(cx)) {
+            Err(DequeueError::Closed) => {
+                self.terminated = true;
+                Poll::Ready(None)
+            }
+            res => Poll::Ready(Some(res)),
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> FusedStream for VectoredStream<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// Stream returned by [`AsyncVectoredQueue::batches`].
+pub struct BatchStream<'a, T, A: Allocator + Clone = Global> {
+    queue: &'a AsyncVectoredQueue<T, A>,
+    terminated: bool,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for BatchStream<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchStream")
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
+
+impl<T, A: Allocator + Clone> Stream for BatchStream<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    type Item = Result<Vec<T>, DequeueError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        match ready!(self.queue.poll_dequeue_vectored(cx)) {
+            Ok(vectored) => Poll::Ready(Some(Ok(vectored.into_vec()))),
+            Err(DequeueError::Closed) => {
+                self.terminated = true;
+                Poll::Ready(None)
+            }
+            Err(err) => Poll::Ready(Some(Err(err))),
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> FusedStream for BatchStream<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// Future returned by [`AsyncVectoredQueue::enqueue`].
+///
+/// It panics if polled after completion, which can be checked with
+/// [`is_terminated`](FusedFuture::is_terminated).
+pub struct EnqueueFuture<'a, T, A: Allocator + Clone = Global> {
+    queue: &'a AsyncVectoredQueue<T, A>,
+    // taken on completion
+    bytes: Option<T>,
+    notified: Notified<'a>,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for EnqueueFuture<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnqueueFuture")
+            .field("terminated", &self.bytes.is_none())
+            .finish()
+    }
+}
+
+impl<T, A: Allocator + Clone> Future for EnqueueFuture<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    type Output = Result<(), EnqueueError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `notified` is the only pinned field
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut notified = unsafe { Pin::new_unchecked(&mut this.notified) };
+        loop {
+            let bytes = (this.bytes.take()).expect("`EnqueueFuture` polled after completion");
+            // the notification is registered before trying, so it cannot be missed
+            notified.as_mut().enable();
+            match this.queue.try_enqueue(bytes) {
+                Ok(_) => return Poll::Ready(Ok(())),
+                Err(TryEnqueueError::Closed(bytes)) => {
+                    return Poll::Ready(Err(EnqueueError(bytes)))
+                }
+                Err(TryEnqueueError::Full(bytes)) => this.bytes = Some(bytes),
+            }
+            ready!(notified.as_mut().poll(cx));
+            notified.set(this.queue.notify.notified());
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> FusedFuture for EnqueueFuture<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn is_terminated(&self) -> bool {
+        self.bytes.is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        io,
+        pin::{pin, Pin},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+        thread,
+        time::Duration,
+    };
+
+    use futures::{
+        executor::block_on,
+        future::{join, FusedFuture},
+        poll, select,
+        stream::FusedStream,
+        task::ArcWake,
+        StreamExt,
+    };
+    use tokio::io::AsyncWrite;
+
+    use crate::{
+        error::{DequeueError, TransferError},
+        notify::NotifyThreshold,
+        queue::{TryDequeueResult, VectoredQueue},
+        r#async::{async_channel, AsyncVectoredQueue},
+    };
+
+    #[test]
+    fn enqueue_while_batch_held() {
+        let queue = AsyncVectoredQueue::with_capacity(1);
+        queue.try_enqueue([0]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        queue.try_enqueue([1]).unwrap();
+        thread::scope(|s| {
+            let producer = s.spawn(|| block_on(queue.enqueue([2])).unwrap());
+            thread::sleep(Duration::from_millis(50));
+            assert!(!producer.is_finished());
+            drop(vectored);
+            let vectored = block_on(queue.dequeue_vectored()).unwrap();
+            assert_eq!(&*vectored[0], &[1]);
+            producer.join().unwrap();
+        });
+        assert_eq!(&*block_on(queue.dequeue_vectored()).unwrap()[0], &[2]);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn named_futures() {
+        let queue = AsyncVectoredQueue::with_capacity(1);
+        queue.try_enqueue([0]).unwrap();
+        block_on(async {
+            // a cancelled enqueuing doesn't prevent following ones to be woken
+            let mut cancelled = Box::pin(queue.enqueue([1]));
+            assert!(poll!(cancelled.as_mut()).is_pending());
+            drop(cancelled);
+            let mut enqueue = pin!(queue.enqueue([2]));
+            let mut dequeue = queue.dequeue_vectored();
+            assert_eq!(
+                format!("{dequeue:?}"),
+                "DequeueFuture { terminated: false }"
+            );
+            select! {
+                _ = enqueue => unreachable!(),
+                vectored = dequeue => assert_eq!(&*vectored.unwrap()[0], &[0]),
+            }
+            assert!(dequeue.is_terminated());
+            assert!(!enqueue.is_terminated());
+            enqueue.as_mut().await.unwrap();
+            assert!(enqueue.is_terminated());
+        });
+        assert_eq!(&*block_on(queue.dequeue_vectored()).unwrap()[0], &[2]);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn wait_nonempty() {
+        let queue = AsyncVectoredQueue::with_capacity(4);
+        thread::scope(|s| {
+            s.spawn(|| queue.try_enqueue([0]).unwrap());
+            block_on(queue.wait_nonempty());
+            assert_eq!(queue.len(), 1);
+            drop(queue.try_dequeue_vectored().unwrap().vectored().unwrap());
+            s.spawn(|| queue.close());
+            block_on(queue.wait_nonempty());
+            assert!(queue.is_closed());
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn from_queue() {
+        let queue = VectoredQueue::with_capacity(2);
+        queue.try_enqueue([0]).unwrap();
+        let queue = AsyncVectoredQueue::from_queue(queue);
+        assert_eq!(queue.as_queue().len(), 1);
+        let queue = queue.into_inner();
+        queue.try_enqueue([1]).unwrap();
+        let queue = AsyncVectoredQueue::from_queue(queue);
+        assert_eq!(
+            block_on(queue.dequeue_vectored()).unwrap().into_vec(),
+            [[0], [1]]
+        );
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn from_sync() {
+        let queue = crate::SyncVectoredQueue::with_capacity(2);
+        queue.set_notify_threshold(NotifyThreshold::Items(2), Duration::from_secs(3600));
+        queue.try_enqueue([0]).unwrap();
+        queue.enqueue_unbounded([1]).unwrap();
+        queue.enqueue_unbounded([2]).unwrap();
+        let queue = AsyncVectoredQueue::from(queue);
+        assert_eq!(
+            block_on(queue.dequeue_vectored()).unwrap().into_vec(),
+            [[0], [1]]
+        );
+        queue.try_enqueue([3]).unwrap();
+        let queue = crate::SyncVectoredQueue::from(queue);
+        assert_eq!(queue.dequeue_vectored().unwrap().into_vec(), [[2], [3]]);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn transfer_to() {
+        let src = AsyncVectoredQueue::with_capacity(2);
+        for i in 0..5 {
+            src.enqueue_unbounded([i]).unwrap();
+        }
+        let dst = AsyncVectoredQueue::with_capacity(2);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut items = Vec::new();
+                while items.len() < 5 {
+                    items.extend(block_on(dst.dequeue_vectored()).unwrap().into_vec());
+                }
+                items
+            });
+            assert_eq!(block_on(src.transfer_to(&dst)), Ok(5));
+            assert_eq!(
+                consumer.join().unwrap(),
+                (0..5).map(|i| [i]).collect::<Vec<_>>()
+            );
+        });
+        src.try_enqueue([5]).unwrap();
+        dst.close();
+        assert_eq!(
+            block_on(src.transfer_to(&dst)),
+            Err(TransferError::Closed {
+                transferred: 0,
+                items: vec![[5]]
+            })
+        );
+        src.as_queue().debug_assert_invariants();
+        dst.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn close_dequeue() {
+        let queue = AsyncVectoredQueue::with_capacity(1);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| block_on(queue.dequeue_vectored()).map(|v| v.len()));
+            s.spawn(|| queue.close_dequeue());
+            assert_eq!(consumer.join().unwrap(), Err(DequeueError::Closed));
+        });
+        queue.reopen();
+        queue.try_enqueue([0]).unwrap();
+        thread::scope(|s| {
+            let producer = s.spawn(|| block_on(queue.enqueue([1])));
+            s.spawn(|| queue.close_dequeue());
+            assert_eq!(producer.join().unwrap().unwrap_err().0, [1]);
+        });
+        queue.reopen_dequeue();
+        assert_eq!(block_on(queue.dequeue_vectored()).unwrap().len(), 1);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_with() {
+        let queue = AsyncVectoredQueue::with_capacity(1);
+        thread::scope(|s| {
+            s.spawn(|| queue.try_enqueue([0]).unwrap());
+            assert_eq!(block_on(queue.dequeue_with(|v| v.total_size())), Ok(1));
+        });
+        assert_eq!(queue.try_dequeue_with(|v| v.len()), Ok(None));
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_at_least() {
+        let queue = AsyncVectoredQueue::with_capacity(4);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..3 {
+                    thread::sleep(Duration::from_millis(10));
+                    queue.try_enqueue([i]).unwrap();
+                }
+            });
+            assert_eq!(block_on(queue.dequeue_at_least(3)).unwrap().len(), 3);
+        });
+        // more than the capacity
+        queue.try_enqueue([0]).unwrap();
+        queue.try_enqueue([1]).unwrap();
+        queue.try_enqueue([2]).unwrap();
+        queue.try_enqueue([3]).unwrap();
+        assert_eq!(block_on(queue.dequeue_at_least(10)).unwrap().len(), 4);
+        queue.try_enqueue([0]).unwrap();
+        let res = block_on(queue.dequeue_at_least_until(2, async {}));
+        assert_eq!(res.unwrap().vectored().unwrap().len(), 1);
+        let res = block_on(queue.dequeue_at_least_until(2, async {}));
+        assert!(matches!(res, Ok(TryDequeueResult::Empty)));
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn enqueue_from_stream() {
+        let queue = AsyncVectoredQueue::with_capacity(2);
+        let consumer = async {
+            let mut items = Vec::new();
+            while items.len() < 5 {
+                items.extend(queue.dequeue_vectored().await.unwrap().into_vec());
+            }
+            items
+        };
+        let mut stream = futures::stream::iter((0..5).map(|i| [i]));
+        let (enqueued, items) =
+            block_on(async { futures::join!(queue.enqueue_from_stream(&mut stream), consumer) });
+        assert_eq!(enqueued.unwrap(), 5);
+        assert_eq!(items, (0..5).map(|i| [i]).collect::<Vec<_>>());
+        queue.close();
+        let mut stream = futures::stream::iter([[5]]);
+        let err = block_on(queue.enqueue_from_stream(&mut stream)).unwrap_err();
+        assert_eq!(err.inner(), [5]);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_select() {
+        let control = AsyncVectoredQueue::with_capacity(2);
+        let data = AsyncVectoredQueue::with_capacity(2);
+        data.try_enqueue([0]).unwrap();
+        control.try_enqueue([1]).unwrap();
+        // the first queue has priority
+        let (index, vectored) =
+            block_on(AsyncVectoredQueue::dequeue_select([&control, &data])).unwrap();
+        assert_eq!((index, vectored.into_vec()), (0, vec![[1]]));
+        let (index, vectored) =
+            block_on(AsyncVectoredQueue::dequeue_select([&control, &data])).unwrap();
+        assert_eq!((index, vectored.into_vec()), (1, vec![[0]]));
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                data.try_enqueue([2]).unwrap();
+            });
+            let (index, _) =
+                block_on(AsyncVectoredQueue::dequeue_select([&control, &data])).unwrap();
+            assert_eq!(index, 1);
+        });
+        control.close();
+        data.close();
+        assert_eq!(
+            block_on(AsyncVectoredQueue::dequeue_select([&control, &data])).unwrap_err(),
+            DequeueError::Closed
+        );
+        control.as_queue().debug_assert_invariants();
+        data.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn split() {
+        let (sender, receiver) = AsyncVectoredQueue::with_capacity(4).split();
+        thread::scope(|s| {
+            for i in 0..4u8 {
+                let sender = sender.clone();
+                s.spawn(move || {
+                    for j in 0..100u8 {
+                        block_on(sender.enqueue([i, j])).unwrap();
+                    }
+                });
+            }
+            drop(sender);
+            let items = block_on(receiver.collect::<Vec<_>>());
+            let mut items: Vec<_> = items.into_iter().flatten().collect();
+            assert_eq!(items.len(), 400);
+            items.sort();
+            items.dedup();
+            assert_eq!(items.len(), 400);
+        });
+        // enqueuing fails once the receiver is dropped
+        let (sender, receiver) = async_channel(4);
+        sender.try_enqueue([0]).unwrap();
+        drop(receiver);
+        assert!(block_on(sender.enqueue([1])).is_err());
+        sender.0 .0.as_queue().debug_assert_invariants();
+    }
+
+    // Writer accepting at most `max` bytes per call, pending every other call
+    struct ShortWriter {
+        written: Vec<u8>,
+        max: usize,
+        pending: bool,
+        fail: Option<io::ErrorKind>,
+    }
+
+    impl AsyncWrite for ShortWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            if let Some(kind) = self.fail.take() {
+                return Poll::Ready(Err(kind.into()));
+            }
+            let len = buf.len().min(self.max);
+            self.written.extend_from_slice(&buf[..len]);
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn write_to() {
+        let queue = AsyncVectoredQueue::with_capacity(2);
+        let mut writer = ShortWriter {
+            written: Vec::new(),
+            max: 2,
+            pending: false,
+            fail: Some(io::ErrorKind::Interrupted),
+        };
+        queue.try_enqueue(vec![0, 1, 2]).unwrap();
+        queue.try_enqueue(vec![3]).unwrap();
+        assert_eq!(block_on(queue.write_to(&mut writer)).unwrap(), 4);
+        assert_eq!(writer.written, [0, 1, 2, 3]);
+        // the batch is released on error
+        writer.fail = Some(io::ErrorKind::BrokenPipe);
+        queue.try_enqueue(vec![4]).unwrap();
+        queue.try_enqueue(vec![5]).unwrap();
+        let err = block_on(queue.write_to(&mut writer)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(queue.is_empty());
+        // remaining items are written before the closing error
+        queue.try_enqueue(vec![6]).unwrap();
+        queue.close();
+        assert_eq!(block_on(queue.write_to(&mut writer)).unwrap(), 1);
+        assert_eq!(writer.written, [0, 1, 2, 3, 6]);
+        let err = block_on(queue.write_to(&mut writer)).unwrap_err();
+        let err = err
+            .into_inner()
+            .unwrap()
+            .downcast::<DequeueError>()
+            .unwrap();
+        assert_eq!(*err, DequeueError::Closed);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_timeout() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let queue = AsyncVectoredQueue::with_capacity(4);
+        runtime.block_on(async {
+            let timeout = Duration::from_millis(10);
+            assert!(queue.dequeue_timeout(timeout).await.unwrap().is_none());
+            queue.try_enqueue([0]).unwrap();
+            let vectored = queue.dequeue_timeout(timeout).await.unwrap().unwrap();
+            assert_eq!(vectored.into_vec(), [[0]]);
+        });
+        // closing wakes the waiting consumer
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                queue.close();
+            });
+            let res = runtime.block_on(queue.dequeue_timeout(Duration::from_secs(3600)));
+            assert_eq!(res.unwrap_err(), DequeueError::Closed);
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn stream() {
+        let queue = AsyncVectoredQueue::with_capacity(4);
+        queue.try_enqueue([0]).unwrap();
+        queue.try_enqueue([1]).unwrap();
+        let mut stream = queue.stream();
+        block_on(async {
+            let vectored = stream.next().await.unwrap().unwrap();
+            assert_eq!(vectored.into_vec(), [[0], [1]]);
+            assert!(poll!(stream.next()).is_pending());
+            queue.try_enqueue([2]).unwrap();
+            queue.close();
+            let vectored = stream.next().await.unwrap().unwrap();
+            assert_eq!(vectored.into_vec(), [[2]]);
+            // closed and drained
+            assert!(stream.next().await.is_none());
+            assert!(stream.is_terminated());
+            assert!(stream.next().await.is_none());
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn batches() {
+        struct CountWake(AtomicUsize);
+        impl ArcWake for CountWake {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let wakes = Arc::new(CountWake(AtomicUsize::new(0)));
+        let waker = futures::task::waker(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+        let queue = AsyncVectoredQueue::with_capacity(4);
+        queue.try_enqueue([0]).unwrap();
+        let mut batches = queue.batches();
+        let first = match batches.poll_next_unpin(&mut cx) {
+            Poll::Ready(Some(batch)) => batch.unwrap(),
+            _ => unreachable!(),
+        };
+        assert!(batches.poll_next_unpin(&mut cx).is_pending());
+        // owned batches don't prevent the next dequeuing
+        thread::scope(|s| {
+            s.spawn(|| queue.try_enqueue([1]).unwrap());
+        });
+        assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+        match batches.poll_next_unpin(&mut cx) {
+            Poll::Ready(Some(batch)) => assert_eq!(batch.unwrap(), [[1]]),
+            _ => unreachable!(),
+        }
+        assert_eq!(first, [[0]]);
+        assert!(batches.poll_next_unpin(&mut cx).is_pending());
+        queue.close();
+        assert!(wakes.0.load(Ordering::Relaxed) >= 2);
+        assert!(matches!(
+            batches.poll_next_unpin(&mut cx),
+            Poll::Ready(None)
+        ));
+        assert!(batches.is_terminated());
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn notify_threshold() {
+        struct CountWake(AtomicUsize);
+        impl ArcWake for CountWake {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let wakes = Arc::new(CountWake(AtomicUsize::new(0)));
+        let waker = futures::task::waker(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+        // waiting consumers arm the max delay timer
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let _runtime = runtime.enter();
+        let queue = AsyncVectoredQueue::with_capacity(8);
+        queue.set_notify_threshold(NotifyThreshold::Items(2), Duration::from_secs(3600));
+        let mut dequeue_len = |enqueue: &dyn Fn(), expected_wakes| {
+            let mut dequeue = pin!(queue.dequeue_vectored());
+            assert!(dequeue.as_mut().poll(&mut cx).is_pending());
+            enqueue();
+            assert_eq!(wakes.0.load(Ordering::Relaxed), expected_wakes);
+            match dequeue.as_mut().poll(&mut cx) {
+                Poll::Ready(vectored) => vectored.unwrap().len(),
+                Poll::Pending => 0,
+            }
+        };
+        // only reaching the threshold wakes the consumer
+        let enqueue_two = || {
+            queue.try_enqueue([0]).unwrap();
+            assert_eq!(wakes.0.load(Ordering::Relaxed), 0);
+            queue.try_enqueue([1]).unwrap();
+        };
+        assert_eq!(dequeue_len(&enqueue_two, 1), 2);
+        // or kicking it
+        let enqueue_kick = || {
+            queue.try_enqueue([0]).unwrap();
+            assert_eq!(wakes.0.load(Ordering::Relaxed), 1);
+            queue.kick();
+        };
+        assert_eq!(dequeue_len(&enqueue_kick, 2), 1);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn notify_threshold_liveness() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let queue = AsyncVectoredQueue::with_capacity(8);
+        queue.set_notify_threshold(NotifyThreshold::Bytes(1024), Duration::from_millis(10));
+        let queue = &queue;
+        let enqueue = |item| async move {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            queue.try_enqueue([item]).unwrap();
+        };
+        let start = std::time::Instant::now();
+        // the timeouts only bound a failing test, as they wake the consumer too
+        const TIMEOUT: Duration = Duration::from_secs(10);
+        runtime.block_on(async {
+            // items below the threshold are dequeued after the max delay
+            let dequeue = tokio::time::timeout(TIMEOUT, queue.dequeue_vectored());
+            let (vectored, ()) = join(dequeue, enqueue(0)).await;
+            assert_eq!(vectored.unwrap().unwrap().len(), 1);
+            let mut batches = queue.batches();
+            let (batch, ()) = join(tokio::time::timeout(TIMEOUT, batches.next()), enqueue(1)).await;
+            assert_eq!(batch.unwrap().unwrap().unwrap(), [[1]]);
+            let wait = tokio::time::timeout(TIMEOUT, queue.wait_nonempty());
+            let (res, ()) = join(wait, enqueue(2)).await;
+            res.unwrap();
+        });
+        assert!(start.elapsed() < TIMEOUT);
+        queue.as_queue().debug_assert_invariants();
+    }
 }
@@ -1,100 +1,808 @@
 use std::{
     cell::UnsafeCell,
-    hint,
+    cmp,
     io::IoSlice,
-    mem,
-    mem::MaybeUninit,
-    sync::atomic::{AtomicUsize, Ordering},
+    iter,
+    mem::{self, ManuallyDrop, MaybeUninit},
+    ops::Range,
+    ptr::NonNull,
+    slice,
+    sync::Arc,
+};
+
+use crate::{
+    alloc::{Allocator, Box as AllocBox, Global, Vec as AllocVec},
+    primitives::{hint, yield_now, AtomicBool, AtomicUsize, Ordering},
 };
 
 static EMPTY_SLICE: &[u8] = &[];
 
-pub(crate) struct Buffer<T> {
-    owned: UnsafeCell<Box<[MaybeUninit<T>]>>,
-    slices: UnsafeCell<Box<[IoSlice<'static>]>>,
+// `writev` fails when the total size of the slices overflows `isize`
+#[cfg(not(test))]
+pub(crate) const MAX_TOTAL_SIZE: usize = isize::MAX as usize;
+#[cfg(test)]
+pub(crate) const MAX_TOTAL_SIZE: usize = u16::MAX as usize;
+
+// Slots reserved before the regular ones for high priority items, filled backward so that
+// they are dequeued at the head of the batch
+pub(crate) const HIGH_PRIORITY_SLOTS: usize = 4;
+
+// Frame headers are stored in blocks prefixed by their length
+pub(crate) const MAX_FRAME_HEADER: usize = 15;
+const FRAME_BLOCK: usize = MAX_FRAME_HEADER + 1;
+
+// Write the frame header of the item bytes into the block, returning its size
+pub(crate) type Framer = Arc<dyn Fn(&[u8], &mut [u8]) -> usize + Send + Sync>;
+
+pub(crate) fn framer<H: AsRef<[u8]>>(
+    framer: impl Fn(&[u8]) -> H + Send + Sync + 'static,
+) -> Framer {
+    Arc::new(move |bytes, block| {
+        let header = framer(bytes);
+        let header = header.as_ref();
+        assert!(
+            header.len() <= MAX_FRAME_HEADER,
+            "frame header exceeds {MAX_FRAME_HEADER} bytes"
+        );
+        block[..header.len()].copy_from_slice(header);
+        header.len()
+    })
+}
+
+// Boxed slice only accessed through raw pointers, so that borrowing some of its elements, e.g.
+// by a concurrent insertion or a gotten batch, is never invalidated by borrowing all of them
+struct RawSlice<T, A: Allocator> {
+    ptr: NonNull<[T]>,
+    alloc: ManuallyDrop<A>,
+}
+
+impl<T, A: Allocator> RawSlice<T, A> {
+    fn new(boxed: AllocBox<[T], A>) -> Self {
+        let (ptr, alloc) = AllocBox::into_raw_with_allocator(boxed);
+        Self {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            alloc: ManuallyDrop::new(alloc),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.ptr.len()
+    }
+
+    fn as_ptr(&self) -> *mut T {
+        self.ptr.as_ptr().cast()
+    }
+
+    // The elements must not be mutably borrowed
+    unsafe fn slice(&self, range: Range<usize>) -> &[T] {
+        assert!(range.start <= range.end && range.end <= self.len());
+        unsafe { slice::from_raw_parts(self.as_ptr().add(range.start), range.len()) }
+    }
+
+    // The elements must not be borrowed
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn slice_mut(&self, range: Range<usize>) -> &mut [T] {
+        assert!(range.start <= range.end && range.end <= self.len());
+        unsafe { slice::from_raw_parts_mut(self.as_ptr().add(range.start), range.len()) }
+    }
+
+    unsafe fn get(&self, index: usize) -> &T {
+        unsafe { &self.slice(index..index + 1)[0] }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_mut(&self, index: usize) -> &mut T {
+        unsafe { &mut self.slice_mut(index..index + 1)[0] }
+    }
+}
+
+impl<T, A: Allocator> Drop for RawSlice<T, A> {
+    fn drop(&mut self) {
+        let alloc = unsafe { ManuallyDrop::take(&mut self.alloc) };
+        drop(unsafe { AllocBox::from_raw_in(self.ptr.as_ptr(), alloc) });
+    }
+}
+
+pub(crate) struct Buffer<T, A: Allocator = Global> {
+    owned: UnsafeCell<RawSlice<MaybeUninit<T>, A>>,
+    slices: UnsafeCell<RawSlice<IoSlice<'static>, A>>,
     len: AtomicUsize,
+    priority_len: AtomicUsize,
+    // items of the batch already dequeued and released, only accessed by the consumer
+    dequeued: AtomicUsize,
     total_size: AtomicUsize,
+    sealed: AtomicBool,
+    // slots reserved before and after the items of a batch, e.g. for headers and trailers
+    header_slots: usize,
+    trailer_slots: usize,
+    // items of at most `inline_threshold` bytes are copied into the arena block of their
+    // owned slot, so their slices are gathered from contiguous memory
+    arena: UnsafeCell<RawSlice<u8, A>>,
+    inline_threshold: usize,
+    // tag of the item of each owned slot, empty if item tags are disabled
+    tags: UnsafeCell<RawSlice<u64, A>>,
+    tagged: bool,
+    // frame header block of each owned slot, empty if framing is disabled; framed items have
+    // their header slice right before their payload slice
+    frames: UnsafeCell<RawSlice<u8, A>>,
+    framer: Option<Framer>,
+    alloc: A,
 }
 
-unsafe impl<T> Send for Buffer<T> {}
+unsafe impl<T, A: Allocator + Send> Send for Buffer<T, A> {}
 
-unsafe impl<T> Sync for Buffer<T> {}
+unsafe impl<T, A: Allocator + Sync> Sync for Buffer<T, A> {}
 
-impl<T> Default for Buffer<T> {
-    fn default() -> Self {
+impl<T, A: Allocator + Clone> Buffer<T, A> {
+    fn new_in(alloc: A) -> Self {
         Self {
-            owned: Default::default(),
-            slices: Default::default(),
+            owned: UnsafeCell::new(RawSlice::new(
+                AllocVec::new_in(alloc.clone()).into_boxed_slice(),
+            )),
+            slices: UnsafeCell::new(RawSlice::new(
+                AllocVec::new_in(alloc.clone()).into_boxed_slice(),
+            )),
             len: Default::default(),
+            priority_len: Default::default(),
+            dequeued: Default::default(),
             total_size: Default::default(),
+            sealed: Default::default(),
+            header_slots: 1,
+            trailer_slots: 1,
+            arena: UnsafeCell::new(RawSlice::new(
+                AllocVec::new_in(alloc.clone()).into_boxed_slice(),
+            )),
+            inline_threshold: 0,
+            tags: UnsafeCell::new(RawSlice::new(
+                AllocVec::new_in(alloc.clone()).into_boxed_slice(),
+            )),
+            tagged: false,
+            frames: UnsafeCell::new(RawSlice::new(
+                AllocVec::new_in(alloc.clone()).into_boxed_slice(),
+            )),
+            framer: None,
+            alloc,
         }
     }
-}
 
-impl<T> Buffer<T> {
-    pub(crate) fn with_capacity(capacity: usize) -> Self {
-        let buffer = Buffer::default();
+    pub(crate) fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let buffer = Buffer::new_in(alloc);
         if capacity > 0 {
             buffer.resize(capacity);
         }
         buffer
     }
 
+    pub(crate) fn resize(&self, capacity: usize) {
+        if capacity > self.capacity() {
+            let mut owned =
+                AllocVec::with_capacity_in(HIGH_PRIORITY_SLOTS + capacity, self.alloc.clone());
+            owned.resize_with(HIGH_PRIORITY_SLOTS + capacity, MaybeUninit::uninit);
+            unsafe { *self.owned.get() = RawSlice::new(owned.into_boxed_slice()) };
+            unsafe { *self.slices.get() = self.alloc_slices(capacity) };
+            unsafe { *self.arena.get() = self.alloc_arena(capacity) };
+            unsafe { *self.tags.get() = self.alloc_tags(capacity) };
+            unsafe { *self.frames.get() = self.alloc_frames(capacity) };
+        }
+    }
+
+    // Must be called on an empty buffer
+    pub(crate) fn set_reserved_slots(&mut self, header_slots: usize, trailer_slots: usize) {
+        self.header_slots = header_slots;
+        self.trailer_slots = trailer_slots;
+        if self.capacity() > 0 {
+            *self.slices.get_mut() = self.alloc_slices(self.capacity());
+        }
+    }
+
+    // Must be called on an empty buffer
+    pub(crate) fn set_inline_threshold(&mut self, inline_threshold: usize) {
+        self.inline_threshold = inline_threshold;
+        *self.arena.get_mut() = self.alloc_arena(self.capacity());
+    }
+
+    // Must be called on an empty buffer
+    pub(crate) fn enable_tags(&mut self) {
+        self.tagged = true;
+        *self.tags.get_mut() = self.alloc_tags(self.capacity());
+    }
+
+    // Must be called on an empty buffer
+    pub(crate) fn set_framer(&mut self, framer: Framer) {
+        self.framer = Some(framer);
+        if self.capacity() > 0 {
+            *self.slices.get_mut() = self.alloc_slices(self.capacity());
+        }
+        *self.frames.get_mut() = self.alloc_frames(self.capacity());
+    }
+
+    fn alloc_frames(&self, capacity: usize) -> RawSlice<u8, A> {
+        let len = match capacity {
+            _ if self.framer.is_none() => 0,
+            0 => 0,
+            _ => (HIGH_PRIORITY_SLOTS + capacity) * FRAME_BLOCK,
+        };
+        let mut frames = AllocVec::with_capacity_in(len, self.alloc.clone());
+        frames.resize(len, 0);
+        RawSlice::new(frames.into_boxed_slice())
+    }
+
+    fn alloc_tags(&self, capacity: usize) -> RawSlice<u64, A> {
+        let len = match capacity {
+            _ if !self.tagged => 0,
+            0 => 0,
+            _ => HIGH_PRIORITY_SLOTS + capacity,
+        };
+        let mut tags = AllocVec::with_capacity_in(len, self.alloc.clone());
+        tags.resize(len, 0);
+        RawSlice::new(tags.into_boxed_slice())
+    }
+
+    fn alloc_arena(&self, capacity: usize) -> RawSlice<u8, A> {
+        let len = match capacity {
+            0 => 0,
+            _ => (HIGH_PRIORITY_SLOTS + capacity)
+                .checked_mul(self.inline_threshold)
+                .expect("capacity overflow"),
+        };
+        let mut arena = AllocVec::with_capacity_in(len, self.alloc.clone());
+        arena.resize(len, 0);
+        RawSlice::new(arena.into_boxed_slice())
+    }
+
+    fn alloc_slices(&self, capacity: usize) -> RawSlice<IoSlice<'static>, A> {
+        let len = self.stride() * (HIGH_PRIORITY_SLOTS + capacity)
+            + self.header_slots
+            + self.trailer_slots;
+        let mut slices = AllocVec::with_capacity_in(len, self.alloc.clone());
+        slices.resize(len, IoSlice::new(EMPTY_SLICE));
+        RawSlice::new(slices.into_boxed_slice())
+    }
+}
+
+impl<T, A: Allocator> Buffer<T, A> {
+    // Storage accessors, only the elements of the storage being borrowed afterwards
+    fn owned(&self) -> &RawSlice<MaybeUninit<T>, A> {
+        unsafe { &*self.owned.get() }
+    }
+
+    fn slices(&self) -> &RawSlice<IoSlice<'static>, A> {
+        unsafe { &*self.slices.get() }
+    }
+
+    fn arena(&self) -> &RawSlice<u8, A> {
+        unsafe { &*self.arena.get() }
+    }
+
+    fn tags(&self) -> &RawSlice<u64, A> {
+        unsafe { &*self.tags.get() }
+    }
+
+    fn frames(&self) -> &RawSlice<u8, A> {
+        unsafe { &*self.frames.get() }
+    }
+
     pub(crate) fn capacity(&self) -> usize {
-        unsafe { &*self.owned.get() }.len()
+        self.owned().len().saturating_sub(HIGH_PRIORITY_SLOTS)
+    }
+
+    // `MaybeUninit<T>` has the size of `T`, so zero-sized items allocate nothing
+    pub(crate) fn allocated_size(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.owned().len() * mem::size_of::<MaybeUninit<T>>()
+            + self.slices().len() * mem::size_of::<IoSlice>()
+            + self.arena().len()
+            + self.tags().len() * mem::size_of::<u64>()
+            + self.frames().len()
+    }
+
+    pub(crate) fn is_tagged(&self) -> bool {
+        self.tagged
+    }
+
+    // Items of the gotten batch, which must still be alive, with their tags
+    pub(crate) fn gotten_items(&self, count: usize) -> impl Iterator<Item = (&T, u64)> {
+        let first = self.start() + self.dequeued();
+        let owned = unsafe { self.owned().slice(first..first + count) };
+        owned.iter().enumerate().map(move |(i, item)| {
+            let tag = match self.tagged {
+                true => unsafe { *self.tags().get(first + i) },
+                false => 0,
+            };
+            (unsafe { item.assume_init_ref() }, tag)
+        })
+    }
+
+    pub(crate) fn reserved_slots(&self) -> (usize, usize) {
+        (self.header_slots, self.trailer_slots)
     }
 
     pub(crate) fn len(&self) -> usize {
-        self.len.load(Ordering::Relaxed)
+        self.len.load(Ordering::Relaxed) + self.priority_len()
     }
 
-    pub(crate) fn resize(&self, capacity: usize) {
-        if capacity > self.capacity() {
-            let owned = (0..capacity).map(|_| MaybeUninit::uninit()).collect();
-            let slices = vec![IoSlice::new(EMPTY_SLICE); capacity + 2];
-            unsafe { self.owned.get().replace(owned) };
-            unsafe { self.slices.get().replace(slices.into()) };
+    pub(crate) fn priority_len(&self) -> usize {
+        self.priority_len.load(Ordering::Acquire)
+    }
+
+    // Index of the first batch header slot; the slices of the item at index `i` of the owned
+    // slots start at index `slot(i)`, so the header slots end right before the high priority
+    // items
+    fn start(&self) -> usize {
+        HIGH_PRIORITY_SLOTS - self.priority_len()
+    }
+
+    // Number of slices per item, 2 for framed items
+    pub(crate) fn stride(&self) -> usize {
+        1 + self.framer.is_some() as usize
+    }
+
+    // Index of the first slice of the item at index `i` of the owned slots, i.e. its frame
+    // header slice if framed, its payload slice otherwise
+    fn slot(&self, i: usize) -> usize {
+        self.stride() * i + self.header_slots
+    }
+
+    fn payload_slot(&self, i: usize) -> usize {
+        self.slot(i) + self.stride() - 1
+    }
+
+    pub(crate) fn total_size(&self) -> usize {
+        self.total_size.load(Ordering::Relaxed)
+    }
+
+    // Number of completed insertions, synchronized with their writes
+    pub(crate) fn inserted(&self) -> usize {
+        self.len.load(Ordering::Acquire) + self.priority_len()
+    }
+
+    // Lengths passed to buffer methods include high priority items.
+
+    // The `len` first slots must have been inserted, and no batch gotten from them be alive
+    pub(crate) fn items(&self, len: usize) -> impl Iterator<Item = &T> {
+        // only the inserted slots are borrowed, as others may be concurrently inserted
+        self.item_indexes(len)
+            .map(|i| unsafe { self.owned().get(i).assume_init_ref() })
+    }
+
+    // Owned slots of the `len` first items, in order
+    fn item_indexes(&self, len: usize) -> Box<dyn Iterator<Item = usize>> {
+        let start = self.start();
+        let dequeued = self.dequeued();
+        if len == dequeued {
+            Box::new(iter::empty())
+        } else if dequeued > 0 {
+            // the batch has already been gotten, so high priority items are in order
+            Box::new(start + dequeued..start + len)
+        } else {
+            let priority_len = self.priority_len();
+            let priority = HIGH_PRIORITY_SLOTS - priority_len..HIGH_PRIORITY_SLOTS;
+            let regular = HIGH_PRIORITY_SLOTS..HIGH_PRIORITY_SLOTS + len - priority_len;
+            Box::new(priority.rev().chain(regular))
         }
     }
 
-    pub(crate) fn get(&self, len: usize) -> Option<(&mut [IoSlice], usize)> {
-        for _ in 0..100 {
-            if self.len.load(Ordering::Acquire) == len {
-                return Some((
-                    unsafe { mem::transmute(&mut (*self.slices.get())[..len + 2]) },
-                    self.total_size.load(Ordering::Acquire),
-                ));
+    pub(crate) fn dequeued(&self) -> usize {
+        self.dequeued.load(Ordering::Relaxed)
+    }
+
+    // Whether `count` items of `size` bytes in total would exceed the maximum total size
+    pub(crate) fn would_overflow(&self, count: usize, size: usize) -> bool {
+        // the frame headers are not known before insertion, so the largest ones are assumed
+        let headers = match self.framer {
+            Some(_) => count.saturating_mul(MAX_FRAME_HEADER),
+            None => 0,
+        };
+        let size = size.saturating_add(headers);
+        match self.total_size.load(Ordering::Relaxed).checked_add(size) {
+            Some(total_size) => total_size > MAX_TOTAL_SIZE,
+            None => true,
+        }
+    }
+
+    // A sealed buffer is considered full, whatever its remaining slots
+    pub(crate) fn seal(&self) {
+        self.sealed.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_sealed(&self) -> bool {
+        self.sealed.load(Ordering::Relaxed)
+    }
+
+    // Get at most `max` of the remaining items of the batch, waiting for insertions in
+    // progress by spinning `spins` times, then yielding `yields` times; the slices, borrowing
+    // the items, are valid until the batch is released or ungotten.
+    // Giving up is safe whatever the bound: once swapped, the buffer cannot be reserved
+    // anymore, so `len` is final, and the batch is kept pending to be gotten by the next
+    // dequeuing, which a producer triggers by notifying the consumer after its insertion.
+    pub(crate) fn get(
+        &self,
+        len: usize,
+        max: usize,
+        (spins, yields): (u32, u32),
+    ) -> Option<(NonNull<[IoSlice<'static>]>, usize)> {
+        let attempts = spins.saturating_add(yields);
+        for attempt in 0..cmp::max(attempts, 1) {
+            if self.inserted() == len {
+                let dequeued = self.dequeued();
+                if dequeued == 0 {
+                    // high priority items were inserted backward
+                    self.reverse_priority();
+                }
+                let first = self.stride() * (self.start() + dequeued);
+                let count = cmp::min(len - dequeued, max);
+                let (header_slots, trailer_slots) = self.reserved_slots();
+                let payload = self.stride() * count;
+                let slices = unsafe {
+                    self.slices()
+                        .slice_mut(first..first + header_slots + payload + trailer_slots)
+                };
+                let total_size = if count == len - dequeued {
+                    self.total_size.load(Ordering::Acquire)
+                } else {
+                    // trailer slots of a partial batch are the ones of the next items, restored
+                    // when the batch is released or ungotten
+                    for slice in &mut slices[header_slots + payload..] {
+                        *slice = IoSlice::new(EMPTY_SLICE);
+                    }
+                    slices[header_slots..header_slots + payload]
+                        .iter()
+                        .map(|slice| slice.len())
+                        .sum()
+                };
+                return Some((NonNull::from(slices), total_size));
+            }
+            if attempt < spins {
+                hint::spin_loop();
+            } else if attempt < attempts {
+                yield_now();
             }
-            hint::spin_loop()
         }
         None
     }
 
+    fn reverse_priority(&self) {
+        let start = self.start();
+        let slices = unsafe {
+            self.slices()
+                .slice_mut(self.slot(start)..self.slot(HIGH_PRIORITY_SLOTS))
+        };
+        slices.reverse();
+        // the slices of each item are reversed too, frame header last
+        slices
+            .chunks_exact_mut(self.stride())
+            .for_each(<[_]>::reverse);
+        unsafe { self.owned().slice_mut(start..HIGH_PRIORITY_SLOTS).reverse() };
+        if self.tagged {
+            unsafe { self.tags().slice_mut(start..HIGH_PRIORITY_SLOTS).reverse() };
+        }
+        if self.inline_threshold > 0 {
+            self.reverse_priority_arena(start);
+        }
+        if self.framer.is_some() {
+            self.reverse_priority_frames(start);
+        }
+    }
+
+    // Arena blocks follow their owned slots, so the inlined slices must be moved with them
+    fn reverse_priority_arena(&self, start: usize) {
+        let threshold = self.inline_threshold;
+        // moving the blocks invalidates their slices, which must not be read afterwards
+        let mut lens = [0; HIGH_PRIORITY_SLOTS];
+        for (i, len) in lens.iter_mut().enumerate().skip(start) {
+            *len = unsafe { self.slices().get(self.payload_slot(i)) }.len();
+        }
+        let blocks = unsafe {
+            self.arena()
+                .slice_mut(start * threshold..HIGH_PRIORITY_SLOTS * threshold)
+        };
+        reverse_blocks(blocks, threshold);
+        for (i, &len) in lens.iter().enumerate().skip(start) {
+            if self.is_inline(len) {
+                unsafe { *self.slices().get_mut(self.payload_slot(i)) = self.arena_slice(i, len) };
+            }
+        }
+    }
+
+    // Frame blocks follow their owned slots like arena ones, their length being kept inside
+    fn reverse_priority_frames(&self, start: usize) {
+        let blocks = unsafe {
+            self.frames()
+                .slice_mut(start * FRAME_BLOCK..HIGH_PRIORITY_SLOTS * FRAME_BLOCK)
+        };
+        reverse_blocks(blocks, FRAME_BLOCK);
+        for i in start..HIGH_PRIORITY_SLOTS {
+            unsafe { *self.slices().get_mut(self.slot(i)) = self.frame_slice(i) };
+        }
+    }
+
+    fn is_inline(&self, len: usize) -> bool {
+        self.inline_threshold > 0 && len <= self.inline_threshold
+    }
+
+    // The `len` first bytes of the arena block of the owned slot `i`
+    #[allow(clippy::mut_from_ref)]
+    fn arena_block(&self, i: usize, len: usize) -> &mut [u8] {
+        let offset = i * self.inline_threshold;
+        unsafe { self.arena().slice_mut(offset..offset + len) }
+    }
+
+    // The arena block is only written again after the batch is released
+    fn arena_slice(&self, i: usize, len: usize) -> IoSlice<'static> {
+        let block = self.arena_block(i, len);
+        IoSlice::new(unsafe { slice::from_raw_parts(block.as_ptr(), len) })
+    }
+
+    // The frame header block of the owned slot `i`, its first byte being the header length
+    #[allow(clippy::mut_from_ref)]
+    fn frame_block(&self, i: usize) -> &mut [u8] {
+        unsafe {
+            self.frames()
+                .slice_mut(i * FRAME_BLOCK..(i + 1) * FRAME_BLOCK)
+        }
+    }
+
+    fn frame_len(&self, i: usize) -> usize {
+        unsafe { *self.frames().get(i * FRAME_BLOCK) }.into()
+    }
+
+    // The frame block is only written again after the batch is released
+    fn frame_slice(&self, i: usize) -> IoSlice<'static> {
+        let header = unsafe {
+            self.frames()
+                .slice(i * FRAME_BLOCK + 1..(i + 1) * FRAME_BLOCK)
+        };
+        IoSlice::new(unsafe { slice::from_raw_parts(header.as_ptr(), self.frame_len(i)) })
+    }
+
+    // Drop the remaining items of the batch
     pub(crate) fn clear(&self, len: usize) {
-        for i in 0..len {
-            unsafe { (*self.owned.get())[i].assume_init_drop() }
+        let start = self.start();
+        for i in start + self.dequeued()..start + len {
+            unsafe { self.owned().get_mut(i).assume_init_drop() }
+        }
+        self.reset(start, len);
+    }
+
+    // Reset the buffer without dropping its items, e.g. those of a leaked batch
+    pub(crate) fn leak(&self) {
+        self.reset(self.start(), self.len());
+    }
+
+    // Move the `len` first items out, in order, resetting the buffer; items not iterated are
+    // leaked, so the iterator must be exhausted, and dequeuing must be claimed
+    pub(crate) fn drain_items(&self, len: usize) -> impl Iterator<Item = T> + '_ {
+        let (start, dequeued) = (self.start(), self.dequeued());
+        self.reset(start, len);
+        // high priority items were inserted backward if the batch has not been gotten
+        let (first, regular) = match dequeued {
+            0 => (start, HIGH_PRIORITY_SLOTS),
+            _ => (start + dequeued, start + dequeued),
+        };
+        let owned = self.owned();
+        (first..regular)
+            .rev()
+            .chain(regular..start + len)
+            .map(move |i| unsafe { owned.get(i).assume_init_read() })
+    }
+
+    fn reset(&self, start: usize, len: usize) {
+        // reset every slot the batch may have exposed, header/trailer included, so that
+        // nothing swapped in by a leaked frame survives into the next batch
+        let reserved = self.header_slots + self.trailer_slots;
+        let stride = self.stride();
+        let end = cmp::min(stride * (start + len) + reserved, self.slices().len());
+        for slice in unsafe { self.slices().slice_mut(cmp::min(stride * start, end)..end) } {
+            *slice = IoSlice::new(EMPTY_SLICE);
         }
         self.len.store(0, Ordering::Relaxed);
+        self.priority_len.store(0, Ordering::Relaxed);
+        self.dequeued.store(0, Ordering::Relaxed);
         self.total_size.store(0, Ordering::Relaxed);
+        self.sealed.store(false, Ordering::Relaxed);
     }
 }
 
-impl<T> Buffer<T>
+impl<T, A: Allocator> Buffer<T, A>
 where
     T: AsRef<[u8]>,
 {
-    pub(crate) fn insert(&self, slot: usize, bytes: T) {
-        let index = self.capacity() - slot;
-        let owned_bytes = unsafe { (*self.owned.get())[index].write(bytes) };
-        let slice = IoSlice::new(owned_bytes.as_ref());
-        unsafe { (*self.slices.get())[index + 1] = mem::transmute(slice) };
-        self.total_size.fetch_add(slice.len(), Ordering::AcqRel);
+    // Size of the inserted items, frame headers included, i.e. the expected total size
+    #[cfg(debug_assertions)]
+    pub(crate) fn items_size(&self) -> usize {
+        let frame_len = |i| match self.framer {
+            Some(_) => self.frame_len(i),
+            None => 0,
+        };
+        self.items(self.len())
+            .zip(self.item_indexes(self.len()))
+            .map(|(item, i)| item.as_ref().len() + frame_len(i))
+            .sum()
+    }
+
+    // Return the size of the inserted bytes, frame header included
+    pub(crate) fn insert(&self, slot: usize, bytes: T, tag: u64) -> usize {
+        let size = self.write(HIGH_PRIORITY_SLOTS + self.capacity() - slot, bytes, tag);
         self.len.fetch_add(1, Ordering::AcqRel);
+        size
+    }
+
+    // Release the `count` first remaining items of the gotten batch, returning whether the
+    // whole batch has been released
+    pub(crate) fn release(&self, count: usize) -> bool {
+        let (start, dequeued, len) = (self.start(), self.dequeued(), self.len());
+        // items without drop glue, e.g. `&'static [u8]`, are only read for the size of a
+        // partial release
+        if !mem::needs_drop::<T>() && dequeued + count == len {
+            self.reset(start, len);
+            return true;
+        }
+        self.consume(count, drop)
+    }
+
+    pub(crate) fn drain(&self, count: usize) -> (Vec<T>, bool) {
+        let mut items = Vec::with_capacity(count);
+        let released = self.consume(count, |item| items.push(item));
+        (items, released)
+    }
+
+    fn consume(&self, count: usize, mut f: impl FnMut(T)) -> bool {
+        let (start, dequeued, len) = (self.start(), self.dequeued(), self.len());
+        let consumed = start + dequeued..start + dequeued + count;
+        let owned = unsafe { self.owned().slice_mut(consumed.clone()) };
+        let mut size = match self.framer {
+            Some(_) => consumed.map(|i| self.frame_len(i)).sum(),
+            None => 0,
+        };
+        for item in owned {
+            let item = unsafe { item.assume_init_read() };
+            size += item.as_ref().len();
+            f(item);
+        }
+        if dequeued + count == len {
+            self.reset(start, len);
+            return true;
+        }
+        let next = start + dequeued + count;
+        self.dequeued.store(dequeued + count, Ordering::Relaxed);
+        self.total_size.fetch_sub(size, Ordering::Relaxed);
+        // the header slots of the remaining items were the last released ones
+        self.reset_headers(next);
+        self.restore_trailers(next, start + len);
+        false
+    }
+
+    // Undo `get` of `count` items, so the batch can be gotten again
+    pub(crate) fn unget(&self, count: usize) {
+        let (start, dequeued, len) = (self.start(), self.dequeued(), self.len());
+        if dequeued == 0 {
+            self.reverse_priority();
+        }
+        self.reset_headers(start + dequeued);
+        self.restore_trailers(start + dequeued + count, start + len);
+    }
+
+    fn reset_headers(&self, first: usize) {
+        let first = self.stride() * first;
+        for slice in unsafe { self.slices().slice_mut(first..first + self.header_slots) } {
+            *slice = IoSlice::new(EMPTY_SLICE);
+        }
+    }
+
+    // Trailer slots of the batch ending before `next` may be the slots of the next items, or
+    // the batch trailer slots after `end`
+    fn restore_trailers(&self, next: usize, end: usize) {
+        let stride = self.stride();
+        for slot in self.slot(next)..self.slot(next) + self.trailer_slots {
+            let i = (slot - self.header_slots) / stride;
+            let slice = if i >= end {
+                IoSlice::new(EMPTY_SLICE)
+            } else if slot < self.payload_slot(i) {
+                self.frame_slice(i)
+            } else {
+                self.item_slice(i)
+            };
+            unsafe { *self.slices().get_mut(slot) = slice };
+        }
+    }
+
+    // Set the slices of the item in the owned slot `index`, copying it into the arena if small,
+    // and writing its frame header if framed; return their size
+    fn place(&self, index: usize) -> usize {
+        let bytes = unsafe { self.owned().get(index).assume_init_ref() }.as_ref();
+        if self.is_inline(bytes.len()) {
+            self.arena_block(index, bytes.len()).copy_from_slice(bytes);
+        }
+        let mut size = 0;
+        if let Some(framer) = &self.framer {
+            let block = self.frame_block(index);
+            size = framer(bytes, &mut block[1..]);
+            block[0] = size as u8;
+            unsafe { *self.slices().get_mut(self.slot(index)) = self.frame_slice(index) };
+        }
+        let slice = self.item_slice(index);
+        unsafe { *self.slices().get_mut(self.payload_slot(index)) = slice };
+        size + slice.len()
+    }
+
+    // Remove the owned slots `dead`, left unfilled by a panicking or short insertion, from the
+    // regular slots ending at `end`, all the others being inserted; their items, if any, are
+    // leaked.
+    pub(crate) fn remove_dead(&self, dead: Range<usize>, end: usize) {
+        let count = dead.len();
+        unsafe { self.owned().slice_mut(dead.start..end).rotate_left(count) };
+        if self.tagged {
+            unsafe { self.tags().slice_mut(dead.start..end).rotate_left(count) };
+        }
+        for index in dead.start..end - count {
+            self.place(index);
+        }
+        for slice in unsafe {
+            self.slices()
+                .slice_mut(self.slot(end - count)..self.slot(end))
+        } {
+            *slice = IoSlice::new(EMPTY_SLICE);
+        }
+    }
+
+    // The item is only moved or dropped after the batch is released
+    fn item_slice(&self, i: usize) -> IoSlice<'static> {
+        let bytes = unsafe { self.owned().get(i).assume_init_ref() }.as_ref();
+        if self.is_inline(bytes.len()) {
+            return self.arena_slice(i, bytes.len());
+        }
+        IoSlice::new(unsafe { slice::from_raw_parts(bytes.as_ptr(), bytes.len()) })
+    }
+
+    // Must be called while holding the overflow lock, on the enqueuing buffer, so that no
+    // concurrent high priority insertion nor swap can happen.
+    // Return the size of the inserted bytes, frame header included
+    pub(crate) fn insert_priority(&self, bytes: T, tag: u64) -> Result<usize, T> {
+        let priority_len = self.priority_len.load(Ordering::Relaxed);
+        let size = bytes.as_ref().len();
+        if priority_len == HIGH_PRIORITY_SLOTS
+            || self.capacity() == 0
+            || self.would_overflow(1, size)
+        {
+            return Err(bytes);
+        }
+        let size = self.write(HIGH_PRIORITY_SLOTS - 1 - priority_len, bytes, tag);
+        self.priority_len.store(priority_len + 1, Ordering::Release);
+        Ok(size)
+    }
+
+    // Items are kept in their owned slot even if they are slices themselves: the slices of a
+    // batch can be modified through `Vectored`, so they cannot be read back as items.
+    // Return the size of the written slices.
+    fn write(&self, index: usize, bytes: T, tag: u64) -> usize {
+        unsafe { self.owned().get_mut(index).write(bytes) };
+        if self.tagged {
+            unsafe { *self.tags().get_mut(index) = tag };
+        }
+        let size = self.place(index);
+        // concurrent insertions may still exceed the maximum, saturate as a last resort
+        let prev_size = self
+            .total_size
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |total_size| {
+                Some(total_size.saturating_add(size))
+            })
+            .unwrap();
+        debug_assert!(prev_size.checked_add(size).is_some());
+        size
+    }
+}
+
+// Reverse the order of the blocks of `size` bytes
+fn reverse_blocks(blocks: &mut [u8], size: usize) {
+    let count = blocks.len() / size;
+    for i in 0..count / 2 {
+        let (left, right) = blocks.split_at_mut((count - 1 - i) * size);
+        left[i * size..(i + 1) * size].swap_with_slice(&mut right[..size]);
     }
 }
 
-impl<T> Drop for Buffer<T> {
+impl<T, A: Allocator> Drop for Buffer<T, A> {
     fn drop(&mut self) {
-        self.clear(self.len.load(Ordering::Relaxed));
+        self.clear(self.len());
     }
 }
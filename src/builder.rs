@@ -0,0 +1,295 @@
+use std::{marker::PhantomData, sync::Arc, time::Instant};
+
+#[cfg(feature = "async")]
+use crate::r#async::AsyncVectoredQueue;
+#[cfg(feature = "sync")]
+use crate::sync::SyncVectoredQueue;
+use crate::{
+    alloc::{Allocator, Global},
+    buffer::{self, Framer, HIGH_PRIORITY_SLOTS},
+    clock::Clock,
+    error::BuildError,
+    queue::VectoredQueue,
+};
+
+// Encoded capacities are shifted by one bit, and the closed flags take the two highest ones.
+const MAX_CAPACITY: usize = usize::MAX >> 3;
+
+/// Growth of the capacity when overflowing items are moved into the buffers.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Growth {
+    /// Grow by the number of overflowing items.
+    #[default]
+    Exact,
+    /// Grow to at least twice the current capacity, to amortize repeated overflows.
+    Double,
+}
+
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) max_bytes: usize,
+    pub(crate) growth: Growth,
+    // `None` for the real clock, to avoid allocating it
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_bytes: usize::MAX,
+            growth: Growth::default(),
+            clock: None,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn now(&self) -> Instant {
+        match &self.clock {
+            Some(clock) => clock.now(),
+            None => Instant::now(),
+        }
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn park_timeout(&self, timeout: std::time::Duration) -> std::time::Duration {
+        match &self.clock {
+            Some(clock) => clock.park_timeout(timeout),
+            None => timeout,
+        }
+    }
+}
+
+/// Builder of a configured [`VectoredQueue`].
+///
+/// # Examples
+///
+/// ```
+/// use vectored_queue::{Growth, VectoredQueue};
+///
+/// let queue = VectoredQueue::<Vec<u8>>::builder()
+///     .capacity(64)
+///     .max_bytes(4096)
+///     .growth(Growth::Double)
+///     .build()
+///     .unwrap();
+/// assert_eq!(queue.capacity(), 64);
+/// ```
+pub struct VectoredQueueBuilder<T, A = Global> {
+    capacity: usize,
+    reserved_slots: (usize, usize),
+    inline_threshold: usize,
+    item_tags: bool,
+    framer: Option<Framer>,
+    config: Config,
+    alloc: A,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for VectoredQueueBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> VectoredQueueBuilder<T> {
+    pub fn new() -> Self {
+        Self {
+            capacity: 0,
+            reserved_slots: (1, 1),
+            inline_threshold: 0,
+            item_tags: false,
+            framer: None,
+            config: Config::default(),
+            alloc: Global,
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> VectoredQueueBuilder<T, A> {
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Bound the pending bytes accepted by [`try_enqueue`](VectoredQueue::try_enqueue).
+    ///
+    /// The bound is checked against [`pending_bytes`](VectoredQueue::pending_bytes), so it
+    /// is approximate; an empty queue still accepts larger bytes.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.config.max_bytes = max_bytes;
+        self
+    }
+
+    /// Numbers of header and trailer slots surrounding the payload of each batch, one of each
+    /// by default, see [`Vectored::reserved_mut`](crate::Vectored::reserved_mut).
+    pub fn reserved_slots(mut self, header_slots: usize, trailer_slots: usize) -> Self {
+        self.reserved_slots = (header_slots, trailer_slots);
+        self
+    }
+
+    /// Copy items of at most `bytes` bytes into an arena owned by the queue, so that their
+    /// slices are gathered from contiguous memory; disabled by default.
+    ///
+    /// Each slot is given an arena block of `bytes` bytes, allocated with the buffers. Inlined
+    /// items are still owned by the queue until released.
+    pub fn inline_threshold(mut self, bytes: usize) -> Self {
+        self.inline_threshold = bytes;
+        self
+    }
+
+    /// Store a tag with each item, see [`try_enqueue_tagged`](VectoredQueue::try_enqueue_tagged);
+    /// disabled by default.
+    pub fn item_tags(mut self) -> Self {
+        self.item_tags = true;
+        self
+    }
+
+    /// Prefix each item with the frame header returned by `framer` for its bytes, e.g. a
+    /// length prefix; disabled by default.
+    ///
+    /// The payload slices of a batch alternate the header and the bytes of each item, the
+    /// header being copied into a block of the queue. The [total size](crate::Vectored::total_size)
+    /// counts the headers, while the [length](VectoredQueue::len) still counts the items; an
+    /// overflowing item is framed, and its header counted, once moved into the buffers.
+    ///
+    /// # Panics
+    ///
+    /// Enqueuing panics if a header exceeds 15 bytes.
+    pub fn framer<H: AsRef<[u8]>>(
+        mut self,
+        framer: impl Fn(&[u8]) -> H + Send + Sync + 'static,
+    ) -> Self {
+        self.framer = Some(buffer::framer(framer));
+        self
+    }
+
+    pub fn growth(mut self, growth: Growth) -> Self {
+        self.config.growth = growth;
+        self
+    }
+
+    /// Time source of batch ages and of the sync queue deadlines, e.g. a
+    /// [`MockClock`](crate::MockClock) for testing.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.config.clock = Some(Arc::new(clock));
+        self
+    }
+
+    #[cfg(feature = "allocator-api2")]
+    pub fn allocator<B: Allocator + Clone>(self, alloc: B) -> VectoredQueueBuilder<T, B> {
+        VectoredQueueBuilder {
+            capacity: self.capacity,
+            reserved_slots: self.reserved_slots,
+            inline_threshold: self.inline_threshold,
+            item_tags: self.item_tags,
+            framer: self.framer,
+            config: self.config,
+            alloc,
+            _item: PhantomData,
+        }
+    }
+
+    pub fn build(self) -> Result<VectoredQueue<T, A>, BuildError> {
+        let (header_slots, trailer_slots) = self.reserved_slots;
+        let slots = [header_slots, trailer_slots]
+            .into_iter()
+            .try_fold(self.capacity, usize::checked_add);
+        if slots.is_none_or(|slots| slots > MAX_CAPACITY) {
+            return Err(BuildError::CapacityOverflow);
+        }
+        let arena_size = (HIGH_PRIORITY_SLOTS + self.capacity).checked_mul(self.inline_threshold);
+        if arena_size.is_none_or(|size| size > isize::MAX as usize) {
+            return Err(BuildError::CapacityOverflow);
+        }
+        if self.config.max_bytes == 0 {
+            return Err(BuildError::ZeroMaxBytes);
+        }
+        let mut queue = VectoredQueue::with_capacity_alloc(self.capacity, self.alloc);
+        queue.set_reserved_slots(header_slots, trailer_slots);
+        if self.inline_threshold > 0 {
+            queue.set_inline_threshold(self.inline_threshold);
+        }
+        if self.item_tags {
+            queue.enable_tags();
+        }
+        if let Some(framer) = self.framer {
+            queue.set_framer(framer);
+        }
+        queue.configure(self.config);
+        Ok(queue)
+    }
+
+    #[cfg(feature = "sync")]
+    pub fn build_sync(self) -> Result<SyncVectoredQueue<T, A>, BuildError> {
+        Ok(SyncVectoredQueue::from_queue(self.build()?))
+    }
+
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> Result<AsyncVectoredQueue<T, A>, BuildError> {
+        Ok(AsyncVectoredQueue::from_queue(self.build()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{builder::Growth, error::BuildError, VectoredQueue};
+
+    #[test]
+    fn validation() {
+        assert!(matches!(
+            VectoredQueue::<[u8; 1]>::builder()
+                .capacity(usize::MAX)
+                .build(),
+            Err(BuildError::CapacityOverflow)
+        ));
+        assert!(matches!(
+            VectoredQueue::<[u8; 1]>::builder()
+                .capacity(1)
+                .reserved_slots(usize::MAX, 1)
+                .build(),
+            Err(BuildError::CapacityOverflow)
+        ));
+        assert!(matches!(
+            VectoredQueue::<[u8; 1]>::builder().max_bytes(0).build(),
+            Err(BuildError::ZeroMaxBytes)
+        ));
+    }
+
+    #[test]
+    fn max_bytes() {
+        let queue = VectoredQueue::builder()
+            .capacity(8)
+            .max_bytes(3)
+            .build()
+            .unwrap();
+        // an empty queue accepts larger bytes
+        queue.try_enqueue(vec![0; 4]).unwrap();
+        assert!(queue.try_enqueue(vec![0]).is_err());
+        drop(queue.try_dequeue_vectored().unwrap().vectored().unwrap());
+        queue.try_enqueue(vec![0; 2]).unwrap();
+        queue.try_enqueue(vec![0]).unwrap();
+        assert!(queue.try_enqueue(vec![0]).is_err());
+        // unbounded enqueuing ignores the limit
+        queue.enqueue_unbounded(vec![0]).unwrap();
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn growth() {
+        for (growth, capacity) in [(Growth::Exact, 5), (Growth::Double, 8)] {
+            let queue = VectoredQueue::builder()
+                .capacity(4)
+                .growth(growth)
+                .build()
+                .unwrap();
+            for i in 0..5 {
+                queue.enqueue_unbounded([i]).unwrap();
+            }
+            drop(queue.try_dequeue_vectored().unwrap().vectored().unwrap());
+            assert_eq!(queue.capacity(), capacity);
+            queue.debug_assert_invariants();
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Source of time of the queue, used for batch ages and blocking deadlines.
+///
+/// It allows simulating time in tests, see [`MockClock`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Duration actually blocked by the sync queue when waiting `timeout` of this clock time;
+    /// waiting is resumed afterwards if the deadline has not elapsed.
+    fn park_timeout(&self, timeout: Duration) -> Duration {
+        timeout
+    }
+}
+
+/// System monotonic clock, used by default.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Manually advanced clock, starting at its creation instant.
+///
+/// Clones share the same time. Blocked waiters poll it every millisecond.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    elapsed_ns: Arc<AtomicU64>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed_ns: Arc::default(),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.elapsed_ns.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + Duration::from_nanos(self.elapsed_ns.load(Ordering::Relaxed))
+    }
+
+    fn park_timeout(&self, timeout: Duration) -> Duration {
+        timeout.min(Duration::from_millis(1))
+    }
+}
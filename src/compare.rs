@@ -0,0 +1,180 @@
+use std::io::IoSlice;
+
+use crate::{
+    alloc::Allocator,
+    vectored::{Vectored, VectoredFrame},
+};
+
+// Offset of the first byte differing between the concatenated slices and `bytes`, or of the
+// end of the shortest one, `None` if they are equal.
+fn first_divergence(slices: &[IoSlice], mut bytes: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    for slice in slices {
+        let len = slice.len().min(bytes.len());
+        if let Some(i) = (0..len).find(|&i| slice[i] != bytes[i]) {
+            return Some(offset + i);
+        }
+        if len < slice.len() {
+            return Some(offset + len);
+        }
+        bytes = &bytes[len..];
+        offset += len;
+    }
+    (!bytes.is_empty()).then_some(offset)
+}
+
+fn slices_eq(slices: &[IoSlice], bytes: &[u8]) -> bool {
+    first_divergence(slices, bytes).is_none()
+}
+
+/// Describe how the slices differ from the expected bytes, used by [`assert_batch_eq!`].
+#[cfg(feature = "test-util")]
+#[doc(hidden)]
+pub fn batch_diff(slices: &[IoSlice], expected: &[u8]) -> Option<String> {
+    let offset = first_divergence(slices, expected)?;
+    let len: usize = slices.iter().map(|slice| slice.len()).sum();
+    let byte = |bytes: Option<&u8>| bytes.map_or("end".into(), |b| format!("{b:#04x}"));
+    let actual = slices.iter().flat_map(|slice| slice.iter()).nth(offset);
+    Some(format!(
+        "batch length {len}, expected length {}, first divergent offset {offset} ({} != {})",
+        expected.len(),
+        byte(actual),
+        byte(expected.get(offset)),
+    ))
+}
+
+/// Assert that the concatenated payload of a batch, e.g. a [`Vectored`], equals the expected
+/// bytes, printing the lengths and the first divergent offset on failure.
+///
+/// # Examples
+///
+/// ```
+/// use vectored_queue::{assert_batch_eq, VectoredQueue};
+///
+/// let queue = VectoredQueue::with_capacity(2);
+/// queue.try_enqueue(b"hello ".to_vec()).unwrap();
+/// queue.try_enqueue(b"world".to_vec()).unwrap();
+/// let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+/// assert_batch_eq!(vectored, b"hello world");
+/// ```
+#[cfg(feature = "test-util")]
+#[macro_export]
+macro_rules! assert_batch_eq {
+    ($batch:expr, $expected:expr $(,)?) => {
+        if let Some(diff) = $crate::__batch_diff(
+            &$batch[..],
+            ::core::convert::AsRef::<[u8]>::as_ref(&$expected),
+        ) {
+            panic!("assertion `batch == expected` failed: {diff}");
+        }
+    };
+}
+
+impl<T, A: Allocator + Clone> Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    /// Whether the payload slices equal the given chunks, item boundaries included.
+    pub fn eq_chunks(&self, chunks: &[&[u8]]) -> bool {
+        self.len() == chunks.len()
+            && self
+                .iter()
+                .zip(chunks)
+                .all(|(slice, chunk)| **slice == **chunk)
+    }
+}
+
+/// Compare the concatenated payload, without allocating.
+impl<T, A: Allocator + Clone> PartialEq<[u8]> for Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn eq(&self, other: &[u8]) -> bool {
+        slices_eq(self, other)
+    }
+}
+
+impl<T, A: Allocator + Clone> PartialEq<&[u8]> for Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn eq(&self, other: &&[u8]) -> bool {
+        slices_eq(self, other)
+    }
+}
+
+/// Compare the concatenated slices, header and trailer included, without allocating.
+impl PartialEq<[u8]> for VectoredFrame<'_> {
+    fn eq(&self, other: &[u8]) -> bool {
+        slices_eq(self, other)
+    }
+}
+
+impl PartialEq<&[u8]> for VectoredFrame<'_> {
+    fn eq(&self, other: &&[u8]) -> bool {
+        slices_eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::IoSlice;
+
+    use crate::{compare::first_divergence, VectoredQueue};
+
+    #[test]
+    fn divergence() {
+        let slices = [IoSlice::new(b"ab"), IoSlice::new(b""), IoSlice::new(b"cd")];
+        assert_eq!(first_divergence(&slices, b"abcd"), None);
+        assert_eq!(first_divergence(&slices, b"abce"), Some(3));
+        assert_eq!(first_divergence(&slices, b"xbcd"), Some(0));
+        assert_eq!(first_divergence(&slices, b"abc"), Some(3));
+        assert_eq!(first_divergence(&slices, b"abcde"), Some(4));
+        assert_eq!(first_divergence(&slices, b"a"), Some(1));
+        assert_eq!(first_divergence(&[], b""), None);
+        assert_eq!(first_divergence(&[], b"a"), Some(0));
+    }
+
+    #[test]
+    fn partial_eq() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(b"ab".to_vec()).unwrap();
+        queue.try_enqueue(b"c".to_vec()).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored == b"abc"[..]);
+        let expected: &[u8] = b"abc";
+        assert!(vectored == expected);
+        assert!(vectored != b"ab"[..]);
+        assert!(vectored != b"abcd"[..]);
+        assert!(vectored.eq_chunks(&[b"ab", b"c"]));
+        assert!(!vectored.eq_chunks(&[b"a", b"bc"]));
+        assert!(!vectored.eq_chunks(&[b"ab"]));
+        let frame = vectored.frame(.., Some(IoSlice::new(b"<")), Some(IoSlice::new(b">")));
+        assert!(frame == b"<abc>"[..]);
+        assert!(frame != expected);
+        drop(frame);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn assert_batch_eq() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(b"ab".to_vec()).unwrap();
+        queue.try_enqueue(b"cd".to_vec()).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        crate::assert_batch_eq!(vectored, b"abcd");
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            crate::assert_batch_eq!(vectored, b"abd")
+        }))
+        .unwrap_err();
+        assert_eq!(
+            panic.downcast_ref::<String>().unwrap(),
+            "assertion `batch == expected` failed: batch length 4, expected length 3, \
+             first divergent offset 2 (0x63 != 0x64)"
+        );
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+}
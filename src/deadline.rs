@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+/// Deadline of a blocking operation.
+///
+/// It allows plugging custom timer backends, e.g. simulated time for testing.
+pub trait Deadline {
+    fn has_elapsed(&self) -> bool;
+    fn remaining(&self) -> Duration;
+
+    /// Same as [`has_elapsed`](Self::has_elapsed), with the current instant given by a
+    /// [`Clock`](crate::Clock).
+    fn has_elapsed_at(&self, now: Instant) -> bool {
+        let _ = now;
+        self.has_elapsed()
+    }
+
+    /// Same as [`remaining`](Self::remaining), with the current instant given by a
+    /// [`Clock`](crate::Clock).
+    fn remaining_at(&self, now: Instant) -> Duration {
+        let _ = now;
+        self.remaining()
+    }
+}
+
+impl Deadline for Instant {
+    fn has_elapsed(&self) -> bool {
+        *self <= Instant::now()
+    }
+
+    fn remaining(&self) -> Duration {
+        self.saturating_duration_since(Instant::now())
+    }
+
+    fn has_elapsed_at(&self, now: Instant) -> bool {
+        *self <= now
+    }
+
+    fn remaining_at(&self, now: Instant) -> Duration {
+        self.saturating_duration_since(now)
+    }
+}
+
+/// Deadline from now; like `*_timeout` methods, it is restarted each time waiting is
+/// interrupted by a notification.
+impl Deadline for Duration {
+    fn has_elapsed(&self) -> bool {
+        self.is_zero()
+    }
+
+    fn remaining(&self) -> Duration {
+        *self
+    }
+}
+
+/// Deadline which never elapses.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Never;
+
+impl Deadline for Never {
+    fn has_elapsed(&self) -> bool {
+        false
+    }
+
+    fn remaining(&self) -> Duration {
+        Duration::MAX
+    }
+}
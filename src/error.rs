@@ -14,8 +14,10 @@ impl<T> TryEnqueueError<T> {
     }
 }
 
+/// Rejection of an enqueuing, because the queue is closed, or because unbounded enqueuing
+/// reached the [maximum capacity](crate::VectoredQueue::set_max_capacity).
 #[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
-#[error("queue is closed")]
+#[error("queue is closed or at its maximum capacity")]
 pub struct EnqueueError<T>(pub T);
 
 impl<T> EnqueueError<T> {
@@ -24,10 +26,68 @@ impl<T> EnqueueError<T> {
     }
 }
 
+/// Corruption of a snapshot restored by
+/// [`VectoredQueue::restore_from`](crate::VectoredQueue::restore_from), wrapped in an
+/// [`io::ErrorKind::InvalidData`](std::io::ErrorKind::InvalidData) error; truncated snapshots
+/// return [`io::ErrorKind::UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) instead.
+#[cfg(feature = "persist")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum RestoreError {
+    #[error("not a queue snapshot")]
+    Magic,
+    #[error("unsupported snapshot version {0}")]
+    Version(u16),
+    #[error("snapshot item of {0} bytes is too large")]
+    ItemSize(u64),
+    #[error("snapshot checksum mismatch")]
+    Checksum,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
 pub enum DequeueError {
     #[error("queue is closed")]
     Closed,
     #[error("queue is dequeued in another thread")]
     Conflict,
+    /// A producer panicked while inserting an item, see
+    /// [`VectoredQueue::clear_poison`](crate::VectoredQueue::clear_poison).
+    #[error("queue is poisoned by a panicking producer")]
+    Poisoned,
+}
+
+/// Error of [`VectoredQueue::transfer_to`](crate::VectoredQueue::transfer_to), with the
+/// number of items transferred before it.
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum TransferError<T> {
+    /// The rest of the current batch didn't fit in the destination, following items are left
+    /// in the source.
+    #[error("destination queue is full")]
+    Full { transferred: usize, items: Vec<T> },
+    /// The rest of the current batch has been rejected by the destination, following items
+    /// are left in the source.
+    #[error("destination queue is closed")]
+    Closed { transferred: usize, items: Vec<T> },
+    #[error("source queue is dequeued in another thread")]
+    Conflict { transferred: usize },
+    #[error("source queue is poisoned by a panicking producer")]
+    Poisoned { transferred: usize },
+}
+
+impl<T> TransferError<T> {
+    pub fn transferred(&self) -> usize {
+        match self {
+            Self::Full { transferred, .. }
+            | Self::Closed { transferred, .. }
+            | Self::Conflict { transferred }
+            | Self::Poisoned { transferred } => *transferred,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum BuildError {
+    #[error("capacity is too large")]
+    CapacityOverflow,
+    #[error("maximum bytes must not be zero")]
+    ZeroMaxBytes,
 }
@@ -0,0 +1,150 @@
+use std::{
+    fs::File,
+    io::{self, IoSlice, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+use crate::{alloc::Allocator, vectored::Vectored};
+
+/// Synchronization performed by [`Vectored::write_and_sync`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum SyncMode {
+    /// [`File::sync_data`], which skips metadata not needed to read the data back.
+    #[default]
+    Data,
+    /// [`File::sync_all`].
+    All,
+}
+
+fn write_vectored_retry(mut file: &File, slices: &[IoSlice<'_>]) -> io::Result<usize> {
+    loop {
+        match file.write_vectored(slices) {
+            Ok(0) if slices.iter().any(|slice| !slice.is_empty()) => {
+                return Err(io::ErrorKind::WriteZero.into())
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+fn write_all_vectored(file: &File, slices: &[IoSlice<'_>], total_size: usize) -> io::Result<()> {
+    let mut written = write_vectored_retry(file, slices)?;
+    if written == total_size {
+        return Ok(());
+    }
+    // the remaining slices are copied so the batch ones are left untouched
+    let mut remaining = slices.to_vec();
+    let mut slices = &mut remaining[..];
+    IoSlice::advance_slices(&mut slices, written);
+    while written < total_size {
+        let n = write_vectored_retry(file, slices)?;
+        IoSlice::advance_slices(&mut slices, n);
+        written += n;
+    }
+    Ok(())
+}
+
+impl<T, A: Allocator + Clone> Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    /// Append the [full slices](Self::full_slices) to `file` at its current position, then
+    /// synchronize it to disk according to `mode`, returning the byte range written.
+    ///
+    /// Partial writes are completed before synchronizing, so items of the batch can be
+    /// acknowledged once it returns, e.g. for group commit of a write-ahead log. The range is
+    /// computed from the position after writing, so it is right for files opened in append
+    /// mode.
+    pub fn write_and_sync(&self, file: &File, mode: SyncMode) -> io::Result<Range<u64>> {
+        let total_size = self.full_size();
+        write_all_vectored(file, self.full_slices(), total_size)?;
+        let end = (&*file).stream_position()?;
+        sync(file, mode)?;
+        Ok(end - total_size as u64..end)
+    }
+
+    /// Same as [`write_and_sync`](Self::write_and_sync), but write at `offset`, which is
+    /// ignored by files opened in append mode.
+    pub fn write_at_and_sync(
+        &self,
+        file: &File,
+        offset: u64,
+        mode: SyncMode,
+    ) -> io::Result<Range<u64>> {
+        (&*file).seek(SeekFrom::Start(offset))?;
+        self.write_and_sync(file, mode)
+    }
+}
+
+fn sync(file: &File, mode: SyncMode) -> io::Result<()> {
+    match mode {
+        SyncMode::Data => file.sync_data(),
+        SyncMode::All => file.sync_all(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, fs::OpenOptions, io::IoSlice, path::PathBuf, process};
+
+    use crate::{fs::SyncMode, VectoredQueue};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("vectored-queue-{}-{name}", process::id()))
+    }
+
+    #[test]
+    fn write_and_sync() {
+        let path = temp_path("write_and_sync");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(b"hello ".to_vec()).unwrap();
+        queue.try_enqueue(b"world".to_vec()).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        *vectored.parts_mut().2 = IoSlice::new(b"\n");
+        let range = vectored.write_and_sync(&file, SyncMode::Data).unwrap();
+        assert_eq!(range, 0..12);
+        // the batch slices are left untouched
+        assert_eq!(&*vectored[0], b"hello ");
+        drop(vectored);
+        queue.try_enqueue(b"again".to_vec()).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(
+            vectored.write_and_sync(&file, SyncMode::All).unwrap(),
+            12..17
+        );
+        let range = vectored
+            .write_at_and_sync(&file, 6, SyncMode::Data)
+            .unwrap();
+        assert_eq!(range, 6..11);
+        drop(vectored);
+        assert_eq!(fs::read(&path).unwrap(), b"hello again\nagain");
+        fs::remove_file(path).unwrap();
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn append_mode() {
+        let path = temp_path("append_mode");
+        fs::write(&path, b"log:").unwrap();
+        let file = OpenOptions::new().append(true).open(&path).unwrap();
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(b"entry".to_vec()).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(
+            vectored.write_and_sync(&file, SyncMode::Data).unwrap(),
+            4..9
+        );
+        drop(vectored);
+        assert_eq!(fs::read(&path).unwrap(), b"log:entry");
+        fs::remove_file(path).unwrap();
+        queue.debug_assert_invariants();
+    }
+}
@@ -1,18 +1,146 @@
+mod alloc;
 #[cfg(feature = "async")]
 mod r#async;
 mod buffer;
+mod builder;
+mod clock;
+mod compare;
+mod deadline;
 pub mod error;
+mod fs;
+#[cfg(any(feature = "sync", feature = "async"))]
+mod notify;
+#[cfg(feature = "persist")]
+mod persist;
+#[cfg(feature = "bytemuck")]
+mod pod;
+mod primitives;
 mod queue;
+#[cfg(all(unix, feature = "os-notify"))]
+mod readiness;
+mod slice;
+#[cfg(feature = "socket2")]
+mod socket;
+mod stats;
 #[cfg(feature = "sync")]
 mod sync;
+#[cfg(all(unix, feature = "unix"))]
+mod unix;
 mod vectored;
+mod write;
 
+#[cfg(feature = "allocator-api2")]
+pub use allocator_api2::alloc::{AllocError, Allocator, Global};
+#[cfg(feature = "test-util")]
+#[doc(hidden)]
+pub use compare::batch_diff as __batch_diff;
+#[cfg(any(feature = "sync", feature = "async"))]
+pub use notify::NotifyThreshold;
+#[cfg(feature = "persist")]
+pub use persist::PersistSummary;
+#[cfg(feature = "bytemuck")]
+pub use pod::PodItem;
 #[cfg(feature = "async")]
-pub use r#async::AsyncVectoredQueue;
+pub use r#async::{
+    async_channel, AsyncReceiver, AsyncSender, AsyncVectoredQueue, BatchStream, DequeueFuture,
+    EnqueueFuture, VectoredStream,
+};
+#[cfg(feature = "metrics")]
+pub use stats::QueueStats;
 #[cfg(feature = "sync")]
-pub use sync::SyncVectoredQueue;
+pub use sync::{sync_channel, SpinDecision, SyncReceiver, SyncSender, SyncVectoredQueue};
+#[cfg(all(unix, feature = "unix"))]
+pub use unix::ControlMessage;
 
 pub use crate::{
-    queue::{TryDequeueResult, VectoredQueue},
-    vectored::{Vectored, VectoredFrame},
+    builder::{Growth, VectoredQueueBuilder},
+    clock::{Clock, MockClock, RealClock},
+    deadline::{Deadline, Never},
+    fs::SyncMode,
+    queue::{
+        Consumer, ConsumerToken, DrainIter, HighPriorityGuard, Producer, TryDequeueResult,
+        VectoredQueue,
+    },
+    slice::StaticSlice,
+    vectored::{
+        DequeueInto, DequeueTicket, DequeueTxn, InterleavedVectored, TagSummary, Vectored,
+        VectoredChunks, VectoredFrame, VectoredSlice,
+    },
 };
+
+#[cfg(test)]
+mod test {
+    use crate::{error::DequeueError, VectoredQueue};
+
+    const ITEMS: u8 = 5;
+
+    // overflowing items make the drain span several batches
+    fn enqueue_and_close(queue: &VectoredQueue<[u8; 1]>) {
+        for i in 0..ITEMS {
+            queue.enqueue_unbounded([i]).unwrap();
+        }
+        queue.close();
+    }
+
+    #[test]
+    fn close_drain() {
+        let queue = VectoredQueue::with_capacity(2);
+        enqueue_and_close(&queue);
+        let mut dequeued = Vec::new();
+        loop {
+            match queue.try_dequeue_vectored() {
+                Ok(res) => dequeued.extend(res.vectored().unwrap().into_vec()),
+                Err(err) => {
+                    assert_eq!(err, DequeueError::Closed);
+                    break;
+                }
+            }
+        }
+        assert_eq!(dequeued, (0..ITEMS).map(|i| [i]).collect::<Vec<_>>());
+        queue.debug_assert_invariants();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn sync_close_drain() {
+        let queue = crate::SyncVectoredQueue::with_capacity(2);
+        for i in 0..ITEMS {
+            queue.enqueue_unbounded([i]).unwrap();
+        }
+        queue.close();
+        let mut dequeued = Vec::new();
+        loop {
+            match queue.dequeue_vectored() {
+                Ok(vectored) => dequeued.extend(vectored.into_vec()),
+                Err(err) => {
+                    assert_eq!(err, DequeueError::Closed);
+                    break;
+                }
+            }
+        }
+        assert_eq!(dequeued, (0..ITEMS).map(|i| [i]).collect::<Vec<_>>());
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_close_drain() {
+        let queue = crate::AsyncVectoredQueue::with_capacity(2);
+        for i in 0..ITEMS {
+            queue.enqueue_unbounded([i]).unwrap();
+        }
+        queue.close();
+        let mut dequeued = Vec::new();
+        loop {
+            match futures::executor::block_on(queue.dequeue_vectored()) {
+                Ok(vectored) => dequeued.extend(vectored.into_vec()),
+                Err(err) => {
+                    assert_eq!(err, DequeueError::Closed);
+                    break;
+                }
+            }
+        }
+        assert_eq!(dequeued, (0..ITEMS).map(|i| [i]).collect::<Vec<_>>());
+        queue.as_queue().debug_assert_invariants();
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use crate::{
+    alloc::Allocator,
+    primitives::{AtomicU64, AtomicUsize, Ordering},
+    queue::VectoredQueue,
+};
+
+/// Pending amount from which producers wake the consumer of a blocking queue, see
+/// [`SyncVectoredQueue::set_notify_threshold`](crate::SyncVectoredQueue::set_notify_threshold).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NotifyThreshold {
+    /// Threshold on [`pending_bytes`](VectoredQueue::pending_bytes).
+    Bytes(usize),
+    /// Threshold on [`len`](VectoredQueue::len).
+    Items(usize),
+}
+
+// Zero thresholds mean that producers always notify.
+#[derive(Default)]
+pub(crate) struct Threshold {
+    bytes: AtomicUsize,
+    items: AtomicUsize,
+    max_delay_ns: AtomicU64,
+}
+
+impl Threshold {
+    pub(crate) fn set(&self, threshold: NotifyThreshold, max_delay: Duration) {
+        assert!(!max_delay.is_zero(), "max delay must not be zero");
+        let (bytes, items) = match threshold {
+            NotifyThreshold::Bytes(bytes) => (bytes, 0),
+            NotifyThreshold::Items(items) => (0, items),
+        };
+        let max_delay_ns = u64::try_from(max_delay.as_nanos()).unwrap_or(u64::MAX);
+        self.max_delay_ns.store(max_delay_ns, Ordering::Relaxed);
+        self.bytes.store(bytes, Ordering::Relaxed);
+        self.items.store(items, Ordering::Relaxed);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.bytes.store(0, Ordering::Relaxed);
+        self.items.store(0, Ordering::Relaxed);
+    }
+
+    // Maximum time a consumer may wait before checking pending items below the threshold
+    pub(crate) fn max_delay(&self) -> Option<Duration> {
+        let (bytes, items) = (
+            self.bytes.load(Ordering::Relaxed),
+            self.items.load(Ordering::Relaxed),
+        );
+        (bytes > 0 || items > 0)
+            .then(|| Duration::from_nanos(self.max_delay_ns.load(Ordering::Relaxed)))
+    }
+
+    // Whether producers should notify the consumer after enqueuing
+    pub(crate) fn is_reached<T, A: Allocator + Clone>(&self, queue: &VectoredQueue<T, A>) -> bool {
+        let (bytes, items) = (
+            self.bytes.load(Ordering::Relaxed),
+            self.items.load(Ordering::Relaxed),
+        );
+        if bytes == 0 && items == 0 {
+            return true;
+        }
+        let max_delay = Duration::from_nanos(self.max_delay_ns.load(Ordering::Relaxed));
+        (bytes > 0 && queue.pending_bytes() >= bytes)
+            || (items > 0 && queue.len() >= items)
+            || queue.pending_age() >= max_delay
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        notify::{NotifyThreshold, Threshold},
+        MockClock, VectoredQueue,
+    };
+
+    #[test]
+    fn is_reached() {
+        let clock = MockClock::new();
+        let queue = VectoredQueue::builder()
+            .capacity(8)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let threshold = Threshold::default();
+        assert!(threshold.is_reached(&queue));
+        assert_eq!(threshold.max_delay(), None);
+        threshold.set(NotifyThreshold::Bytes(4), Duration::from_secs(1));
+        assert_eq!(threshold.max_delay(), Some(Duration::from_secs(1)));
+        queue.try_enqueue(vec![0; 3]).unwrap();
+        assert!(!threshold.is_reached(&queue));
+        queue.try_enqueue(vec![0]).unwrap();
+        assert!(threshold.is_reached(&queue));
+        threshold.set(NotifyThreshold::Items(3), Duration::from_secs(1));
+        assert!(!threshold.is_reached(&queue));
+        // the oldest pending item has waited the max delay
+        clock.advance(Duration::from_secs(1));
+        assert!(threshold.is_reached(&queue));
+        drop(queue.try_dequeue_vectored().unwrap().vectored().unwrap());
+        queue.try_enqueue(vec![0]).unwrap();
+        assert!(!threshold.is_reached(&queue));
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.try_enqueue(vec![0]).unwrap();
+        assert!(threshold.is_reached(&queue));
+        threshold.clear();
+        assert_eq!(threshold.max_delay(), None);
+        queue.debug_assert_invariants();
+    }
+}
@@ -0,0 +1,229 @@
+//! Snapshot format, all integers being little-endian:
+//! - magic `VQSN`, then version `u16` and reserved flags `u16`;
+//! - item count `u64`, then for each item, its length `u64` followed by its bytes;
+//! - FNV-1a 64 checksum `u64` of everything after the header.
+
+use std::io::{self, Read, Write};
+
+use crate::{alloc::Allocator, error::RestoreError, queue::VectoredQueue};
+
+const MAGIC: [u8; 4] = *b"VQSN";
+const VERSION: u16 = 1;
+
+/// Numbers of items and payload bytes written by [`VectoredQueue::persist_to`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct PersistSummary {
+    pub items: usize,
+    pub bytes: u64,
+}
+
+struct Checksum(u64);
+
+impl Checksum {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn invalid(err: RestoreError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn read_u64(r: &mut impl Read, checksum: &mut Checksum) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    checksum.update(&buf);
+    Ok(u64::from_le_bytes(buf))
+}
+
+impl<T, A: Allocator + Clone> VectoredQueue<T, A>
+where
+    T: AsRef<[u8]>,
+{
+    /// Write a snapshot of the pending items, in order, overflowing ones included, to be
+    /// restored with [`restore_from`](Self::restore_from).
+    ///
+    /// Items are not dequeued, and the queue cannot be dequeued while writing. A batch being
+    /// dequeued conflicts with the snapshot; to include it, e.g. because it was not sent, it
+    /// must be put back first, by dropping an uncommitted [`DequeueTxn`](crate::DequeueTxn).
+    /// Conflicting and poisoned queues return an [`io::ErrorKind::Other`] error wrapping the
+    /// [`DequeueError`](crate::error::DequeueError).
+    pub fn persist_to(&self, w: &mut impl Write) -> io::Result<PersistSummary> {
+        self.with_contents(|items| {
+            w.write_all(&MAGIC)?;
+            w.write_all(&VERSION.to_le_bytes())?;
+            w.write_all(&0u16.to_le_bytes())?;
+            let mut checksum = Checksum::new();
+            let mut write = |bytes: &[u8], checksum: &mut Checksum| {
+                checksum.update(bytes);
+                w.write_all(bytes)
+            };
+            write(&(items.len() as u64).to_le_bytes(), &mut checksum)?;
+            let mut bytes = 0;
+            for item in items {
+                write(&(item.len() as u64).to_le_bytes(), &mut checksum)?;
+                write(item, &mut checksum)?;
+                bytes += item.len() as u64;
+            }
+            w.write_all(&checksum.0.to_le_bytes())?;
+            Ok(PersistSummary {
+                items: items.len(),
+                bytes,
+            })
+        })
+        .map_err(io::Error::other)?
+    }
+}
+
+impl<T> VectoredQueue<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Restore a queue from a snapshot written by [`persist_to`](Self::persist_to), with its
+    /// items pending in order.
+    ///
+    /// The queue capacity is the number of items; items exceeding the maximum total size are
+    /// kept in the overflow, as with [`enqueue_unbounded`](Self::enqueue_unbounded).
+    /// Corrupted snapshots return an [`io::ErrorKind::InvalidData`] error wrapping a
+    /// [`RestoreError`], truncated ones an [`io::ErrorKind::UnexpectedEof`] error.
+    pub fn restore_from(r: &mut impl Read) -> io::Result<Self>
+    where
+        T: From<Vec<u8>>,
+    {
+        Self::restore_with(r, T::from)
+    }
+
+    /// Same as [`restore_from`](Self::restore_from), building the items with `f`.
+    pub fn restore_with(r: &mut impl Read, mut f: impl FnMut(Vec<u8>) -> T) -> io::Result<Self> {
+        let mut header = [0; 8];
+        r.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(invalid(RestoreError::Magic));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != VERSION {
+            return Err(invalid(RestoreError::Version(version)));
+        }
+        let mut checksum = Checksum::new();
+        let count = read_u64(r, &mut checksum)?;
+        // the count is not trusted to preallocate, items are read until the input ends
+        let mut items = Vec::new();
+        for _ in 0..count {
+            let len = read_u64(r, &mut checksum)?;
+            if len > isize::MAX as u64 {
+                return Err(invalid(RestoreError::ItemSize(len)));
+            }
+            let mut bytes = Vec::new();
+            r.take(len).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            checksum.update(&bytes);
+            items.push(bytes);
+        }
+        let mut expected = [0; 8];
+        r.read_exact(&mut expected)?;
+        if u64::from_le_bytes(expected) != checksum.0 {
+            return Err(invalid(RestoreError::Checksum));
+        }
+        let queue = Self::with_capacity(items.len());
+        for bytes in items {
+            if queue.enqueue_unbounded(f(bytes)).is_err() {
+                unreachable!("a new queue is not closed");
+            }
+        }
+        Ok(queue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Cursor};
+
+    use crate::{error::RestoreError, persist::PersistSummary, VectoredQueue};
+
+    fn snapshot(queue: &VectoredQueue<Vec<u8>>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        queue.persist_to(&mut buf).unwrap();
+        buf
+    }
+
+    fn restore_err(buf: &[u8]) -> io::Error {
+        match VectoredQueue::<Vec<u8>>::restore_from(&mut Cursor::new(buf)) {
+            Ok(_) => panic!("restored"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let queue = VectoredQueue::with_capacity(2);
+        queue.try_enqueue(b"ab".to_vec()).unwrap();
+        queue.try_enqueue(b"".to_vec()).unwrap();
+        queue.enqueue_unbounded(b"overflow".to_vec()).unwrap();
+        let vectored = queue.try_dequeue_n(1).unwrap().vectored().unwrap();
+        assert!(queue.persist_to(&mut Vec::new()).is_err());
+        drop(vectored);
+        queue.try_enqueue(b"c".to_vec()).unwrap();
+        let mut buf = Vec::new();
+        let summary = queue.persist_to(&mut buf).unwrap();
+        assert_eq!(summary, PersistSummary { items: 3, bytes: 9 });
+        // the snapshot leaves the items in the queue
+        assert_eq!(snapshot(&queue), buf);
+        let restored = VectoredQueue::<Vec<u8>>::restore_from(&mut Cursor::new(&buf)).unwrap();
+        assert!(restored.content_eq(&queue).unwrap());
+        let empty = snapshot(&VectoredQueue::new());
+        let restored = VectoredQueue::<Vec<u8>>::restore_from(&mut Cursor::new(&empty)).unwrap();
+        assert!(restored.is_empty());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn corrupted() {
+        let queue = VectoredQueue::with_capacity(2);
+        queue.try_enqueue(b"abc".to_vec()).unwrap();
+        queue.try_enqueue(b"de".to_vec()).unwrap();
+        let buf = snapshot(&queue);
+        // every truncation is detected, whatever the field it cuts
+        for len in 0..buf.len() {
+            assert_eq!(
+                restore_err(&buf[..len]).kind(),
+                io::ErrorKind::UnexpectedEof,
+                "{len}"
+            );
+        }
+        let check = |buf: &[u8], expected| {
+            let err = restore_err(buf);
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                *err.get_ref()
+                    .unwrap()
+                    .downcast_ref::<RestoreError>()
+                    .unwrap(),
+                expected
+            );
+        };
+        let mut corrupted = buf.clone();
+        corrupted[0] = b'X';
+        check(&corrupted, RestoreError::Magic);
+        let mut corrupted = buf.clone();
+        corrupted[4] = 2;
+        check(&corrupted, RestoreError::Version(2));
+        let mut corrupted = buf.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        check(&corrupted, RestoreError::Checksum);
+        let mut corrupted = buf.clone();
+        corrupted[16 + 8] ^= 1;
+        check(&corrupted, RestoreError::Checksum);
+        let mut corrupted = buf.clone();
+        corrupted[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        check(&corrupted, RestoreError::ItemSize(u64::MAX));
+        queue.debug_assert_invariants();
+    }
+}
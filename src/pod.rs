@@ -0,0 +1,134 @@
+use std::ops::{Deref, DerefMut};
+
+use bytemuck::Pod;
+
+#[cfg(feature = "async")]
+use crate::r#async::{AsyncVectoredQueue, EnqueueFuture};
+use crate::{alloc::Allocator, error::TryEnqueueError, queue::VectoredQueue};
+#[cfg(feature = "sync")]
+use crate::{error::EnqueueError, sync::SyncVectoredQueue};
+
+/// Plain-old-data value enqueued as its own bytes, without conversion.
+///
+/// The value is stored inline in the queue slots, which never move while a batch is
+/// dequeued, as buffers are only resized by the consumer when swapping them.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct PodItem<P>(pub P);
+
+impl<P: Pod> AsRef<[u8]> for PodItem<P> {
+    fn as_ref(&self) -> &[u8] {
+        bytemuck::bytes_of(&self.0)
+    }
+}
+
+impl<P> Deref for PodItem<P> {
+    type Target = P;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<P> DerefMut for PodItem<P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<P> From<P> for PodItem<P> {
+    fn from(value: P) -> Self {
+        Self(value)
+    }
+}
+
+impl<P: Pod, A: Allocator + Clone> VectoredQueue<PodItem<P>, A> {
+    /// Shortcut for [`try_enqueue`](Self::try_enqueue) of a [`PodItem`].
+    pub fn try_enqueue_pod(&self, value: P) -> Result<(), TryEnqueueError<P>> {
+        self.try_enqueue(PodItem(value)).map_err(|err| match err {
+            TryEnqueueError::Full(item) => TryEnqueueError::Full(item.0),
+            TryEnqueueError::Closed(item) => TryEnqueueError::Closed(item.0),
+        })
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<P: Pod, A: Allocator + Clone> SyncVectoredQueue<PodItem<P>, A> {
+    /// Shortcut for [`enqueue`](Self::enqueue) of a [`PodItem`].
+    pub fn enqueue_pod(&self, value: P) -> Result<(), EnqueueError<P>> {
+        self.enqueue(PodItem(value))
+            .map_err(|EnqueueError(item)| EnqueueError(item.0))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<P: Pod, A: Allocator + Clone> AsyncVectoredQueue<PodItem<P>, A> {
+    /// Shortcut for [`enqueue`](Self::enqueue) of a [`PodItem`].
+    pub fn enqueue_pod(&self, value: P) -> EnqueueFuture<'_, PodItem<P>, A> {
+        self.enqueue(PodItem(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytemuck::{Pod, Zeroable};
+
+    use crate::{pod::PodItem, VectoredQueue};
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    #[repr(C)]
+    struct Record {
+        id: u32,
+        value: f32,
+    }
+
+    unsafe impl Zeroable for Record {}
+    unsafe impl Pod for Record {}
+
+    fn wire(record: Record) -> Vec<u8> {
+        let mut bytes = record.id.to_ne_bytes().to_vec();
+        bytes.extend(record.value.to_ne_bytes());
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let records = [Record { id: 0, value: 0.5 }, Record { id: 1, value: -1.0 }];
+        let queue = VectoredQueue::<PodItem<Record>>::with_capacity(records.len());
+        for record in records {
+            queue.try_enqueue_pod(record).unwrap();
+        }
+        assert!(queue.try_enqueue_pod(records[0]).is_err());
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        for (slice, record) in vectored.iter().zip(records) {
+            assert_eq!(&**slice, wire(record));
+            assert_eq!(*bytemuck::from_bytes::<Record>(slice), record);
+        }
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn sync_round_trip() {
+        let record = Record { id: 42, value: 1.5 };
+        let queue = crate::SyncVectoredQueue::<PodItem<Record>>::with_capacity(1);
+        queue.enqueue_pod(record).unwrap();
+        assert_eq!(&*queue.dequeue_vectored().unwrap()[0], wire(record));
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_round_trip() {
+        use futures::executor::block_on;
+        let record = Record { id: 42, value: 1.5 };
+        let queue = crate::AsyncVectoredQueue::<PodItem<Record>>::with_capacity(1);
+        block_on(queue.enqueue_pod(record)).unwrap();
+        assert_eq!(
+            &*block_on(queue.dequeue_vectored()).unwrap()[0],
+            wire(record)
+        );
+        queue.as_queue().debug_assert_invariants();
+    }
+}
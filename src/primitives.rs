@@ -0,0 +1,68 @@
+#[cfg(all(not(loom), not(feature = "parking_lot"), feature = "sync"))]
+pub(crate) use std::sync::Condvar;
+#[cfg(all(not(loom), not(feature = "parking_lot")))]
+pub(crate) use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "sync")]
+use std::time::Duration;
+#[cfg(not(loom))]
+pub(crate) use std::{
+    hint,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    thread::yield_now,
+};
+
+#[cfg(all(loom, feature = "sync"))]
+pub(crate) use loom::sync::Condvar;
+#[cfg(loom)]
+pub(crate) use loom::{
+    hint,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+    thread::yield_now,
+};
+#[cfg(all(not(loom), feature = "parking_lot", feature = "sync"))]
+pub(crate) use parking_lot::Condvar;
+#[cfg(all(not(loom), feature = "parking_lot"))]
+pub(crate) use parking_lot::{Mutex, MutexGuard};
+
+// loom explores every iteration, so spinning must be kept short
+#[cfg(loom)]
+pub(crate) const SPIN_LIMIT: u32 = 2;
+#[cfg(not(loom))]
+pub(crate) const SPIN_LIMIT: u32 = 100;
+
+// std locks are poisoned by a panicking holder, which is propagated as a panic; parking_lot
+// ones are not, and their condition variable waits borrow the guard instead of moving it.
+
+#[cfg(any(loom, not(feature = "parking_lot")))]
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap()
+}
+
+#[cfg(all(not(loom), feature = "parking_lot"))]
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock()
+}
+
+// Return the guard, and whether the wait timed out
+#[cfg(all(any(loom, not(feature = "parking_lot")), feature = "sync"))]
+pub(crate) fn wait_timeout<'a, T>(
+    condvar: &Condvar,
+    guard: MutexGuard<'a, T>,
+    timeout: Duration,
+) -> (MutexGuard<'a, T>, bool) {
+    let (guard, res) = condvar.wait_timeout(guard, timeout).unwrap();
+    (guard, res.timed_out())
+}
+
+#[cfg(all(not(loom), feature = "parking_lot", feature = "sync"))]
+pub(crate) fn wait_timeout<'a, T>(
+    condvar: &Condvar,
+    mut guard: MutexGuard<'a, T>,
+    timeout: Duration,
+) -> (MutexGuard<'a, T>, bool) {
+    let res = condvar.wait_for(&mut guard, timeout);
+    (guard, res.timed_out())
+}
@@ -1,26 +1,97 @@
 use std::{
-    cmp, fmt, mem,
-    ops::DerefMut,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Mutex,
-    },
+    cell::Cell,
+    cmp, fmt,
+    io::IoSlice,
+    iter::{self, Peekable},
+    marker::PhantomData,
+    mem, ptr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "metrics")]
+use crate::stats::QueueStats;
 use crate::{
-    buffer::Buffer,
-    error::{DequeueError, EnqueueError, TryEnqueueError},
-    vectored::Vectored,
+    alloc::{Allocator, Global, Vec as AllocVec},
+    buffer::{self, Buffer, Framer, HIGH_PRIORITY_SLOTS, MAX_TOTAL_SIZE},
+    builder::{Config, Growth, VectoredQueueBuilder},
+    error::{DequeueError, EnqueueError, TransferError, TryEnqueueError},
+    primitives::{
+        hint, lock, yield_now, AtomicU32, AtomicU64, AtomicUsize, Mutex, Ordering, SPIN_LIMIT,
+    },
+    stats::Stats,
+    vectored::{DequeueInto, DequeueTicket, DequeueTxn, Vectored},
 };
 
-const CLOSED_FLAG: usize = (usize::MAX >> 1) + 1;
+const ENQUEUE_CLOSED_FLAG: usize = (usize::MAX >> 1) + 1;
+const DEQUEUE_CLOSED_FLAG: usize = ENQUEUE_CLOSED_FLAG >> 1;
+const CLOSED_FLAGS: usize = ENQUEUE_CLOSED_FLAG | DEQUEUE_CLOSED_FLAG;
+const CAPACITY_HISTORY_LEN: usize = 16;
+const NOT_POISONED: usize = usize::MAX;
+
+// Decode `buffer_remain` into the enqueuing buffer index, its remaining slots and whether
+// either side is closed; the flags must never be left in the remaining slots.
+fn decode(buffer_remain: usize) -> (usize, usize, bool) {
+    (
+        buffer_remain & 1,
+        (buffer_remain & !CLOSED_FLAGS) >> 1,
+        buffer_remain & CLOSED_FLAGS != 0,
+    )
+}
+
+thread_local! {
+    // queue marked as high priority by the current thread
+    static HIGH_PRIORITY: Cell<*const ()> = const { Cell::new(ptr::null()) };
+}
 
-pub struct VectoredQueue<T> {
+pub struct VectoredQueue<T, A: Allocator + Clone = Global> {
     buffer_remain: AtomicUsize,
+    // buffer index and slot, encoded like `buffer_remain`, of an insertion which panicked or
+    // of the first slot left unfilled by a reservation, and the number of unfilled slots,
+    // written right after
+    poison: AtomicUsize,
+    poison_len: AtomicUsize,
     pending_dequeue: AtomicUsize,
     capacity: AtomicUsize,
-    buffers: [Buffer<T>; 2],
-    tmp: Mutex<Vec<T>>,
+    // bound of the pending bytes, `usize::MAX` if unbounded
+    max_bytes: AtomicUsize,
+    max_capacity: AtomicUsize,
+    // waiting for insertions in progress when dequeuing
+    spin_limit: AtomicU32,
+    yield_limit: AtomicU32,
+    buffers: [Buffer<T, A>; 2],
+    tmp: Mutex<AllocVec<T, A>>,
+    // mirrors of `tmp` length and bytes size, to read them without locking
+    tmp_len: AtomicUsize,
+    tmp_size: AtomicUsize,
+    // batch-level timing: arrival of the first item of each buffer, in nanoseconds since
+    // `epoch`, and moving average of the batch ages at dequeuing
+    epoch: Instant,
+    first_enqueue: [AtomicU64; 2],
+    latency: AtomicU64,
+    pending_dequeue_count: AtomicU64,
+    capacity_history: Mutex<CapacityHistory>,
+    stats: Stats,
+    pub(crate) config: Config,
+}
+
+// Ring of the last capacity increases, stored inline so recording never allocates.
+#[derive(Default)]
+struct CapacityHistory {
+    ring: [usize; CAPACITY_HISTORY_LEN],
+    recorded: usize,
+}
+
+impl CapacityHistory {
+    fn push(&mut self, capacity: usize) {
+        self.ring[self.recorded % CAPACITY_HISTORY_LEN] = capacity;
+        self.recorded += 1;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let len = self.recorded.min(CAPACITY_HISTORY_LEN);
+        (self.recorded - len..self.recorded).map(|i| self.ring[i % CAPACITY_HISTORY_LEN])
+    }
 }
 
 impl<T> Default for VectoredQueue<T> {
@@ -29,25 +100,181 @@ impl<T> Default for VectoredQueue<T> {
     }
 }
 
+/// Queue pre-filled with the items, in order, dequeued as the first batch unless they exceed
+/// the maximum total size of a batch.
+impl<T> From<Vec<T>> for VectoredQueue<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn from(items: Vec<T>) -> Self {
+        items.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<T> for VectoredQueue<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        // the capacity must be known before inserting, so other iterators are collected first
+        let (min, max) = iter.size_hint();
+        if max != Some(min) {
+            return iter.collect::<Vec<_>>().into_iter().collect();
+        }
+        let queue = Self::with_capacity(min);
+        for bytes in iter {
+            // a batch overflowing the total size, or a wrong hint, end up in the overflow
+            if let Err(err) = queue.enqueue_unbounded(bytes) {
+                unreachable!("queue is not closed: {err}");
+            }
+        }
+        queue
+    }
+}
+
 impl<T> VectoredQueue<T> {
     pub fn new() -> Self {
         Self::with_capacity(0)
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_alloc(capacity, Global)
+    }
+
+    /// Queue also bounding its pending bytes, i.e. the same as the
+    /// [builder](VectoredQueueBuilder::max_bytes) with the given capacity and maximum bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_bytes` is zero.
+    pub fn with_byte_capacity(capacity: usize, max_bytes: usize) -> Self {
+        let queue = Self::with_capacity(capacity);
+        queue.set_byte_capacity(max_bytes);
+        queue
+    }
+
+    /// Queue prefixing each item with the frame header returned by `framer` for its bytes,
+    /// e.g. a length prefix, i.e. the same as the [builder](VectoredQueueBuilder::framer) with
+    /// the given capacity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vectored_queue::VectoredQueue;
+    ///
+    /// let queue = VectoredQueue::with_framer(4, |bytes: &[u8]| (bytes.len() as u32).to_be_bytes());
+    /// queue.try_enqueue(b"hello".to_vec()).unwrap();
+    /// queue.try_enqueue(b"world".to_vec()).unwrap();
+    /// assert_eq!(queue.len(), 2);
+    /// let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+    /// assert_eq!(vectored.len(), 4);
+    /// assert!(vectored == b"\0\0\0\x05hello\0\0\0\x05world"[..]);
+    /// ```
+    pub fn with_framer<H: AsRef<[u8]>>(
+        capacity: usize,
+        framer: impl Fn(&[u8]) -> H + Send + Sync + 'static,
+    ) -> Self {
+        let mut queue = Self::with_capacity(capacity);
+        queue.set_framer(buffer::framer(framer));
+        queue
+    }
+
+    /// Queue bounding the growth of [`enqueue_unbounded`](Self::enqueue_unbounded) to `max`
+    /// items, see [`set_max_capacity`](Self::set_max_capacity).
+    pub fn with_max_capacity(initial: usize, max: usize) -> Self {
+        let queue = Self::with_capacity(initial);
+        queue.set_max_capacity(max);
+        queue
+    }
+
+    /// Queue with the given [spin limit](Self::set_spin_limit).
+    pub fn with_capacity_and_spin(capacity: usize, spin: u32) -> Self {
+        let queue = Self::with_capacity(capacity);
+        queue.set_spin_limit(spin);
+        queue
+    }
+
+    pub fn builder() -> VectoredQueueBuilder<T> {
+        VectoredQueueBuilder::new()
+    }
+
+    /// Compute a capacity able to buffer `target_buffer_duration_ms` of enqueuing at the given
+    /// throughput.
+    ///
+    /// The result is at least 1, and is bounded so that a full batch of
+    /// `expected_item_size_bytes` items doesn't exceed the maximum total size of a batch.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vectored_queue::VectoredQueue;
+    ///
+    /// // 10ms of buffering at 50k items/s
+    /// let capacity = VectoredQueue::<Vec<u8>>::optimal_capacity(128, 10, 50_000);
+    /// assert_eq!(capacity, 500);
+    /// let queue = VectoredQueue::<Vec<u8>>::with_capacity(capacity);
+    /// # assert_eq!(queue.capacity(), 500);
+    /// // a null throughput still gives a usable capacity
+    /// assert_eq!(VectoredQueue::<Vec<u8>>::optimal_capacity(128, 10, 0), 1);
+    /// ```
+    pub fn optimal_capacity(
+        expected_item_size_bytes: usize,
+        target_buffer_duration_ms: u64,
+        throughput_items_per_sec: u64,
+    ) -> usize {
+        let capacity = throughput_items_per_sec.saturating_mul(target_buffer_duration_ms) / 1000;
+        let capacity = usize::try_from(capacity).unwrap_or(usize::MAX);
+        let max_capacity = match expected_item_size_bytes {
+            0 => usize::MAX,
+            size => MAX_TOTAL_SIZE / size,
+        };
+        capacity.min(max_capacity).max(1)
+    }
+}
+
+impl<T, A: Allocator + Clone> VectoredQueue<T, A> {
+    #[cfg(feature = "allocator-api2")]
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_in(0, alloc)
+    }
+
+    /// Create a queue whose internal storage, including buffers growth and overflow, is
+    /// allocated with `alloc`.
+    #[cfg(feature = "allocator-api2")]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::with_capacity_alloc(capacity, alloc)
+    }
+
+    // Without the `allocator-api2` feature, the allocator is always the global one
+    pub(crate) fn with_capacity_alloc(capacity: usize, alloc: A) -> Self {
         Self {
             buffer_remain: AtomicUsize::new(capacity << 1),
+            poison: AtomicUsize::new(NOT_POISONED),
+            poison_len: AtomicUsize::new(0),
             pending_dequeue: AtomicUsize::new(0),
             capacity: AtomicUsize::new(capacity),
+            max_bytes: AtomicUsize::new(usize::MAX),
+            max_capacity: AtomicUsize::new(usize::MAX),
+            spin_limit: AtomicU32::new(SPIN_LIMIT),
+            yield_limit: AtomicU32::new(0),
             buffers: [
-                Buffer::with_capacity(capacity),
-                Buffer::with_capacity(capacity),
+                Buffer::with_capacity_in(capacity, alloc.clone()),
+                Buffer::with_capacity_in(capacity, alloc.clone()),
             ],
-            tmp: Default::default(),
+            tmp: Mutex::new(AllocVec::new_in(alloc)),
+            tmp_len: AtomicUsize::new(0),
+            tmp_size: AtomicUsize::new(0),
+            epoch: Instant::now(),
+            first_enqueue: [AtomicU64::new(0), AtomicU64::new(0)],
+            latency: AtomicU64::new(0),
+            pending_dequeue_count: AtomicU64::new(0),
+            capacity_history: Mutex::new(CapacityHistory::default()),
+            stats: Stats::default(),
+            config: Config::default(),
         }
     }
-
-    fn current_buffer(&self) -> &Buffer<T> {
+    fn current_buffer(&self) -> &Buffer<T, A> {
         &self.buffers[self.buffer_remain.load(Ordering::Relaxed) & 1]
     }
 
@@ -55,6 +282,53 @@ impl<T> VectoredQueue<T> {
         self.current_buffer().capacity()
     }
 
+    /// Bound of the [pending bytes](Self::pending_bytes) accepted by
+    /// [`try_enqueue`](Self::try_enqueue), `usize::MAX` if unbounded.
+    pub fn byte_capacity(&self) -> usize {
+        self.max_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Set the [byte capacity](Self::byte_capacity), `usize::MAX` to unbound the pending bytes.
+    ///
+    /// The bound is checked against the pending bytes before reserving a slot, so concurrent
+    /// producers may exceed it by their items; an empty queue still accepts larger bytes, so
+    /// that they cannot be blocked forever. Items already enqueued are kept if it is lowered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_bytes` is zero.
+    pub fn set_byte_capacity(&self, max_bytes: usize) {
+        assert_ne!(max_bytes, 0, "maximum bytes must not be zero");
+        self.max_bytes.store(max_bytes, Ordering::Relaxed);
+    }
+
+    /// Bound of the [length](Self::len) up to which
+    /// [`enqueue_unbounded`](Self::enqueue_unbounded) overflows, `usize::MAX` if unbounded.
+    pub fn max_capacity(&self) -> usize {
+        self.max_capacity.load(Ordering::Relaxed)
+    }
+
+    /// Set the [maximum capacity](Self::max_capacity), `usize::MAX` to let
+    /// [`enqueue_unbounded`](Self::enqueue_unbounded) overflow without bound.
+    ///
+    /// Overflowing is rejected with [`EnqueueError`] once the queue holds `max` items; the
+    /// regular capacity is not affected, and items already enqueued are kept if it is lowered.
+    pub fn set_max_capacity(&self, max: usize) {
+        self.max_capacity.store(max, Ordering::Relaxed);
+    }
+
+    /// Set the number of spins waiting for insertions in progress when dequeuing, before
+    /// yielding the thread, then returning [`TryDequeueResult::Pending`]; 100 by default.
+    pub fn set_spin_limit(&self, spin: u32) {
+        self.spin_limit.store(spin, Ordering::Relaxed);
+    }
+
+    /// Set the number of thread yields following the [spins](Self::set_spin_limit) when
+    /// dequeuing; none by default.
+    pub fn set_yield_limit(&self, yields: u32) {
+        self.yield_limit.store(yields, Ordering::Relaxed);
+    }
+
     pub fn set_capacity(&self, capacity: usize) {
         let mut current_capa = self.capacity.load(Ordering::Relaxed);
         while capacity > current_capa {
@@ -64,231 +338,3341 @@ impl<T> VectoredQueue<T> {
                 Ordering::Relaxed,
                 Ordering::Relaxed,
             ) {
-                Ok(_) => break,
+                Ok(_) => {
+                    self.record_capacity(capacity);
+                    break;
+                }
                 Err(c) => current_capa = c,
             }
         }
     }
 
+    fn record_capacity(&self, capacity: usize) {
+        lock(&self.capacity_history).push(capacity);
+    }
+
+    /// The last capacity increases, oldest first, up to 16 of them.
+    pub fn capacity_history(&self) -> Vec<usize> {
+        lock(&self.capacity_history).iter().collect()
+    }
+
+    pub fn clear_capacity_history(&self) {
+        lock(&self.capacity_history).recorded = 0;
+    }
+
+    /// Memory allocated by the queue, including itself but excluding the memory owned by the
+    /// enqueued items.
+    pub fn memory_footprint(&self) -> usize {
+        let tmp_capacity = lock(&self.tmp).capacity();
+        let buffers_size: usize = self.buffers.iter().map(Buffer::allocated_size).sum();
+        // buffers are already counted in the queue size
+        mem::size_of::<Self>() - mem::size_of::<[Buffer<T, A>; 2]>()
+            + buffers_size
+            + tmp_capacity * mem::size_of::<T>()
+    }
+
+    /// Snapshot of the cumulative enqueuing and dequeuing counters.
+    ///
+    /// Items are counted as dequeued once released; rejections are the enqueuings which
+    /// returned [`TryEnqueueError::Full`], e.g. while a blocking enqueuing waits.
+    #[cfg(feature = "metrics")]
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+
+    /// Reset the [counters](Self::stats), e.g. to sample them by interval.
+    ///
+    /// They are also reset by [`reset`](Self::reset), which goes through this method, but
+    /// not by [`purge`](Self::purge).
+    #[cfg(feature = "metrics")]
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// Number of enqueued items, whose total size is given by
+    /// [`pending_bytes`](Self::pending_bytes).
+    ///
+    /// It never blocks, but it is only a snapshot, possibly stale, of a concurrently modified
+    /// state: items being inserted or moved out of the overflow may not be counted yet.
     pub fn len(&self) -> usize {
-        self.current_buffer().len() + self.tmp.lock().unwrap().len()
+        self.current_buffer().len() + self.tmp_len.load(Ordering::Relaxed)
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    /// Whether [`try_enqueue`](Self::try_enqueue) is rejected as full, so no more items can be
+    /// enqueued without overflowing before the next dequeuing, with the same staleness as
+    /// [`len`](Self::len).
+    pub fn is_full(&self) -> bool {
+        let (buffer_index, remain, _) = decode(self.buffer_remain.load(Ordering::Relaxed));
+        remain == 0 || self.buffers[buffer_index].is_sealed()
     }
 
-    pub fn close(&self) {
-        self.buffer_remain.fetch_or(CLOSED_FLAG, Ordering::Relaxed);
+    /// Moving average of the time spent in the queue by the oldest item of each batch.
+    ///
+    /// Only the arrival of the first item of a batch is timed, so the cost is per batch, not
+    /// per item; the residency of the other items is thus overestimated.
+    pub fn sampled_latency(&self) -> Duration {
+        Duration::from_nanos(self.latency.load(Ordering::Relaxed))
     }
 
-    pub fn is_closed(&self) -> bool {
-        self.buffer_remain.load(Ordering::Relaxed) & CLOSED_FLAG != 0
+    /// Number of dequeuings which returned [`TryDequeueResult::Pending`] because insertions
+    /// were still in progress.
+    ///
+    /// A high count reveals strong producer contention, or a too short spinning on insertions.
+    pub fn pending_dequeue_count(&self) -> u64 {
+        self.pending_dequeue_count.load(Ordering::Relaxed)
     }
 
-    pub fn reopen(&self) {
-        self.buffer_remain
-            .fetch_and(!CLOSED_FLAG, Ordering::Relaxed);
+    fn now(&self) -> u64 {
+        let elapsed = self.config.now().saturating_duration_since(self.epoch);
+        u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX)
     }
-}
 
-impl<T> VectoredQueue<T>
-where
-    T: AsRef<[u8]>,
-{
-    pub fn try_enqueue(&self, bytes: T) -> Result<(), TryEnqueueError<T>> {
-        let mut buffer_remain = self.buffer_remain.load(Ordering::Relaxed);
-        loop {
-            if buffer_remain & CLOSED_FLAG != 0 {
-                return Err(TryEnqueueError::Closed(bytes));
-            }
-            if buffer_remain >> 1 == 0 {
-                return Err(TryEnqueueError::Full(bytes));
-            }
+    pub(crate) fn configure(&mut self, config: Config) {
+        self.epoch = config.now();
+        self.max_bytes.store(config.max_bytes, Ordering::Relaxed);
+        self.config = config;
+    }
+
+    // Must be called on an empty queue
+    pub(crate) fn set_reserved_slots(&mut self, header_slots: usize, trailer_slots: usize) {
+        for buffer in &mut self.buffers {
+            buffer.set_reserved_slots(header_slots, trailer_slots);
+        }
+    }
+
+    // Must be called on an empty queue
+    pub(crate) fn enable_tags(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.enable_tags();
+        }
+    }
+
+    // The unfilled slots are reusable if no slot has been reserved since, nor the buffers
+    // swapped, otherwise they are skipped once the poison is cleared
+    fn give_back_slots(&self, buffer_remain: usize, count: usize, unfilled: usize) {
+        let reserved = buffer_remain - 2 * count;
+        let mut current = self.buffer_remain.load(Ordering::Relaxed);
+        while current & !CLOSED_FLAGS == reserved {
             match self.buffer_remain.compare_exchange_weak(
-                buffer_remain,
-                buffer_remain - 2,
+                current,
+                current + 2 * unfilled,
                 Ordering::AcqRel,
                 Ordering::Relaxed,
             ) {
-                Ok(_) => break,
-                Err(s) => buffer_remain = s,
+                Ok(_) => return,
+                Err(s) => current = s,
             }
         }
-        self.buffers[buffer_remain & 1].insert(buffer_remain >> 1, bytes);
-        Ok(())
+        let (buffer_index, remain, _) = decode(buffer_remain);
+        let first_unfilled = remain - (count - unfilled);
+        self.poison_slots(buffer_index | (first_unfilled << 1), unfilled);
     }
 
-    pub fn enqueue_unbounded(&self, bytes: T) -> Result<(), EnqueueError<T>> {
-        let bytes = match self.try_enqueue(bytes) {
-            Ok(_) => return Ok(()),
-            Err(TryEnqueueError::Closed(bytes)) => return Err(EnqueueError(bytes)),
-            Err(TryEnqueueError::Full(bytes)) => bytes,
-        };
-        let mut tmp = self.tmp.lock().unwrap();
-        if self.capacity() == 0 {
-            self.set_capacity(1);
-            self.buffers[0].resize(1);
-            self.buffers[1].resize(1);
-            self.buffer_remain.store(1, Ordering::Release);
-            drop(tmp);
-            return self.enqueue_unbounded(bytes);
+    // Only the first unfilled slots can be skipped
+    fn poison_slots(&self, buffer_slot: usize, len: usize) {
+        if self
+            .poison
+            .compare_exchange(
+                NOT_POISONED,
+                buffer_slot,
+                Ordering::Release,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            self.poison_len.store(len, Ordering::Release);
         }
-        match self.try_enqueue(bytes) {
-            Ok(_) => return Ok(()),
-            Err(TryEnqueueError::Closed(bytes)) => return Err(EnqueueError(bytes)),
-            Err(TryEnqueueError::Full(bytes)) => tmp.push(bytes),
-        };
-        Ok(())
     }
 
-    pub fn try_dequeue_vectored(&self) -> Result<TryDequeueResult<T>, DequeueError> {
-        let pending_dequeue = self.pending_dequeue.swap(usize::MAX, Ordering::Relaxed);
-        if pending_dequeue == usize::MAX {
-            return Err(DequeueError::Conflict);
+    // Must be called on an empty queue
+    pub(crate) fn set_framer(&mut self, framer: Framer) {
+        for buffer in &mut self.buffers {
+            buffer.set_framer(framer.clone());
         }
-        let buffer_index = pending_dequeue & 1;
-        let buffer = &self.buffers[buffer_index];
-        let mut buffer_remain = self.buffer_remain.load(Ordering::Acquire);
-        let len = if pending_dequeue >> 1 == 0 {
-            assert_eq!(buffer_index, buffer_remain & 1);
-            let buffer_capa = buffer.capacity();
-            if (buffer_remain & !CLOSED_FLAG) >> 1 == buffer_capa {
-                self.pending_dequeue
-                    .store(pending_dequeue, Ordering::Relaxed);
-                return if buffer_remain & CLOSED_FLAG != 0 {
-                    Err(DequeueError::Closed)
-                } else {
-                    Ok(TryDequeueResult::Empty)
-                };
-            }
-            let next_buffer_index = !buffer_remain & 1;
-            let next_buffer = &self.buffers[next_buffer_index];
-            let mut tmp = self.tmp.lock().unwrap();
-            let tmp_len = tmp.len();
-            let next_capa = cmp::max(
-                next_buffer.capacity() + tmp_len,
-                self.capacity.load(Ordering::Relaxed),
-            );
-            self.set_capacity(next_capa);
-            next_buffer.resize(next_capa);
-            for (i, bytes) in mem::take(tmp.deref_mut()).into_iter().enumerate() {
-                next_buffer.insert(next_capa - i, bytes);
-            }
-            let next_buffer_remain = next_buffer_index | ((next_capa - tmp_len) << 1);
-            while let Err(s) = self.buffer_remain.compare_exchange_weak(
-                buffer_remain,
-                next_buffer_remain | (buffer_remain & CLOSED_FLAG),
-                Ordering::AcqRel,
-                Ordering::Relaxed,
-            ) {
-                buffer_remain = s
-            }
-            buffer_capa - (buffer_remain >> 1)
-        } else {
-            pending_dequeue >> 1
-        };
-        let Some((slices, total_size)) = buffer.get(len) else {
-            self.pending_dequeue.store(buffer_index | (len << 1), Ordering::Relaxed);
-            return Ok(TryDequeueResult::Pending)
+    }
+
+    // Must be called on an empty queue
+    pub(crate) fn set_inline_threshold(&mut self, inline_threshold: usize) {
+        for buffer in &mut self.buffers {
+            buffer.set_inline_threshold(inline_threshold);
+        }
+    }
+
+    // Time spent in the queue by the oldest item of the enqueuing buffer, zero if it is empty
+    #[cfg(any(feature = "sync", feature = "async"))]
+    pub(crate) fn pending_age(&self) -> Duration {
+        let buffer_index = self.buffer_remain.load(Ordering::Relaxed) & 1;
+        if self.buffers[buffer_index].len() == 0 {
+            return Duration::ZERO;
+        }
+        let first_enqueue = self.first_enqueue[buffer_index].load(Ordering::Relaxed);
+        Duration::from_nanos(self.now().saturating_sub(first_enqueue))
+    }
+
+    fn record_first_enqueue(&self, buffer_index: usize) {
+        self.first_enqueue[buffer_index].store(self.now(), Ordering::Relaxed);
+    }
+
+    // Only called by the consumer
+    fn record_dequeue(&self, buffer_index: usize) {
+        let first_enqueue = self.first_enqueue[buffer_index].load(Ordering::Relaxed);
+        let age = self.now().saturating_sub(first_enqueue);
+        let latency = match self.latency.load(Ordering::Relaxed) {
+            0 => age,
+            latency => latency - latency / 8 + age / 8,
         };
-        Ok(TryDequeueResult::Vectored(Vectored {
-            queue: self,
-            buffer_index,
-            slices,
-            total_size,
-        }))
+        self.latency.store(latency, Ordering::Relaxed);
     }
 
-    pub(crate) fn release(&self, buffer_index: usize, len: usize) {
-        let buffer = &self.buffers[buffer_index];
-        buffer.clear(len);
-        self.pending_dequeue
-            .store(!buffer_index & 1, Ordering::Relaxed);
+    /// Whether [`len`](Self::len) is zero, with the same staleness.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size of the enqueued items, overflowing ones included, with the same staleness as
+    /// [`len`](Self::len), e.g. for byte-level backpressure.
+    ///
+    /// These are the bytes written out, so, as the [total size](Vectored::total_size) of a
+    /// batch, they include the frame headers of a [framed](VectoredQueueBuilder::framer)
+    /// queue; the header of an overflowing item is only counted once it is framed.
+    #[doc(alias = "len_bytes")]
+    pub fn pending_bytes(&self) -> usize {
+        self.current_buffer().total_size() + self.tmp_size.load(Ordering::Relaxed)
+    }
+
+    /// Number of items of the next dequeued batch, without claiming dequeuing, e.g. to decide
+    /// whether it is worth dequeuing yet.
+    ///
+    /// It is the rest of a partially dequeued batch if any, otherwise the enqueued items, the
+    /// overflowing ones being only counted if nothing else is enqueued. Only completed
+    /// insertions are counted; it has otherwise the same staleness as [`len`](Self::len).
+    pub fn peek_len(&self) -> usize {
+        self.peek().0
+    }
+
+    /// Total size of the next dequeued batch, see [`peek_len`](Self::peek_len).
+    ///
+    /// The size of an item is counted once its bytes are written, possibly just before it is
+    /// counted by [`peek_len`](Self::peek_len), but never before.
+    pub fn peek_total_size(&self) -> usize {
+        self.peek().1
+    }
+
+    fn peek(&self) -> (usize, usize) {
+        let pending_dequeue = self.pending_dequeue.load(Ordering::Relaxed);
+        // a held batch is not peeked, its rest is only known once released
+        if pending_dequeue != usize::MAX && pending_dequeue >> 1 > 0 {
+            let buffer = &self.buffers[pending_dequeue & 1];
+            let len = (pending_dequeue >> 1).saturating_sub(buffer.dequeued());
+            return (len, buffer.total_size());
+        }
+        let buffer = self.current_buffer();
+        match buffer.inserted() {
+            0 => (
+                self.tmp_len.load(Ordering::Relaxed),
+                self.tmp_size.load(Ordering::Relaxed),
+            ),
+            len => (len, buffer.total_size()),
+        }
+    }
+
+    /// Close the queue: enqueuing is rejected, and dequeuing too once the remaining items are
+    /// drained, i.e. the same as [`close_enqueue`](Self::close_enqueue).
+    pub fn close(&self) {
+        self.close_enqueue();
+    }
+
+    /// Reject enqueuing, while the remaining items can still be dequeued; dequeuing returns
+    /// [`DequeueError::Closed`] once they are drained.
+    pub fn close_enqueue(&self) {
+        self.buffer_remain
+            .fetch_or(ENQUEUE_CLOSED_FLAG, Ordering::Relaxed);
+    }
+
+    /// Stop delivering: dequeuing returns [`DequeueError::Closed`] even if items remain, and
+    /// enqueuing is rejected, as they would not be delivered.
+    ///
+    /// Remaining items are kept, and delivered again after [`reopen_dequeue`](Self::reopen_dequeue).
+    pub fn close_dequeue(&self) {
+        self.buffer_remain
+            .fetch_or(DEQUEUE_CLOSED_FLAG, Ordering::Relaxed);
+    }
+
+    /// Whether either side is closed, i.e. whether enqueuing is rejected.
+    pub fn is_closed(&self) -> bool {
+        self.buffer_remain.load(Ordering::Relaxed) & CLOSED_FLAGS != 0
+    }
+
+    pub fn is_dequeue_closed(&self) -> bool {
+        self.buffer_remain.load(Ordering::Relaxed) & DEQUEUE_CLOSED_FLAG != 0
+    }
+
+    /// Reopen both sides.
+    pub fn reopen(&self) {
+        self.buffer_remain
+            .fetch_and(!CLOSED_FLAGS, Ordering::Relaxed);
+    }
+
+    /// Reopen the enqueuing side; enqueuing is still rejected if the dequeuing side is closed.
+    pub fn reopen_enqueue(&self) {
+        self.buffer_remain
+            .fetch_and(!ENQUEUE_CLOSED_FLAG, Ordering::Relaxed);
+    }
+
+    /// Reopen the dequeuing side, remaining items being delivered again.
+    pub fn reopen_dequeue(&self) {
+        self.buffer_remain
+            .fetch_and(!DEQUEUE_CLOSED_FLAG, Ordering::Relaxed);
+    }
+
+    /// Enqueue with high priority from the current thread while the guard is alive.
+    ///
+    /// High priority items are inserted in dedicated slots, ahead of the regular ones, so they
+    /// are dequeued at the head of the next batch. They are accepted even if the regular slots
+    /// are full, but fall back to regular enqueuing when dedicated slots are exhausted.
+    /// Only one queue can be marked at a time by a thread; the previous one is restored when
+    /// the guard is dropped.
+    pub fn mark_high_priority(&self) -> HighPriorityGuard<'_> {
+        let previous = HIGH_PRIORITY.with(|queue| queue.replace(self.as_ptr()));
+        HighPriorityGuard {
+            previous,
+            _queue: PhantomData,
+        }
+    }
+
+    fn is_high_priority(&self) -> bool {
+        HIGH_PRIORITY.with(|queue| queue.get() == self.as_ptr())
+    }
+
+    fn as_ptr(&self) -> *const () {
+        self as *const Self as *const ()
+    }
+
+    /// Split the queue into cloneable producers and a unique consumer, so that dequeuing
+    /// cannot conflict.
+    ///
+    /// The queue is closed when all producers are dropped.
+    pub fn split(self) -> (Producer<T, A>, Consumer<T, A>) {
+        let queue = Arc::new(self);
+        (
+            Producer(Arc::new(ProducerGuard(queue.clone()))),
+            Consumer(queue),
+        )
     }
 }
 
-pub enum TryDequeueResult<'a, T>
-where
-    T: AsRef<[u8]>,
-{
-    Empty,
-    Pending,
-    Vectored(Vectored<'a, T>),
+struct ProducerGuard<T, A: Allocator + Clone>(Arc<VectoredQueue<T, A>>);
+
+impl<T, A: Allocator + Clone> Drop for ProducerGuard<T, A> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
 }
 
-impl<T> fmt::Debug for TryDequeueResult<'_, T>
-where
-    T: AsRef<[u8]>,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Empty => f.debug_struct("TryDequeueResult::Empty").finish(),
-            Self::Pending => f.debug_struct("TryDequeueResult::Pending").finish(),
-            Self::Vectored(v) => f
-                .debug_tuple("TryDequeueResult::Vectored")
-                .field(v)
-                .finish(),
-        }
+/// Enqueuing handle returned by [`VectoredQueue::split`].
+pub struct Producer<T, A: Allocator + Clone = Global>(Arc<ProducerGuard<T, A>>);
+
+impl<T, A: Allocator + Clone> Clone for Producer<T, A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
     }
 }
 
-impl<'a, T> TryDequeueResult<'a, T>
+impl<T, A: Allocator + Clone> Producer<T, A>
 where
     T: AsRef<[u8]>,
 {
-    pub fn vectored(self) -> Option<Vectored<'a, T>> {
-        match self {
-            Self::Vectored(v) => Some(v),
-            _ => None,
-        }
+    pub fn try_enqueue(&self, bytes: T) -> Result<(), TryEnqueueError<T>> {
+        self.0 .0.try_enqueue(bytes)
+    }
+
+    /// See [`VectoredQueue::try_enqueue_many`].
+    pub fn try_enqueue_many<I: Iterator<Item = T>>(
+        &self,
+        items: &mut Peekable<I>,
+    ) -> Result<usize, EnqueueError<()>> {
+        self.0 .0.try_enqueue_many(items)
+    }
+
+    /// See [`VectoredQueue::try_enqueue_batch`].
+    pub fn try_enqueue_batch(&self, items: Vec<T>) -> Result<(), TryEnqueueError<Vec<T>>> {
+        self.0 .0.try_enqueue_batch(items)
+    }
+
+    pub fn enqueue_unbounded(&self, bytes: T) -> Result<(), EnqueueError<T>> {
+        self.0 .0.enqueue_unbounded(bytes)
+    }
+}
+
+/// Dequeuing handle returned by [`VectoredQueue::split`].
+///
+/// Dequeuing borrows it mutably, so it never returns [`DequeueError::Conflict`].
+pub struct Consumer<T, A: Allocator + Clone = Global>(Arc<VectoredQueue<T, A>>);
+
+impl<T, A: Allocator + Clone> Consumer<T, A> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
     }
 }
 
-impl<'a, T> From<TryDequeueResult<'a, T>> for Option<Vectored<'a, T>>
+impl<T, A: Allocator + Clone> Consumer<T, A>
 where
     T: AsRef<[u8]>,
 {
-    fn from(res: TryDequeueResult<'a, T>) -> Self {
-        res.vectored()
+    pub fn try_dequeue_vectored(&mut self) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.0.try_dequeue_vectored()
+    }
+
+    /// See [`VectoredQueue::try_dequeue_n`].
+    pub fn try_dequeue_n(
+        &mut self,
+        max: usize,
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.0.try_dequeue_n(max)
     }
 }
 
-#[cfg(test)]
-mod test {
-    use std::ops::Deref;
+// Poison the queue if dropped, i.e. if the insertion into the slot unwinds
+struct PoisonGuard<'a, T, A: Allocator + Clone> {
+    queue: &'a VectoredQueue<T, A>,
+    buffer_slot: usize,
+}
 
-    use crate::{error::DequeueError, queue::VectoredQueue, vectored::Vectored};
+impl<T, A: Allocator + Clone> Drop for PoisonGuard<'_, T, A> {
+    fn drop(&mut self) {
+        self.queue.poison_slots(self.buffer_slot, 1);
+    }
+}
+
+// Give back the last slots of a reservation left unfilled when dropped, e.g. by a shorter
+// iterator or if the insertions unwind
+struct ReservationGuard<'a, T, A: Allocator + Clone> {
+    queue: &'a VectoredQueue<T, A>,
+    // state before the reservation
+    buffer_remain: usize,
+    count: usize,
+    unfilled: usize,
+}
+
+impl<T, A: Allocator + Clone> Drop for ReservationGuard<'_, T, A> {
+    fn drop(&mut self) {
+        if self.unfilled > 0 {
+            self.queue
+                .give_back_slots(self.buffer_remain, self.count, self.unfilled);
+        }
+    }
+}
+
+/// Guard returned by [`VectoredQueue::mark_high_priority`].
+pub struct HighPriorityGuard<'a> {
+    // also makes the guard `!Send`, as the mark is thread local
+    previous: *const (),
+    _queue: PhantomData<&'a ()>,
+}
+
+impl Drop for HighPriorityGuard<'_> {
+    fn drop(&mut self) {
+        HIGH_PRIORITY.with(|queue| queue.set(self.previous));
+    }
+}
+
+/// Iterator returned by [`VectoredQueue::drain`].
+pub struct DrainIter<'a, T> {
+    items: Box<dyn Iterator<Item = T> + 'a>,
+}
+
+impl<T> fmt::Debug for DrainIter<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DrainIter").finish_non_exhaustive()
+    }
+}
+
+impl<T> Iterator for DrainIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.items.next()
+    }
+}
+
+impl<T> Drop for DrainIter<'_, T> {
+    fn drop(&mut self) {
+        // the buffers have already been reset, so their remaining items must be read
+        self.items.by_ref().for_each(drop);
+    }
+}
+
+/// Exclusive right to dequeue, returned by [`VectoredQueue::consumer_token`].
+///
+/// While the token is alive, dequeuing without it returns [`DequeueError::Conflict`].
+pub struct ConsumerToken<'a, T, A: Allocator + Clone = Global> {
+    queue: &'a VectoredQueue<T, A>,
+    // dequeuing state, kept out of the queue while the token is alive
+    pending_dequeue: usize,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for ConsumerToken<'_, T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConsumerToken").finish_non_exhaustive()
+    }
+}
+
+impl<T, A: Allocator + Clone> Drop for ConsumerToken<'_, T, A> {
+    fn drop(&mut self) {
+        self.queue
+            .pending_dequeue
+            .store(self.pending_dequeue, Ordering::Relaxed);
+    }
+}
+
+impl<T, A: Allocator + Clone> VectoredQueue<T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn try_enqueue(&self, bytes: T) -> Result<(), TryEnqueueError<T>> {
+        self.count_rejected(self.try_enqueue_inner(bytes, 0))
+    }
+
+    /// Enqueue bytes tagged, e.g. with the stream they belong to, to be retrieved with
+    /// [`Vectored::items_with_tags`] and [`Vectored::tag_summary`]; untagged items have the
+    /// tag `0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if item tags are not [enabled](crate::VectoredQueueBuilder::item_tags).
+    pub fn try_enqueue_tagged(&self, bytes: T, tag: u64) -> Result<(), TryEnqueueError<T>> {
+        assert!(self.buffers[0].is_tagged(), "item tags are not enabled");
+        self.count_rejected(self.try_enqueue_inner(bytes, tag))
+    }
+
+    fn count_rejected<R>(
+        &self,
+        res: Result<(), TryEnqueueError<R>>,
+    ) -> Result<(), TryEnqueueError<R>> {
+        if let Err(TryEnqueueError::Full(_)) = res {
+            self.stats.rejected();
+        }
+        res
+    }
+
+    fn try_enqueue_inner(&self, bytes: T, tag: u64) -> Result<(), TryEnqueueError<T>> {
+        if self.is_poisoned() {
+            return Err(TryEnqueueError::Closed(bytes));
+        }
+        let bytes = if self.is_high_priority() {
+            let _tmp = lock(&self.tmp);
+            match self.try_enqueue_priority(bytes, tag) {
+                Err(TryEnqueueError::Full(bytes)) => bytes,
+                res => return res,
+            }
+        } else {
+            bytes
+        };
+        // an empty queue always accepts bytes, so larger ones cannot be blocked forever
+        let max_bytes = self.byte_capacity();
+        if max_bytes != usize::MAX
+            && self.pending_bytes().saturating_add(bytes.as_ref().len()) > max_bytes
+            && !self.is_empty()
+        {
+            return Err(TryEnqueueError::Full(bytes));
+        }
+        self.try_enqueue_slot(bytes, tag)
+    }
+
+    // Must be called while holding the overflow lock, which prevents buffers to be swapped
+    fn try_enqueue_priority(&self, bytes: T, tag: u64) -> Result<(), TryEnqueueError<T>> {
+        let (buffer_index, remain, closed) = decode(self.buffer_remain.load(Ordering::Acquire));
+        if closed {
+            return Err(TryEnqueueError::Closed(bytes));
+        }
+        let buffer = &self.buffers[buffer_index];
+        let first = remain == buffer.capacity() && buffer.priority_len() == 0;
+        let size = buffer
+            .insert_priority(bytes, tag)
+            .map_err(TryEnqueueError::Full)?;
+        self.stats.enqueued(1, size);
+        if first {
+            self.record_first_enqueue(buffer_index);
+        }
+        Ok(())
+    }
+
+    fn try_enqueue_slot(&self, bytes: T, tag: u64) -> Result<(), TryEnqueueError<T>> {
+        // `as_ref` is called once whatever the retries
+        let size = bytes.as_ref().len();
+        let mut buffer_remain = self.buffer_remain.load(Ordering::Relaxed);
+        loop {
+            let (buffer_index, remain, closed) = decode(buffer_remain);
+            if closed {
+                return Err(TryEnqueueError::Closed(bytes));
+            }
+            let buffer = &self.buffers[buffer_index];
+            if remain == 0 || buffer.is_sealed() {
+                return Err(TryEnqueueError::Full(bytes));
+            }
+            if buffer.would_overflow(1, size) {
+                // following bytes must not be enqueued before these ones
+                buffer.seal();
+                return Err(TryEnqueueError::Full(bytes));
+            }
+            match self.buffer_remain.compare_exchange_weak(
+                buffer_remain,
+                buffer_remain - 2,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(s) => buffer_remain = s,
+            }
+        }
+        let (buffer_index, remain, _) = decode(buffer_remain);
+        let buffer = &self.buffers[buffer_index];
+        if remain == buffer.capacity() {
+            self.record_first_enqueue(buffer_index);
+        }
+        let guard = PoisonGuard {
+            queue: self,
+            buffer_slot: buffer_index | (remain << 1),
+        };
+        let size = buffer.insert(remain, bytes, tag);
+        mem::forget(guard);
+        self.stats.enqueued(1, size);
+        Ok(())
+    }
+
+    /// Enqueue as many items as there are available slots, returning the number of enqueued
+    /// items; the iterator is left at the first item not enqueued.
+    ///
+    /// Slots are reserved at once for as many items as the iterator size hint lower bound,
+    /// counting the peeked item, so iterators without one, e.g. filtered ones, are enqueued
+    /// one reservation per item. Slots left unfilled by an iterator yielding fewer items are
+    /// given back, or [poison](Self::is_poisoned) the queue if others have been reserved
+    /// since. Enqueuing stops before an item exceeding the maximum total size, the queue
+    /// being then considered full, as with [`try_enqueue`](Self::try_enqueue). The high
+    /// priority mark and the [byte bound](crate::VectoredQueueBuilder::max_bytes) are
+    /// ignored. Enqueuing into a closed queue returns an error, whatever the number of items.
+    pub fn try_enqueue_many<I: Iterator<Item = T>>(
+        &self,
+        items: &mut Peekable<I>,
+    ) -> Result<usize, EnqueueError<()>> {
+        let mut enqueued = 0;
+        loop {
+            // an empty reservation only checks the queue is open
+            let (max, size) = match items.peek().map(|bytes| bytes.as_ref().len()) {
+                Some(size) => (items.size_hint().0, size),
+                None if enqueued > 0 => break,
+                None => (0, 0),
+            };
+            let (buffer_remain, count) = match self.reserve_slots(1, max, size) {
+                Ok(Some(reserved)) => reserved,
+                Err(err) if enqueued == 0 => return Err(err),
+                Ok(None) | Err(_) => break,
+            };
+            // the unfilled slots left by an overflowing item are given back
+            let buffer = &self.buffers[decode(buffer_remain).0];
+            let mut fitting = iter::from_fn(|| {
+                items.next_if(|bytes| !buffer.would_overflow(1, bytes.as_ref().len()))
+            });
+            let inserted = self.insert_reserved(buffer_remain, count, &mut fitting);
+            enqueued += inserted;
+            if inserted < count || count == 0 {
+                // following bytes must not be enqueued before the overflowing ones
+                if items.peek().is_some() {
+                    buffer.seal();
+                }
+                break;
+            }
+        }
+        Ok(enqueued)
+    }
+
+    /// Enqueue all the items or none of them, reserving their slots at once, so that they are
+    /// contiguous in the same batch.
+    ///
+    /// The items are returned if there are not enough available slots for all of them, or if
+    /// their summed size would exceed the maximum total size; as with
+    /// [`try_enqueue_many`](Self::try_enqueue_many), the high priority mark and the
+    /// [byte bound](crate::VectoredQueueBuilder::max_bytes) are ignored.
+    pub fn try_enqueue_batch(&self, items: Vec<T>) -> Result<(), TryEnqueueError<Vec<T>>> {
+        let count = items.len();
+        let size = items.iter().fold(0, |size: usize, bytes| {
+            size.saturating_add(bytes.as_ref().len())
+        });
+        match self.reserve_slots(count, count, size) {
+            Ok(Some((buffer_remain, _))) => {
+                self.insert_reserved(buffer_remain, count, &mut items.into_iter());
+                Ok(())
+            }
+            Ok(None) => {
+                self.stats.rejected();
+                Err(TryEnqueueError::Full(items))
+            }
+            Err(_) => Err(TryEnqueueError::Closed(items)),
+        }
+    }
+
+    // Reserve at least `min` and at most `max` slots with a single CAS, returning the state
+    // before the reservation and the number of reserved slots, or `None` if less than `min`
+    // are available, or if the `min` first items, of `size` bytes, would overflow; an empty
+    // reservation succeeds if the queue is open.
+    fn reserve_slots(
+        &self,
+        min: usize,
+        max: usize,
+        size: usize,
+    ) -> Result<Option<(usize, usize)>, EnqueueError<()>> {
+        let mut buffer_remain = self.buffer_remain.load(Ordering::Relaxed);
+        loop {
+            let (buffer_index, remain, closed) = decode(buffer_remain);
+            if closed || self.is_poisoned() {
+                return Err(EnqueueError(()));
+            }
+            let count = cmp::min(remain, max);
+            if max == 0 {
+                return Ok(Some((buffer_remain, 0)));
+            }
+            let buffer = &self.buffers[buffer_index];
+            if count < min || buffer.is_sealed() {
+                return Ok(None);
+            }
+            if buffer.would_overflow(min, size) {
+                // following bytes must not be enqueued before these ones, but an empty buffer
+                // is not sealed, as no dequeuing would swap it
+                if buffer.total_size() > 0 {
+                    buffer.seal();
+                }
+                return Ok(None);
+            }
+            match self.buffer_remain.compare_exchange_weak(
+                buffer_remain,
+                buffer_remain - 2 * count,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(Some((buffer_remain, count))),
+                Err(s) => buffer_remain = s,
+            }
+        }
+    }
+
+    // Insert the items into the reserved slots, returning the number of inserted ones
+    fn insert_reserved(
+        &self,
+        buffer_remain: usize,
+        count: usize,
+        items: &mut impl Iterator<Item = T>,
+    ) -> usize {
+        let (buffer_index, remain, _) = decode(buffer_remain);
+        let buffer = &self.buffers[buffer_index];
+        if count > 0 && remain == buffer.capacity() {
+            self.record_first_enqueue(buffer_index);
+        }
+        // slots are filled in decreasing order, so the unfilled ones are the last reserved
+        let mut guard = ReservationGuard {
+            queue: self,
+            buffer_remain,
+            count,
+            unfilled: count,
+        };
+        for slot in (remain + 1 - count..=remain).rev() {
+            let Some(bytes) = items.next() else {
+                break;
+            };
+            let size = bytes.as_ref().len();
+            // concurrent insertions may still exceed the maximum
+            if buffer.would_overflow(1, size) {
+                buffer.seal();
+            }
+            let size = buffer.insert(slot, bytes, 0);
+            self.stats.enqueued(1, size);
+            guard.unfilled -= 1;
+        }
+        count - guard.unfilled
+    }
+
+    /// Enqueue bytes, even if the queue is full.
+    ///
+    /// Enqueuing order is preserved: overflowing bytes are moved to the next enqueuing buffer
+    /// before it is opened to [`try_enqueue`](Self::try_enqueue), so they are always dequeued
+    /// before bytes enqueued afterwards.
+    ///
+    /// Like every enqueuing method of this queue, it doesn't notify consumers; the sync and
+    /// async queues do it whether the bytes are inserted or kept in the overflow.
+    pub fn enqueue_unbounded(&self, bytes: T) -> Result<(), EnqueueError<T>> {
+        self.enqueue_growing(bytes)
+    }
+
+    /// Enqueue bytes, growing the queue capacity if it is full.
+    ///
+    /// Overflowing bytes are kept aside and moved at the head of the next enqueuing buffer,
+    /// resized to hold them, when the buffers are swapped by the next dequeuing. Resizing
+    /// is thus only done by the consumer, while holding the overflow lock, on a buffer no
+    /// producer can access.
+    pub fn enqueue_growing(&self, bytes: T) -> Result<(), EnqueueError<T>> {
+        let bytes = match self.try_enqueue_inner(bytes, 0) {
+            Ok(_) => return Ok(()),
+            Err(TryEnqueueError::Closed(bytes)) => return Err(EnqueueError(bytes)),
+            Err(TryEnqueueError::Full(bytes)) => bytes,
+        };
+        let mut tmp = lock(&self.tmp);
+        match self.try_enqueue_slot(bytes, 0) {
+            Ok(_) => return Ok(()),
+            Err(TryEnqueueError::Closed(bytes)) => return Err(EnqueueError(bytes)),
+            // the overflow lock is held, so the overflowing length cannot be exceeded
+            Err(TryEnqueueError::Full(bytes)) if self.len() >= self.max_capacity() => {
+                self.stats.rejected();
+                return Err(EnqueueError(bytes));
+            }
+            Err(TryEnqueueError::Full(bytes)) => {
+                let size = bytes.as_ref().len();
+                self.tmp_len.fetch_add(1, Ordering::Relaxed);
+                self.tmp_size.fetch_add(size, Ordering::Relaxed);
+                self.stats.enqueued(1, size);
+                tmp.push(bytes);
+            }
+        };
+        Ok(())
+    }
+
+    /// Dequeue the enqueued items as a batch of slices, released when dropped.
+    ///
+    /// Items are dropped on release, before the queue can be dequeued again: their destructor
+    /// may enqueue into the queue, but dequeuing from it returns [`DequeueError::Conflict`].
+    ///
+    /// After [`close`](Self::close), every item enqueued before is still dequeued, pending,
+    /// high priority and overflowing ones included; [`DequeueError::Closed`] is only returned
+    /// once the queue is empty.
+    pub fn try_dequeue_vectored(&self) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.try_dequeue_vectored_notify(&|| {})
+    }
+
+    /// Dequeue like [`try_dequeue_vectored`](Self::try_dequeue_vectored), but wait for the
+    /// insertions in progress, yielding the thread, instead of returning
+    /// [`TryDequeueResult::Pending`].
+    ///
+    /// A producer preempted between reserving its slot and inserting its item delays the
+    /// dequeuing until it is rescheduled, so it should only be used if producers cannot be
+    /// blocked for long; the bound of the spinning is set with
+    /// [`set_spin_limit`](Self::set_spin_limit).
+    pub fn try_dequeue_vectored_complete(
+        &self,
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        loop {
+            match self.try_dequeue_vectored()? {
+                TryDequeueResult::Pending => yield_now(),
+                res => return Ok(res),
+            }
+        }
+    }
+
+    /// Dequeue at most `max` items, the remaining ones being dequeued by the next dequeuing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn try_dequeue_n(&self, max: usize) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        assert!(max > 0, "cannot dequeue zero items");
+        self.try_dequeue_notify(&|| {}, 0, max)
+    }
+
+    /// Dequeue exactly `n` items, only if they are all ready in the current batch, the
+    /// remaining ones being dequeued by the next dequeuing.
+    ///
+    /// `None` is returned if fewer items are enqueued, or if their insertion is still in
+    /// progress. As a batch is bounded by the capacity, `n` should not exceed it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn try_dequeue_exact(&self, n: usize) -> Result<Option<Vectored<'_, T, A>>, DequeueError> {
+        assert!(n > 0, "cannot dequeue zero items");
+        Ok(self.try_dequeue_notify(&|| {}, n, n)?.vectored())
+    }
+
+    pub(crate) fn try_dequeue_vectored_notify(
+        &self,
+        notify_enqueue: &impl Fn(),
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.try_dequeue_notify(notify_enqueue, 0, usize::MAX)
+    }
+
+    /// Take the exclusive right to dequeue, `None` if it is already taken, or if the queue is
+    /// being dequeued.
+    ///
+    /// Dequeuing with the token, which requires a mutable reference, skips the atomic claim
+    /// done by [`try_dequeue_vectored`](Self::try_dequeue_vectored) to detect conflicts.
+    pub fn consumer_token(&self) -> Option<ConsumerToken<'_, T, A>> {
+        let pending_dequeue = self.claim_dequeue().ok()?;
+        Some(ConsumerToken {
+            queue: self,
+            pending_dequeue,
+        })
+    }
+
+    /// Dequeue the enqueued items as a batch of slices, like
+    /// [`try_dequeue_vectored`](Self::try_dequeue_vectored), with the consumer token.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the token has been taken from another queue.
+    pub fn try_dequeue_token<'a>(
+        &'a self,
+        token: &'a mut ConsumerToken<'_, T, A>,
+    ) -> Result<TryDequeueResult<'a, T, A>, DequeueError> {
+        assert!(ptr::eq(self, token.queue), "token of another queue");
+        self.check_dequeue()?;
+        let pending_dequeue = token.pending_dequeue;
+        self.try_dequeue_claimed(
+            pending_dequeue,
+            Some(&mut token.pending_dequeue),
+            &|| {},
+            0,
+            usize::MAX,
+        )
+    }
+
+    fn check_dequeue(&self) -> Result<(), DequeueError> {
+        if self.is_dequeue_closed() {
+            return Err(DequeueError::Closed);
+        }
+        if self.is_poisoned() {
+            return Err(DequeueError::Poisoned);
+        }
+        Ok(())
+    }
+
+    // Store the dequeuing state back, into the token if dequeuing is claimed by it
+    fn end_claim(&self, token: Option<&mut usize>, pending_dequeue: usize) {
+        match token {
+            Some(token) => *token = pending_dequeue,
+            None => self
+                .pending_dequeue
+                .store(pending_dequeue, Ordering::Relaxed),
+        }
+    }
+
+    // Enqueuing capacity is freed when buffers are swapped, i.e. when the enqueuing buffer
+    // becomes the previously released one, so waiting producers are notified there.
+    // A non-empty batch with fewer than `min` items is left in place, without swapping buffers.
+    fn try_dequeue_notify(
+        &self,
+        notify_enqueue: &impl Fn(),
+        min: usize,
+        max: usize,
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.check_dequeue()?;
+        let pending_dequeue = self.claim_dequeue()?;
+        self.try_dequeue_claimed(pending_dequeue, None, notify_enqueue, min, max)
+    }
+
+    fn try_dequeue_claimed<'a>(
+        &'a self,
+        pending_dequeue: usize,
+        token: Option<&'a mut usize>,
+        notify_enqueue: &impl Fn(),
+        min: usize,
+        max: usize,
+    ) -> Result<TryDequeueResult<'a, T, A>, DequeueError> {
+        let buffer_index = pending_dequeue & 1;
+        let buffer = &self.buffers[buffer_index];
+        let mut buffer_remain = self.buffer_remain.load(Ordering::Acquire);
+        let len = if pending_dequeue >> 1 == 0 {
+            let (enqueuing_index, remain, closed) = decode(buffer_remain);
+            if buffer_index != enqueuing_index {
+                // should not happen, but the swap must not be done on inconsistent indexes
+                #[cfg(feature = "log")]
+                log::debug!(
+                    "dequeued buffer {buffer_index} is not the enqueuing one ({enqueuing_index})"
+                );
+                self.end_claim(token, pending_dequeue);
+                return Ok(TryDequeueResult::Pending);
+            }
+            let buffer_capa = buffer.capacity();
+            let available = buffer_capa - remain + buffer.priority_len();
+            if available > 0 && available < min {
+                self.end_claim(token, pending_dequeue);
+                return Ok(TryDequeueResult::Empty);
+            }
+            let mut tmp = lock(&self.tmp);
+            // buffers must be swapped even if empty when there are overflowing bytes
+            if remain == buffer_capa && tmp.is_empty() && buffer.priority_len() == 0 {
+                self.end_claim(token, pending_dequeue);
+                return if closed {
+                    Err(DequeueError::Closed)
+                } else {
+                    Ok(TryDequeueResult::Empty)
+                };
+            }
+            let next_buffer_index = !buffer_remain & 1;
+            let next_buffer = &self.buffers[next_buffer_index];
+            // overflowing bytes beyond the maximum total size are kept for the next swap
+            let mut tmp_size: usize = 0;
+            let tmp_len = tmp
+                .iter()
+                .position(|bytes| match tmp_size.checked_add(bytes.as_ref().len()) {
+                    Some(size) if size <= MAX_TOTAL_SIZE => {
+                        tmp_size = size;
+                        false
+                    }
+                    _ => true,
+                })
+                .unwrap_or(tmp.len())
+                .max(1)
+                .min(tmp.len());
+            let grown_capa = match self.config.growth {
+                _ if tmp_len == 0 => next_buffer.capacity(),
+                Growth::Exact => next_buffer.capacity() + tmp_len,
+                Growth::Double => {
+                    cmp::max(next_buffer.capacity() * 2, next_buffer.capacity() + tmp_len)
+                }
+            };
+            let next_capa = cmp::max(grown_capa, self.capacity.load(Ordering::Relaxed));
+            self.set_capacity(next_capa);
+            next_buffer.resize(next_capa);
+            let mut drained_size = 0;
+            for (i, bytes) in tmp.drain(..tmp_len).enumerate() {
+                let size = bytes.as_ref().len();
+                drained_size += size;
+                // the frame header of an overflowing item is counted once framed
+                let framed_size = next_buffer.insert(next_capa - i, bytes, 0);
+                self.stats.enqueued(0, framed_size - size);
+            }
+            self.tmp_len.fetch_sub(tmp_len, Ordering::Relaxed);
+            self.tmp_size.fetch_sub(drained_size, Ordering::Relaxed);
+            if tmp_len > 0 {
+                self.record_first_enqueue(next_buffer_index);
+            }
+            if !tmp.is_empty() {
+                next_buffer.seal();
+            }
+            let next_buffer_remain = next_buffer_index | ((next_capa - tmp_len) << 1);
+            while let Err(s) = self.buffer_remain.compare_exchange_weak(
+                buffer_remain,
+                next_buffer_remain | (buffer_remain & CLOSED_FLAGS),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                buffer_remain = s
+            }
+            drop(tmp);
+            notify_enqueue();
+            // the flag may have been set concurrently, so the swapped value is decoded again
+            let (_, remain, _) = decode(buffer_remain);
+            let len = buffer_capa - remain + buffer.priority_len();
+            if len == 0 {
+                // the swapped buffer was empty, overflowing bytes are now in the next one
+                return self.try_dequeue_claimed(
+                    next_buffer_index,
+                    token,
+                    notify_enqueue,
+                    min,
+                    max,
+                );
+            }
+            len
+        } else {
+            let len = pending_dequeue >> 1;
+            if len - buffer.dequeued() < min {
+                self.end_claim(token, pending_dequeue);
+                return Ok(TryDequeueResult::Empty);
+            }
+            len
+        };
+        let limits = (
+            self.spin_limit.load(Ordering::Relaxed),
+            self.yield_limit.load(Ordering::Relaxed),
+        );
+        let Some((slices, total_size)) = buffer.get(len, max, limits) else {
+            self.pending_dequeue_count.fetch_add(1, Ordering::Relaxed);
+            self.end_claim(token, buffer_index | (len << 1));
+            return Ok(TryDequeueResult::Pending);
+        };
+        if buffer.dequeued() == 0 {
+            self.record_dequeue(buffer_index);
+        }
+        let reserved_slots = buffer.reserved_slots();
+        Ok(TryDequeueResult::Vectored(Vectored {
+            queue: self,
+            buffer_index,
+            items: (slices.len() - reserved_slots.0 - reserved_slots.1) / buffer.stride(),
+            slices,
+            marker: PhantomData,
+            reserved_slots,
+            total_size,
+            framed: [None, None],
+            split: false,
+            token,
+        }))
+    }
+
+    /// Hand the dequeued batch, if any, to the closure, releasing it when the closure returns.
+    ///
+    /// The batch is also released if the closure panics, so the queue can still be dequeued.
+    pub fn try_dequeue_with<R>(
+        &self,
+        f: impl FnOnce(&mut Vectored<'_, T, A>) -> R,
+    ) -> Result<Option<R>, DequeueError> {
+        Ok(self
+            .try_dequeue_vectored()?
+            .vectored()
+            .map(|mut vectored| f(&mut vectored)))
+    }
+
+    /// Dequeue a batch that is only released if the transaction is committed.
+    ///
+    /// Dropping the transaction without committing leaves the batch in the queue, so it is
+    /// dequeued again by the next dequeuing, e.g. to retry a failed write. `None` is returned
+    /// if the queue is empty or the batch is pending.
+    pub fn begin_dequeue(&self) -> Result<Option<DequeueTxn<'_, T, A>>, DequeueError> {
+        Ok(self
+            .try_dequeue_vectored()?
+            .vectored()
+            .map(|vectored| DequeueTxn {
+                vectored: mem::ManuallyDrop::new(vectored),
+            }))
+    }
+
+    /// Dequeue a batch whose slices are copied into a caller-provided buffer with
+    /// [`DequeueTicket::copy_into`], e.g. a buffer reused across writes.
+    pub fn try_dequeue_ticket(&self) -> Result<Option<DequeueTicket<'_, T, A>>, DequeueError> {
+        Ok(self
+            .try_dequeue_vectored()?
+            .vectored()
+            .map(|vectored| DequeueTicket { vectored }))
+    }
+
+    /// Dequeue a batch whose slices are appended to `out`, e.g. a buffer owned by a
+    /// connection, and removed from it when the batch is released.
+    ///
+    /// Slices already in `out` are kept, e.g. headers, and others can be pushed after the
+    /// payload ones with [`DequeueInto::push`].
+    pub fn try_dequeue_into<'a, 'v>(
+        &'a self,
+        out: &'v mut Vec<IoSlice<'a>>,
+    ) -> Result<Option<DequeueInto<'a, 'v, T, A>>, DequeueError> {
+        let Some(ticket) = self.try_dequeue_ticket()? else {
+            return Ok(None);
+        };
+        let start = out.len();
+        out.extend_from_slice(&ticket.vectored);
+        Ok(Some(DequeueInto { ticket, out, start }))
+    }
+
+    /// Move the pending items into `dst`, in order, batch by batch, and return their number.
+    ///
+    /// A batch dequeued but not released, e.g. by an uncommitted [`DequeueTxn`], is moved
+    /// once it is put back, but not while it is alive, as it conflicts with the transfer.
+    /// Items are enqueued with [`try_enqueue_many`](Self::try_enqueue_many), so the byte bound
+    /// of the destination is ignored. If the destination fills up, the rest of the current
+    /// batch is returned in the error, and the following items are left in the source.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` is the source queue.
+    pub fn transfer_to<B: Allocator + Clone>(
+        &self,
+        dst: &VectoredQueue<T, B>,
+    ) -> Result<usize, TransferError<T>> {
+        assert!(!ptr::addr_eq(self, dst), "cannot transfer into itself");
+        let mut transferred = 0;
+        while let Some(items) = self.dequeue_transfer(&|| {}, transferred)? {
+            let mut items = items.into_iter().peekable();
+            match dst.try_enqueue_many(&mut items) {
+                Ok(count) => transferred += count,
+                Err(_) => {
+                    let items = items.collect();
+                    return Err(TransferError::Closed { transferred, items });
+                }
+            }
+            if items.len() > 0 {
+                let items = items.collect();
+                return Err(TransferError::Full { transferred, items });
+            }
+        }
+        Ok(transferred)
+    }
+
+    // Dequeue the items of the next batch to transfer, `None` once the queue is empty or closed
+    pub(crate) fn dequeue_transfer(
+        &self,
+        notify_enqueue: &impl Fn(),
+        transferred: usize,
+    ) -> Result<Option<Vec<T>>, TransferError<T>> {
+        loop {
+            match self.try_dequeue_notify(notify_enqueue, 0, usize::MAX) {
+                Ok(TryDequeueResult::Vectored(vectored)) => return Ok(Some(vectored.into_vec())),
+                // reserved slots are about to be inserted
+                Ok(TryDequeueResult::Pending) => hint::spin_loop(),
+                Ok(TryDequeueResult::Empty) | Err(DequeueError::Closed) => return Ok(None),
+                Err(DequeueError::Conflict) => return Err(TransferError::Conflict { transferred }),
+                Err(DequeueError::Poisoned) => return Err(TransferError::Poisoned { transferred }),
+            }
+        }
+    }
+
+    /// Compare the enqueued bytes of both queues, item by item, without dequeuing them.
+    ///
+    /// Bytes of a [`Vectored`] still alive are not taken in account, as they have already been
+    /// dequeued; a [`DequeueError::Conflict`] is returned if one of the queues is being
+    /// dequeued.
+    pub fn content_eq(&self, other: &Self) -> Result<bool, DequeueError> {
+        if self.is_poisoned() || other.is_poisoned() {
+            return Err(DequeueError::Poisoned);
+        }
+        if ptr::eq(self, other) {
+            return Ok(true);
+        }
+        // lock in a consistent order to avoid deadlocks
+        let (first, second) = if (self as *const Self) < (other as *const Self) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let first_pending = first.claim_dequeue()?;
+        let second_pending = match second.claim_dequeue() {
+            Ok(pending) => pending,
+            Err(err) => {
+                first
+                    .pending_dequeue
+                    .store(first_pending, Ordering::Relaxed);
+                return Err(err);
+            }
+        };
+        let first_tmp = lock(&first.tmp);
+        let second_tmp = lock(&second.tmp);
+        let eq = first
+            .contents(first_pending, &first_tmp)
+            .eq(second.contents(second_pending, &second_tmp));
+        drop((first_tmp, second_tmp));
+        first
+            .pending_dequeue
+            .store(first_pending, Ordering::Relaxed);
+        second
+            .pending_dequeue
+            .store(second_pending, Ordering::Relaxed);
+        Ok(eq)
+    }
+
+    /// Return the queue to its just-constructed state, dropping all its items, but keeping
+    /// its allocated capacity, e.g. to reuse it from a pool.
+    ///
+    /// The queue is reopened and its metrics are reset. [`DequeueError::Conflict`] is returned
+    /// if the queue is being dequeued. Items concurrently enqueued may be dropped or kept;
+    /// their destructors must not enqueue into the queue.
+    pub fn reset(&self) -> Result<(), DequeueError> {
+        let pending_dequeue = self.claim_dequeue()?;
+        self.clear_claimed(pending_dequeue, true);
+        Ok(())
+    }
+
+    /// Drop all the enqueued items, overflowing ones included, e.g. on a connection reset,
+    /// returning whether they have been dropped.
+    ///
+    /// Unlike [`reset`](Self::reset), the closing state and the metrics are kept. Nothing is
+    /// dropped, and `false` is returned, if the queue is being dequeued, e.g. while a batch is
+    /// held, so that its items are not dropped twice. Items concurrently enqueued may be
+    /// dropped or kept; their destructors must not enqueue into the queue.
+    pub fn purge(&self) -> bool {
+        let Ok(pending_dequeue) = self.claim_dequeue() else {
+            return false;
+        };
+        self.clear_claimed(pending_dequeue, false);
+        true
+    }
+
+    // Drop all the items, dequeuing being claimed, and release the claim; the queue is also
+    // reopened, and its metrics reset, if `reset` is true
+    fn clear_claimed(&self, pending_dequeue: usize, reset: bool) {
+        self.skip_poisoned_slot(pending_dequeue);
+        let mut tmp = lock(&self.tmp);
+        // no more slots can be reserved, but reserved ones must be inserted before clearing
+        let buffer_remain = self
+            .buffer_remain
+            .fetch_and(1 | CLOSED_FLAGS, Ordering::Acquire);
+        let (buffer_index, remain, _) = decode(buffer_remain);
+        let buffer = &self.buffers[buffer_index];
+        let reserved = buffer.capacity() - remain;
+        while buffer.inserted() != reserved + buffer.priority_len() {
+            hint::spin_loop();
+        }
+        for buffer in &self.buffers {
+            buffer.clear(buffer.len());
+        }
+        tmp.clear();
+        self.tmp_len.store(0, Ordering::Relaxed);
+        self.tmp_size.store(0, Ordering::Relaxed);
+        for first_enqueue in &self.first_enqueue {
+            first_enqueue.store(0, Ordering::Relaxed);
+        }
+        let closed_flags = match reset {
+            true => {
+                #[cfg(feature = "metrics")]
+                self.reset_stats();
+                self.latency.store(0, Ordering::Relaxed);
+                self.pending_dequeue_count.store(0, Ordering::Relaxed);
+                self.clear_capacity_history();
+                0
+            }
+            false => buffer_remain & CLOSED_FLAGS,
+        };
+        drop(tmp);
+        self.buffer_remain.store(
+            buffer_index | (buffer.capacity() << 1) | closed_flags,
+            Ordering::Release,
+        );
+        self.pending_dequeue.store(buffer_index, Ordering::Relaxed);
+    }
+
+    /// Move out the pending items, in order, leaving the queue empty, e.g. to give pooled
+    /// buffers back on shutdown.
+    ///
+    /// Items not iterated are dropped with the iterator; the queue closing state is kept. The
+    /// items of a leaked batch, e.g. a forgotten [`Vectored`], are leaked.
+    pub fn drain(&mut self) -> DrainIter<'_, T> {
+        let mut pending_dequeue = self.pending_dequeue.load(Ordering::Relaxed);
+        if pending_dequeue == usize::MAX {
+            // the leaked batch state is unknown, so the swapped buffer is reset as is
+            let leaked = (self.buffer_remain.load(Ordering::Relaxed) & 1) ^ 1;
+            self.buffers[leaked].leak();
+            let poison = self.poison.load(Ordering::Relaxed);
+            if poison != NOT_POISONED && poison & 1 == leaked {
+                self.poison_len.store(0, Ordering::Relaxed);
+                self.poison.store(NOT_POISONED, Ordering::Relaxed);
+            }
+            pending_dequeue = leaked ^ 1;
+        }
+        let pending_dequeue = self.skip_poisoned_slot(pending_dequeue);
+        let buffer_remain = self.buffer_remain.load(Ordering::Relaxed);
+        let (buffer_index, remain, _) = decode(buffer_remain);
+        let capacity = self.buffers[buffer_index].capacity();
+        let len = capacity - remain + self.buffers[buffer_index].priority_len();
+        let tmp = {
+            let mut tmp = lock(&self.tmp);
+            let alloc = tmp.allocator().clone();
+            mem::replace(&mut *tmp, AllocVec::new_in(alloc))
+        };
+        self.tmp_len.store(0, Ordering::Relaxed);
+        self.tmp_size.store(0, Ordering::Relaxed);
+        self.buffer_remain.store(
+            buffer_index | (capacity << 1) | (buffer_remain & CLOSED_FLAGS),
+            Ordering::Relaxed,
+        );
+        self.pending_dequeue.store(buffer_index, Ordering::Relaxed);
+        DrainIter {
+            items: Box::new(
+                self.drain_buffers(pending_dequeue, buffer_index, len)
+                    .chain(tmp),
+            ),
+        }
+    }
+
+    /// Move out the items left in a closed queue, in order, e.g. to recover them on shutdown.
+    ///
+    /// Unlike [`drain`](Self::drain), it can be called on a shared queue. An empty vector is
+    /// returned if the queue is not closed for enqueuing, and [`DequeueError::Conflict`] if
+    /// it is being dequeued. The queue is left empty and can be reused after
+    /// [`reopen`](Self::reopen).
+    pub fn drain_closed(&self) -> Result<Vec<T>, DequeueError> {
+        let pending_dequeue = self.claim_dequeue()?;
+        if self.buffer_remain.load(Ordering::Relaxed) & ENQUEUE_CLOSED_FLAG == 0 {
+            self.pending_dequeue
+                .store(pending_dequeue, Ordering::Relaxed);
+            return Ok(Vec::new());
+        }
+        let pending_dequeue = self.skip_poisoned_slot(pending_dequeue);
+        let mut tmp = lock(&self.tmp);
+        // no more slots can be reserved, but reserved ones must be inserted before draining
+        let buffer_remain = self
+            .buffer_remain
+            .fetch_and(1 | CLOSED_FLAGS, Ordering::Acquire);
+        let (buffer_index, remain, _) = decode(buffer_remain);
+        let buffer = &self.buffers[buffer_index];
+        let len = buffer.capacity() - remain + buffer.priority_len();
+        let pending_len = pending_dequeue >> 1;
+        while buffer.inserted() != len
+            || (pending_len > 0 && self.buffers[pending_dequeue & 1].inserted() != pending_len)
+        {
+            hint::spin_loop();
+        }
+        let mut items = Vec::with_capacity(pending_len + len + tmp.len());
+        items.extend(self.drain_buffers(pending_dequeue, buffer_index, len));
+        items.extend(tmp.drain(..));
+        self.tmp_len.store(0, Ordering::Relaxed);
+        self.tmp_size.store(0, Ordering::Relaxed);
+        drop(tmp);
+        self.buffer_remain.store(
+            buffer_index | (buffer.capacity() << 1) | (buffer_remain & CLOSED_FLAGS),
+            Ordering::Release,
+        );
+        self.pending_dequeue.store(buffer_index, Ordering::Relaxed);
+        Ok(items)
+    }
+
+    // Items must be drained until the end, and dequeuing claimed or the queue borrowed mutably
+    fn drain_buffers(
+        &self,
+        pending_dequeue: usize,
+        buffer_index: usize,
+        len: usize,
+    ) -> impl Iterator<Item = T> + '_ {
+        // without pending batch, the pending buffer is the enqueuing one
+        let pending_len = pending_dequeue >> 1;
+        let pending = &self.buffers[pending_dequeue & 1];
+        let pending_items = (pending_len > 0).then(|| pending.drain_items(pending_len));
+        pending_items
+            .into_iter()
+            .flatten()
+            .chain(self.buffers[buffer_index].drain_items(len))
+    }
+
+    /// Whether a producer panicked while inserting an item, leaving its slot unfilled.
+    ///
+    /// A poisoned queue rejects enqueuing as if it were closed, and dequeuing returns
+    /// [`DequeueError::Poisoned`], until [`clear_poison`](Self::clear_poison) is called.
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.load(Ordering::Relaxed) != NOT_POISONED
+    }
+
+    /// Skip the slots left unfilled by a panicking producer, or by a shorter iterator, and
+    /// resume enqueuing and dequeuing; the item of the panicking insertion is leaked.
+    ///
+    /// It waits for the insertions in progress to complete. [`DequeueError::Conflict`] is
+    /// returned if the queue is being dequeued.
+    pub fn clear_poison(&self) -> Result<(), DequeueError> {
+        let pending_dequeue = self.claim_dequeue()?;
+        let pending_dequeue = self.skip_poisoned_slot(pending_dequeue);
+        self.pending_dequeue
+            .store(pending_dequeue, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // Dequeuing must be claimed; return the pending dequeuing without the unfilled slot
+    fn skip_poisoned_slot(&self, pending_dequeue: usize) -> usize {
+        let poison = self.poison.load(Ordering::Acquire);
+        if poison == NOT_POISONED {
+            return pending_dequeue;
+        }
+        // high priority insertions are excluded while the overflow lock is held
+        let _tmp = lock(&self.tmp);
+        // the number of unfilled slots is written right after the poison
+        let len = loop {
+            match self.poison_len.load(Ordering::Acquire) {
+                0 => hint::spin_loop(),
+                len => break len,
+            }
+        };
+        let (buffer_index, slot) = (poison & 1, poison >> 1);
+        let buffer = &self.buffers[buffer_index];
+        let dead = HIGH_PRIORITY_SLOTS + buffer.capacity() - slot;
+        let dead = dead..dead + len;
+        let (pending_index, pending_len) = (pending_dequeue & 1, pending_dequeue >> 1);
+        let pending_dequeue = if pending_len > 0 && pending_index == buffer_index {
+            // the buffer has been swapped, so no more slots can be reserved
+            let regular = pending_len - buffer.priority_len();
+            while buffer.inserted() != pending_len - len {
+                hint::spin_loop();
+            }
+            buffer.remove_dead(dead, HIGH_PRIORITY_SLOTS + regular);
+            if pending_len == len {
+                buffer.clear(0);
+                pending_index ^ 1
+            } else {
+                buffer_index | ((pending_len - len) << 1)
+            }
+        } else {
+            // no more slots can be reserved, until the unfilled one is given back
+            let (_, remain, _) = decode(
+                self.buffer_remain
+                    .fetch_and(1 | CLOSED_FLAGS, Ordering::Acquire),
+            );
+            let regular = buffer.capacity() - remain;
+            while buffer.inserted() != regular - len + buffer.priority_len() {
+                hint::spin_loop();
+            }
+            buffer.remove_dead(dead, HIGH_PRIORITY_SLOTS + regular);
+            self.buffer_remain
+                .fetch_add((remain + len) << 1, Ordering::Release);
+            pending_dequeue
+        };
+        self.poison_len.store(0, Ordering::Relaxed);
+        self.poison.store(NOT_POISONED, Ordering::Relaxed);
+        pending_dequeue
+    }
+
+    // Pass the pending items, in order, to `f`, while preventing them from being dequeued
+    #[cfg(feature = "persist")]
+    pub(crate) fn with_contents<R>(
+        &self,
+        f: impl FnOnce(&[&[u8]]) -> R,
+    ) -> Result<R, DequeueError> {
+        if self.is_poisoned() {
+            return Err(DequeueError::Poisoned);
+        }
+        let pending_dequeue = self.claim_dequeue()?;
+        let res = {
+            let tmp = lock(&self.tmp);
+            let contents: Vec<_> = self.contents(pending_dequeue, &tmp).collect();
+            f(&contents)
+        };
+        self.pending_dequeue
+            .store(pending_dequeue, Ordering::Relaxed);
+        Ok(res)
+    }
+
+    fn claim_dequeue(&self) -> Result<usize, DequeueError> {
+        match self.pending_dequeue.swap(usize::MAX, Ordering::Relaxed) {
+            usize::MAX => Err(DequeueError::Conflict),
+            pending_dequeue => Ok(pending_dequeue),
+        }
+    }
+
+    // Dequeuing must be claimed, so buffers can neither be swapped nor cleared
+    fn contents<'a>(
+        &'a self,
+        pending_dequeue: usize,
+        tmp: &'a [T],
+    ) -> impl Iterator<Item = &'a [u8]> {
+        let pending_buffer = &self.buffers[pending_dequeue & 1];
+        let pending_len = pending_dequeue >> 1;
+        while pending_len > 0 && pending_buffer.inserted() != pending_len {
+            hint::spin_loop();
+        }
+        // insertions in progress must complete, but new ones can still start, so the number of
+        // reserved slots must be stable while checking completed insertions
+        let (buffer, len) = loop {
+            let buffer_remain = self.buffer_remain.load(Ordering::Acquire);
+            let (buffer_index, remain, _) = decode(buffer_remain);
+            let buffer = &self.buffers[buffer_index];
+            let len = buffer.capacity() - remain + buffer.priority_len();
+            if buffer.inserted() == len
+                && self.buffer_remain.load(Ordering::Acquire) == buffer_remain
+            {
+                break (buffer, len);
+            }
+            hint::spin_loop();
+        };
+        // without pending batch, the pending buffer is the enqueuing one
+        let pending_items = (pending_len > 0).then(|| pending_buffer.items(pending_len));
+        pending_items
+            .into_iter()
+            .flatten()
+            .chain(buffer.items(len))
+            .chain(tmp)
+            .map(AsRef::as_ref)
+    }
+
+    /// Check the internal consistency of a quiescent queue, panicking on violation; it does
+    /// nothing without `debug_assertions`.
+    ///
+    /// It must not be called concurrently with enqueuing or dequeuing.
+    pub fn debug_assert_invariants(&self) {
+        #[cfg(debug_assertions)]
+        if let Err(err) = self.verify_invariants() {
+            panic!("queue invariant violated: {err}");
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    pub(crate) fn verify_invariants(&self) -> Result<(), InvariantViolation> {
+        let tmp = lock(&self.tmp);
+        if tmp.len() != self.tmp_len.load(Ordering::Relaxed) {
+            return Err(InvariantViolation::OverflowLen {
+                len: tmp.len(),
+                counter: self.tmp_len.load(Ordering::Relaxed),
+            });
+        }
+        let size = tmp.iter().map(|bytes| bytes.as_ref().len()).sum();
+        if size != self.tmp_size.load(Ordering::Relaxed) {
+            return Err(InvariantViolation::OverflowSize {
+                size,
+                counter: self.tmp_size.load(Ordering::Relaxed),
+            });
+        }
+        let (buffer_index, remain, _) = decode(self.buffer_remain.load(Ordering::Relaxed));
+        let capacity = self.buffers[buffer_index].capacity();
+        if remain > capacity {
+            return Err(InvariantViolation::Remain { remain, capacity });
+        }
+        let pending_dequeue = self.pending_dequeue.load(Ordering::Relaxed);
+        // a partially released batch may be the previous buffer
+        if pending_dequeue != usize::MAX
+            && pending_dequeue >> 1 == 0
+            && pending_dequeue & 1 != buffer_index
+        {
+            return Err(InvariantViolation::DequeueIndex {
+                dequeue_index: pending_dequeue & 1,
+                buffer_index,
+            });
+        }
+        for (buffer_index, buffer) in self.buffers.iter().enumerate() {
+            let items_size = buffer.items_size();
+            if items_size != buffer.total_size() {
+                return Err(InvariantViolation::TotalSize {
+                    buffer_index,
+                    total_size: buffer.total_size(),
+                    items_size,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Dropped items may enqueue re-entrantly: it is safe, as the released buffer cannot be the
+    // enqueuing one until dequeuing ends, i.e. until the buffer is reset. Ending dequeuing
+    // before would let a re-entrant dequeuing swap buffers while items are still dropped.
+    pub(crate) fn release(&self, buffer_index: usize, count: usize, token: Option<&mut usize>) {
+        let size = self.buffers[buffer_index].total_size();
+        let released = self.buffers[buffer_index].release(count);
+        self.count_dequeued(buffer_index, count, size, released);
+        self.end_dequeue(buffer_index, released, token);
+    }
+
+    // The size of the released items is the decrease of the buffer total size, which producers
+    // cannot modify while it is dequeued
+    fn count_dequeued(&self, buffer_index: usize, count: usize, size: usize, released: bool) {
+        let remaining = match released {
+            true => 0,
+            false => self.buffers[buffer_index].total_size(),
+        };
+        self.stats.dequeued(count, size - remaining);
+    }
+
+    pub(crate) fn rollback(&self, buffer_index: usize, count: usize, token: Option<&mut usize>) {
+        self.buffers[buffer_index].unget(count);
+        self.end_dequeue(buffer_index, false, token);
+    }
+
+    pub(crate) fn gotten_items(
+        &self,
+        buffer_index: usize,
+        count: usize,
+    ) -> impl Iterator<Item = (&T, u64)> {
+        self.buffers[buffer_index].gotten_items(count)
+    }
+
+    pub(crate) fn release_into_vec(
+        &self,
+        buffer_index: usize,
+        count: usize,
+        token: Option<&mut usize>,
+    ) -> Vec<T> {
+        let size = self.buffers[buffer_index].total_size();
+        let (items, released) = self.buffers[buffer_index].drain(count);
+        self.count_dequeued(buffer_index, count, size, released);
+        self.end_dequeue(buffer_index, released, token);
+        items
+    }
+
+    // Remaining items of a partially released batch are still pending
+    fn end_dequeue(&self, buffer_index: usize, released: bool, token: Option<&mut usize>) {
+        let pending_dequeue = match released {
+            true => !buffer_index & 1,
+            false => buffer_index | (self.buffers[buffer_index].len() << 1),
+        };
+        self.end_claim(token, pending_dequeue);
+    }
+}
+
+#[cfg(debug_assertions)]
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum InvariantViolation {
+    #[error("buffer {buffer_index} total size is {total_size} but items size is {items_size}")]
+    TotalSize {
+        buffer_index: usize,
+        total_size: usize,
+        items_size: usize,
+    },
+    #[error("overflow length is {len} but its counter is {counter}")]
+    OverflowLen { len: usize, counter: usize },
+    #[error("overflow size is {size} but its counter is {counter}")]
+    OverflowSize { size: usize, counter: usize },
+    #[error("remaining slots {remain} exceed the capacity {capacity}")]
+    Remain { remain: usize, capacity: usize },
+    #[error("dequeuing buffer {dequeue_index} is not the enqueuing one {buffer_index}")]
+    DequeueIndex {
+        dequeue_index: usize,
+        buffer_index: usize,
+    },
+}
+
+pub enum TryDequeueResult<'a, T, A: Allocator + Clone = Global>
+where
+    T: AsRef<[u8]>,
+{
+    Empty,
+    Pending,
+    Vectored(Vectored<'a, T, A>),
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for TryDequeueResult<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.debug_struct("TryDequeueResult::Empty").finish(),
+            Self::Pending => f.debug_struct("TryDequeueResult::Pending").finish(),
+            Self::Vectored(v) => f
+                .debug_tuple("TryDequeueResult::Vectored")
+                .field(v)
+                .finish(),
+        }
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> TryDequeueResult<'a, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn vectored(self) -> Option<Vectored<'a, T, A>> {
+        match self {
+            Self::Vectored(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> From<TryDequeueResult<'a, T, A>> for Option<Vectored<'a, T, A>>
+where
+    T: AsRef<[u8]>,
+{
+    fn from(res: TryDequeueResult<'a, T, A>) -> Self {
+        res.vectored()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell, io::IoSlice, iter, mem, ops::Deref, panic, slice, sync::atomic::AtomicUsize,
+        thread, time::Duration,
+    };
+
+    use crate::{
+        buffer::{HIGH_PRIORITY_SLOTS, MAX_TOTAL_SIZE},
+        clock::MockClock,
+        error::{DequeueError, EnqueueError, TransferError, TryEnqueueError},
+        primitives::Ordering,
+        queue::{decode, TryDequeueResult, VectoredQueue},
+        vectored::Vectored,
+    };
+
+    #[test]
+    fn it_works() {
+        let queue = VectoredQueue::with_capacity(4);
+        // let b1 = vec![0, 1].into_boxed_slice();
+        let b2 = vec![2].into_boxed_slice();
+        let b3 = vec![3].into_boxed_slice();
+        let b4 = vec![4, 5].into_boxed_slice();
+        let collect = |vectored: &Vectored<Box<[u8]>>| {
+            vectored
+                .iter()
+                .flat_map(|s| s.deref().iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        // queue.try_enqueue(b1).unwrap();
+        queue.try_enqueue(b2).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        queue.try_enqueue(b3).unwrap();
+        assert_eq!(vectored.total_size(), 1);
+        assert_eq!(collect(&vectored), vec![2]);
+        // assert_eq!(vectored.total_size(), 3);
+        // assert_eq!(collect(&vectored), vec![0, 1, 2]);
+        assert!(matches!(
+            queue.try_dequeue_vectored(),
+            Err(DequeueError::Conflict)
+        ));
+        drop(vectored);
+        queue.try_enqueue(b4).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 3);
+        assert_eq!(collect(&vectored), vec![3, 4, 5]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn enqueue_growing_stress() {
+        const PRODUCERS: usize = 8;
+        const ITEMS: usize = 1000;
+        let queue = VectoredQueue::new();
+        let mut received = vec![0; PRODUCERS];
+        thread::scope(|s| {
+            for producer in 0..PRODUCERS {
+                let queue = &queue;
+                s.spawn(move || {
+                    for i in 0..ITEMS {
+                        queue.enqueue_growing([producer as u8, i as u8]).unwrap();
+                    }
+                });
+            }
+            let mut count = 0;
+            while count < PRODUCERS * ITEMS {
+                match queue.try_dequeue_vectored().unwrap() {
+                    TryDequeueResult::Vectored(vectored) => {
+                        for slice in vectored.iter() {
+                            received[slice[0] as usize] += 1;
+                        }
+                        count += vectored.len();
+                    }
+                    _ => thread::yield_now(),
+                }
+            }
+        });
+        assert_eq!(received, vec![ITEMS; PRODUCERS]);
+        assert!(queue.is_empty());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_enqueue_batch_stress() {
+        const PRODUCERS: usize = 8;
+        const ITEMS: usize = 1000;
+        let queue = VectoredQueue::with_capacity(16);
+        let mut received = vec![vec![0; ITEMS]; PRODUCERS];
+        thread::scope(|s| {
+            for producer in 0..PRODUCERS {
+                let queue = &queue;
+                s.spawn(move || {
+                    let items = (0..ITEMS).map(|i| [producer as u8, (i >> 8) as u8, i as u8]);
+                    let mut items = items.peekable();
+                    while items.peek().is_some() {
+                        // batches of various sizes, up to the capacity
+                        let mut batch: Vec<_> = items.by_ref().take(1 + producer * 2).collect();
+                        while let Err(err) = queue.try_enqueue_batch(batch) {
+                            batch = err.inner();
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            let mut count = 0;
+            while count < PRODUCERS * ITEMS {
+                match queue.try_dequeue_vectored().unwrap() {
+                    TryDequeueResult::Vectored(vectored) => {
+                        for slice in vectored.iter() {
+                            received[slice[0] as usize]
+                                [(slice[1] as usize) << 8 | slice[2] as usize] += 1;
+                        }
+                        count += vectored.len();
+                    }
+                    _ => thread::yield_now(),
+                }
+            }
+        });
+        assert!(received.iter().flatten().all(|&count| count == 1));
+        assert!(queue.is_empty());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn drain() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug)]
+        struct Counted(u8);
+        impl AsRef<[u8]> for Counted {
+            fn as_ref(&self) -> &[u8] {
+                slice::from_ref(&self.0)
+            }
+        }
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut queue = VectoredQueue::with_capacity(2);
+        for i in 0..2 {
+            queue.try_enqueue(Counted(i)).unwrap();
+        }
+        // the rest of a partial batch is pending
+        drop(queue.try_dequeue_n(1).unwrap());
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        queue.try_enqueue(Counted(2)).unwrap();
+        {
+            let _priority = queue.mark_high_priority();
+            queue.try_enqueue(Counted(3)).unwrap();
+        }
+        queue.enqueue_unbounded(Counted(4)).unwrap();
+        queue.close();
+        let items: Vec<_> = queue.drain().map(|item| item.0).collect();
+        assert_eq!(items, [1, 3, 2, 4]);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 5);
+        assert!(queue.is_empty());
+        assert_eq!(queue.pending_bytes(), 0);
+        assert!(queue.is_closed());
+        queue.debug_assert_invariants();
+        // items not iterated are dropped with the iterator
+        queue.reopen();
+        for i in 0..3 {
+            queue.enqueue_unbounded(Counted(i)).unwrap();
+        }
+        assert_eq!(queue.drain().next().unwrap().0, 0);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 8);
+        queue.try_enqueue(Counted(0)).unwrap();
+        assert_eq!(
+            queue
+                .try_dequeue_vectored()
+                .unwrap()
+                .vectored()
+                .unwrap()
+                .len(),
+            1
+        );
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn drain_leaked_batch() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug)]
+        struct Counted(u8);
+        impl AsRef<[u8]> for Counted {
+            fn as_ref(&self) -> &[u8] {
+                slice::from_ref(&self.0)
+            }
+        }
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut queue = VectoredQueue::with_capacity(2);
+        for i in 0..2 {
+            queue.try_enqueue(Counted(i)).unwrap();
+        }
+        mem::forget(queue.try_dequeue_vectored().unwrap().vectored().unwrap());
+        // the items of the leaked batch are neither drained nor dropped
+        assert_eq!(queue.drain().count(), 0);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+        queue.try_enqueue(Counted(2)).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(
+            vectored
+                .into_vec()
+                .iter()
+                .map(|item| item.0)
+                .collect::<Vec<_>>(),
+            [2]
+        );
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn with_max_capacity() {
+        let queue = VectoredQueue::with_max_capacity(2, 5);
+        assert_eq!(queue.max_capacity(), 5);
+        for i in 0..5 {
+            queue.enqueue_unbounded(vec![i]).unwrap();
+        }
+        assert_eq!(queue.enqueue_unbounded(vec![5]), Err(EnqueueError(vec![5])));
+        assert_eq!(queue.len(), 5);
+        // the regular capacity, grown by overflowing items, is not affected
+        queue.set_max_capacity(1);
+        drop(queue.try_dequeue_vectored().unwrap());
+        for i in 5..7 {
+            queue.enqueue_unbounded(vec![i]).unwrap();
+        }
+        assert_eq!(queue.enqueue_unbounded(vec![7]), Err(EnqueueError(vec![7])));
+        queue.set_max_capacity(usize::MAX);
+        queue.enqueue_unbounded(vec![7]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [[2], [3], [4], [5], [6]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_dequeue_vectored_complete() {
+        // the second call computes the slice of the item during its insertion, after its
+        // slot is reserved, so the insertion can be delayed
+        struct Slow(AtomicUsize);
+        impl AsRef<[u8]> for Slow {
+            fn as_ref(&self) -> &[u8] {
+                if self.0.fetch_add(1, Ordering::Relaxed) == 1 {
+                    thread::sleep(Duration::from_millis(20));
+                }
+                b"slow"
+            }
+        }
+        let queue = VectoredQueue::with_capacity_and_spin(1, 0);
+        thread::scope(|s| {
+            s.spawn(|| queue.try_enqueue(Slow(AtomicUsize::new(0))));
+            // the slot is reserved before the insertion
+            while !queue.is_full() {
+                thread::yield_now();
+            }
+            assert!(matches!(
+                queue.try_dequeue_vectored(),
+                Ok(TryDequeueResult::Pending)
+            ));
+            let vectored = queue
+                .try_dequeue_vectored_complete()
+                .unwrap()
+                .vectored()
+                .unwrap();
+            assert!(vectored == b"slow"[..]);
+        });
+        assert!(matches!(
+            queue.try_dequeue_vectored_complete(),
+            Ok(TryDequeueResult::Empty)
+        ));
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn peek() {
+        let queue = VectoredQueue::with_capacity(2);
+        assert_eq!((queue.peek_len(), queue.peek_total_size()), (0, 0));
+        queue.try_enqueue(vec![0; 2]).unwrap();
+        queue.try_enqueue(vec![1; 3]).unwrap();
+        queue.enqueue_unbounded(vec![2; 4]).unwrap();
+        // overflowing items are dequeued in a later batch
+        assert_eq!((queue.peek_len(), queue.peek_total_size()), (2, 5));
+        let vectored = queue.try_dequeue_n(1).unwrap().vectored().unwrap();
+        // the enqueuing buffer is peeked while the batch is held
+        assert_eq!((queue.peek_len(), queue.peek_total_size()), (1, 4));
+        drop(vectored);
+        assert_eq!((queue.peek_len(), queue.peek_total_size()), (1, 3));
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!((vectored.len(), vectored.total_size()), (1, 3));
+        drop(vectored);
+        assert_eq!((queue.peek_len(), queue.peek_total_size()), (1, 4));
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert_eq!((queue.peek_len(), queue.peek_total_size()), (0, 0));
+        assert!(queue.is_empty());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn drain_closed() {
+        let queue = VectoredQueue::with_capacity(2);
+        for i in 0..2 {
+            queue.try_enqueue(vec![i]).unwrap();
+        }
+        drop(queue.try_dequeue_n(1).unwrap());
+        queue.try_enqueue(vec![2]).unwrap();
+        queue.enqueue_unbounded(vec![3]).unwrap();
+        // nothing is drained while the queue is open
+        assert_eq!(queue.drain_closed(), Ok(Vec::new()));
+        assert!(!queue.is_empty());
+        let vectored = queue.try_dequeue_n(1).unwrap().vectored().unwrap();
+        queue.close();
+        assert_eq!(queue.drain_closed(), Err(DequeueError::Conflict));
+        drop(vectored);
+        assert_eq!(queue.drain_closed(), Ok(vec![vec![2], vec![3]]));
+        assert!(queue.is_empty());
+        assert_eq!(queue.pending_bytes(), 0);
+        assert_eq!(queue.drain_closed(), Ok(Vec::new()));
+        queue.debug_assert_invariants();
+        queue.reopen();
+        queue.try_enqueue(vec![4]).unwrap();
+        queue.try_enqueue(vec![5]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored.eq_chunks(&[&[4], &[5]]));
+    }
+
+    #[test]
+    fn with_byte_capacity() {
+        let queue = VectoredQueue::with_byte_capacity(8, 4);
+        assert_eq!(queue.byte_capacity(), 4);
+        assert_eq!(VectoredQueue::<Vec<u8>>::new().byte_capacity(), usize::MAX);
+        // a single oversized item is accepted
+        queue.try_enqueue(vec![0; 8]).unwrap();
+        assert!(matches!(
+            queue.try_enqueue(vec![1]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        // the budget is reset when buffers are swapped
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        queue.try_enqueue(vec![1; 3]).unwrap();
+        queue.try_enqueue(vec![2]).unwrap();
+        assert_eq!(queue.pending_bytes(), 4);
+        assert!(matches!(
+            queue.try_enqueue(vec![3]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn set_byte_capacity() {
+        let queue = VectoredQueue::with_capacity(8);
+        queue.try_enqueue(vec![0; 4]).unwrap();
+        queue.set_byte_capacity(4);
+        // slots are free, but bytes are not
+        assert!(matches!(
+            queue.try_enqueue(vec![1]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        queue.set_byte_capacity(5);
+        queue.try_enqueue(vec![1]).unwrap();
+        drop(queue.try_dequeue_vectored().unwrap());
+        // an item larger than the bound is accepted by an empty queue
+        queue.try_enqueue(vec![2; 8]).unwrap();
+        assert!(matches!(
+            queue.try_enqueue(vec![3]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        queue.set_byte_capacity(usize::MAX);
+        queue.try_enqueue(vec![3]).unwrap();
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "maximum bytes must not be zero")]
+    fn zero_byte_capacity() {
+        VectoredQueue::<Vec<u8>>::with_byte_capacity(8, 0);
+    }
+
+    #[test]
+    fn split() {
+        let (producer, mut consumer) = VectoredQueue::with_capacity(4).split();
+        let mut items = Vec::new();
+        thread::scope(|s| {
+            for i in 0..4u8 {
+                let producer = producer.clone();
+                s.spawn(move || {
+                    for j in 0..100u8 {
+                        while producer.try_enqueue([i, j]).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            // the queue is closed once the last producer is dropped
+            drop(producer);
+            loop {
+                match consumer.try_dequeue_vectored() {
+                    Ok(TryDequeueResult::Vectored(vectored)) => items.extend(vectored.into_vec()),
+                    Ok(_) => thread::yield_now(),
+                    Err(err) => break assert_eq!(err, DequeueError::Closed),
+                }
+            }
+        });
+        assert_eq!(items.len(), 400);
+        items.sort();
+        items.dedup();
+        assert_eq!(items.len(), 400);
+        assert!(consumer.is_closed());
+        consumer.0.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_enqueue_batch_overflow() {
+        let queue = VectoredQueue::with_capacity(4);
+        let half = || vec![0; MAX_TOTAL_SIZE / 2 + 1];
+        // the summed size is checked before reserving
+        let Err(TryEnqueueError::Full(items)) = queue.try_enqueue_batch(vec![half(), half()])
+        else {
+            panic!("batch should overflow");
+        };
+        assert_eq!(items.len(), 2);
+        assert!(queue.is_empty());
+        // an empty buffer is not sealed, as it would never be swapped
+        queue.try_enqueue_batch(vec![half()]).unwrap();
+        assert!(queue.try_enqueue_batch(vec![half()]).is_err());
+        // the buffer is sealed to preserve enqueuing order
+        assert!(matches!(
+            queue.try_enqueue(vec![1]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        assert_eq!(queue.len(), 1);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), MAX_TOTAL_SIZE / 2 + 1);
+        drop(vectored);
+        queue
+            .try_enqueue_batch(vec![half(), vec![0; MAX_TOTAL_SIZE / 2]])
+            .unwrap();
+        assert_eq!(queue.pending_bytes(), MAX_TOTAL_SIZE);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), MAX_TOTAL_SIZE);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
 
     #[test]
-    fn it_works() {
+    fn try_enqueue_batch() {
         let queue = VectoredQueue::with_capacity(4);
-        // let b1 = vec![0, 1].into_boxed_slice();
-        let b2 = vec![2].into_boxed_slice();
-        let b3 = vec![3].into_boxed_slice();
-        let b4 = vec![4, 5].into_boxed_slice();
-        let collect = |vectored: &Vectored<Box<[u8]>>| {
-            vectored
-                .iter()
-                .flat_map(|s| s.deref().iter().cloned())
-                .collect::<Vec<_>>()
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.try_enqueue_batch(vec![vec![1], vec![2]]).unwrap();
+        // all or nothing
+        let err = queue.try_enqueue_batch(vec![vec![3], vec![4]]).unwrap_err();
+        assert!(matches!(err, TryEnqueueError::Full(ref items) if items.len() == 2));
+        assert_eq!(queue.len(), 3);
+        queue.try_enqueue_batch(Vec::new()).unwrap();
+        queue.try_enqueue_batch(err.inner()[..1].to_vec()).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [[0], [1], [2], [3]]);
+        queue.close();
+        assert!(matches!(
+            queue.try_enqueue_batch(vec![vec![5]]),
+            Err(TryEnqueueError::Closed(_))
+        ));
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn enqueue_unbounded_fifo() {
+        const ITEMS: u16 = 10000;
+        let queue = VectoredQueue::with_capacity(4);
+        let mut received = Vec::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..ITEMS {
+                    if i % 3 == 0 {
+                        queue.enqueue_unbounded(i.to_be_bytes()).unwrap();
+                    } else if let Err(err) = queue.try_enqueue(i.to_be_bytes()) {
+                        queue.enqueue_unbounded(err.inner()).unwrap();
+                    }
+                }
+            });
+            while received.len() < ITEMS as usize {
+                match queue.try_dequeue_vectored().unwrap() {
+                    TryDequeueResult::Vectored(vectored) => received.extend(
+                        vectored
+                            .iter()
+                            .map(|slice| u16::from_be_bytes([slice[0], slice[1]])),
+                    ),
+                    _ => thread::yield_now(),
+                }
+            }
+        });
+        assert_eq!(received, (0..ITEMS).collect::<Vec<_>>());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn total_size_overflow() {
+        let queue = VectoredQueue::with_capacity(4);
+        let big = || vec![0; MAX_TOTAL_SIZE / 2];
+        queue.try_enqueue(big()).unwrap();
+        queue.try_enqueue(big()).unwrap();
+        assert!(matches!(
+            queue.try_enqueue(big()),
+            Err(TryEnqueueError::Full(_))
+        ));
+        // the buffer is sealed to preserve enqueuing order
+        assert!(matches!(
+            queue.try_enqueue(vec![1]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        queue.enqueue_unbounded(big()).unwrap();
+        queue.enqueue_unbounded(big()).unwrap();
+        queue.enqueue_unbounded(big()).unwrap();
+        queue.enqueue_unbounded(vec![1]).unwrap();
+        assert_eq!(queue.len(), 6);
+        assert_eq!(queue.pending_bytes(), 5 * (MAX_TOTAL_SIZE / 2) + 1);
+        let mut sizes = Vec::new();
+        while let Some(vectored) = queue.try_dequeue_vectored().unwrap().vectored() {
+            assert!(vectored.total_size() <= MAX_TOTAL_SIZE);
+            sizes.push(vectored.iter().map(|s| s.len()).collect::<Vec<_>>());
+            queue.debug_assert_invariants();
+        }
+        let big_len = MAX_TOTAL_SIZE / 2;
+        assert_eq!(
+            sizes,
+            vec![vec![big_len; 2], vec![big_len; 2], vec![big_len, 1]]
+        );
+    }
+
+    #[test]
+    fn enqueue_unbounded_close_race() {
+        for _ in 0..100 {
+            let queue = VectoredQueue::new();
+            let enqueued = thread::scope(|s| {
+                let producers: Vec<_> = (0..2)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let mut count = 0;
+                            while count < 1000 && queue.enqueue_unbounded([0]).is_ok() {
+                                count += 1;
+                            }
+                            count
+                        })
+                    })
+                    .collect();
+                thread::yield_now();
+                queue.close();
+                producers
+                    .into_iter()
+                    .map(|p| p.join().unwrap())
+                    .sum::<usize>()
+            });
+            assert!(queue.enqueue_unbounded([0]).is_err());
+            let mut dequeued = 0;
+            loop {
+                match queue.try_dequeue_vectored() {
+                    Ok(TryDequeueResult::Vectored(vectored)) => dequeued += vectored.len(),
+                    Ok(_) => {}
+                    Err(DequeueError::Closed) => break,
+                    Err(err) => panic!("{err}"),
+                }
+            }
+            assert_eq!(dequeued, enqueued);
+            queue.debug_assert_invariants();
+        }
+    }
+
+    #[test]
+    fn content_eq() {
+        let queue = VectoredQueue::with_capacity(2);
+        let other = VectoredQueue::with_capacity(4);
+        for i in 0..3u8 {
+            queue.enqueue_unbounded(vec![i]).unwrap();
+            other.try_enqueue(vec![i]).unwrap();
+        }
+        assert!(queue.content_eq(&other).unwrap());
+        assert!(queue.content_eq(&queue).unwrap());
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(queue.content_eq(&other), Err(DequeueError::Conflict));
+        drop(vectored);
+        assert!(!queue.content_eq(&other).unwrap());
+        let remaining = VectoredQueue::with_capacity(1);
+        remaining.try_enqueue(vec![2]).unwrap();
+        assert!(queue.content_eq(&remaining).unwrap());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn high_priority() {
+        let queue = VectoredQueue::with_capacity(2);
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.try_enqueue(vec![1]).unwrap();
+        let guard = queue.mark_high_priority();
+        queue.try_enqueue(vec![2]).unwrap();
+        queue.try_enqueue(vec![3]).unwrap();
+        assert_eq!(queue.len(), 4);
+        let expected = [vec![2], vec![3], vec![0], vec![1]];
+        // other queues are not marked
+        let other = VectoredQueue::with_capacity(4);
+        for bytes in &expected {
+            other.try_enqueue(bytes.clone()).unwrap();
+        }
+        assert_eq!(other.len(), 4);
+        assert!(queue.content_eq(&other).unwrap());
+        drop(guard);
+        assert!(matches!(
+            queue.try_enqueue(vec![4]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        queue.debug_assert_invariants();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 4);
+        assert_eq!(vectored.into_vec(), expected);
+        // high priority items alone are dequeued too
+        let _guard = queue.mark_high_priority();
+        queue.try_enqueue(vec![5]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(&*vectored[0], &[5]);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn begin_dequeue() {
+        let queue = VectoredQueue::with_capacity(2);
+        assert!(queue.begin_dequeue().unwrap().is_none());
+        queue.try_enqueue(vec![0]).unwrap();
+        let guard = queue.mark_high_priority();
+        queue.try_enqueue(vec![1]).unwrap();
+        drop(guard);
+        let txn = queue.begin_dequeue().unwrap().unwrap();
+        assert_eq!(txn.len(), 2);
+        assert_eq!(queue.begin_dequeue().unwrap_err(), DequeueError::Conflict);
+        queue.try_enqueue(vec![2]).unwrap();
+        // rolled back
+        drop(txn);
+        let txn = queue.begin_dequeue().unwrap().unwrap();
+        assert_eq!(&*txn[0], &[1]);
+        assert_eq!(&*txn[1], &[0]);
+        assert_eq!(txn.total_size(), 2);
+        txn.commit();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![2]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_dequeue_with() {
+        let queue = VectoredQueue::with_capacity(4);
+        assert_eq!(queue.try_dequeue_with(|v| v.len()), Ok(None));
+        queue.try_enqueue(vec![0]).unwrap();
+        assert_eq!(queue.try_dequeue_with(|v| v.len()), Ok(Some(1)));
+        queue.try_enqueue(vec![1]).unwrap();
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            queue.try_dequeue_with(|_| panic!("closure panic"))
+        }));
+        assert!(res.is_err());
+        // the batch has been released
+        queue.try_enqueue(vec![2]).unwrap();
+        assert_eq!(queue.try_dequeue_with(|v| v.total_size()), Ok(Some(1)));
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_dequeue_n() {
+        let queue = VectoredQueue::with_capacity(4);
+        for i in 0..3 {
+            queue.try_enqueue(vec![i, i]).unwrap();
+        }
+        let guard = queue.mark_high_priority();
+        queue.try_enqueue(vec![3]).unwrap();
+        drop(guard);
+        let mut vectored = queue.try_dequeue_n(2).unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 3);
+        assert_eq!((&*vectored[0], &*vectored[1]), (&[3][..], &[0, 0][..]));
+        // the trailer slot is restored on release
+        *vectored.parts_mut().2 = IoSlice::new(&[42]);
+        drop(vectored);
+        assert_eq!(queue.len(), 0);
+        queue.try_enqueue(vec![4]).unwrap();
+        assert!(!queue.content_eq(&VectoredQueue::new()).unwrap());
+        queue.debug_assert_invariants();
+        let vectored = queue.try_dequeue_n(1).unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![1, 1]]);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 2);
+        assert!(vectored.full_slices()[0].is_empty());
+        assert_eq!(vectored.into_vec(), [vec![2, 2]]);
+        let vectored = queue.try_dequeue_n(usize::MAX).unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![4]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_dequeue_exact() {
+        let queue = VectoredQueue::with_capacity(4);
+        assert!(queue.try_dequeue_exact(2).unwrap().is_none());
+        queue.try_enqueue(vec![0]).unwrap();
+        assert!(queue.try_dequeue_exact(2).unwrap().is_none());
+        for i in 1..4 {
+            queue.try_enqueue(vec![i]).unwrap();
+        }
+        let vectored = queue.try_dequeue_exact(3).unwrap().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![0], vec![1], vec![2]]);
+        // the remaining item is not enough
+        assert!(queue.try_dequeue_exact(2).unwrap().is_none());
+        let vectored = queue.try_dequeue_exact(1).unwrap().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![3]]);
+        queue.close();
+        assert_eq!(
+            queue.try_dequeue_exact(1).unwrap_err(),
+            DequeueError::Closed
+        );
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn sampled_latency() {
+        let queue = VectoredQueue::with_capacity(4);
+        assert_eq!(queue.sampled_latency(), Duration::ZERO);
+        queue.try_enqueue(vec![0]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        queue.try_enqueue(vec![1]).unwrap();
+        drop(queue.try_dequeue_vectored().unwrap());
+        let latency = queue.sampled_latency();
+        assert!(latency >= Duration::from_millis(20));
+        queue.try_enqueue(vec![2]).unwrap();
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert!(queue.sampled_latency() < latency);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn mock_clock() {
+        let clock = MockClock::new();
+        let queue = VectoredQueue::builder()
+            .capacity(4)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        queue.try_enqueue(vec![0]).unwrap();
+        clock.advance(Duration::from_secs(8));
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert_eq!(queue.sampled_latency(), Duration::from_secs(8));
+        queue.try_enqueue(vec![1]).unwrap();
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert_eq!(queue.sampled_latency(), Duration::from_secs(7));
+        queue.debug_assert_invariants();
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn stats() {
+        use crate::stats::QueueStats;
+        let queue = VectoredQueue::with_capacity(2);
+        queue.try_enqueue(vec![0; 2]).unwrap();
+        {
+            let _priority = queue.mark_high_priority();
+            queue.try_enqueue(vec![1]).unwrap();
+        }
+        queue.try_enqueue(vec![2]).unwrap();
+        queue.try_enqueue(vec![3]).unwrap_err();
+        // overflowing items are not rejected
+        queue.enqueue_unbounded(vec![4; 3]).unwrap();
+        let stats = QueueStats {
+            enqueued_items: 4,
+            enqueued_bytes: 7,
+            rejected: 1,
+            ..Default::default()
         };
-        // queue.try_enqueue(b1).unwrap();
-        queue.try_enqueue(b2).unwrap();
+        assert_eq!(queue.stats(), stats);
+        drop(queue.try_dequeue_n(2).unwrap());
+        assert_eq!(queue.stats().dequeued_items, 2);
+        assert_eq!(queue.stats().dequeued_bytes, 3);
+        let items = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(items.into_vec(), [vec![2]]);
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert_eq!(
+            (queue.stats().dequeued_items, queue.stats().dequeued_bytes),
+            (4, 7)
+        );
+        queue.reset_stats();
+        assert_eq!(queue.stats(), QueueStats::default());
+        queue.debug_assert_invariants();
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn framed_stats() {
+        let queue = VectoredQueue::with_framer(2, |bytes: &[u8]| [bytes.len() as u8]);
+        queue.try_enqueue(vec![0; 2]).unwrap();
+        {
+            let _priority = queue.mark_high_priority();
+            queue.try_enqueue(vec![1]).unwrap();
+        }
+        queue.try_enqueue(vec![2]).unwrap();
+        // the header of an overflowing item is counted once framed
+        queue.enqueue_unbounded(vec![4; 3]).unwrap();
+        assert_eq!(
+            (queue.stats().enqueued_items, queue.stats().enqueued_bytes),
+            (4, 10)
+        );
+        assert_eq!(queue.pending_bytes(), 10);
+        queue.debug_assert_invariants();
+        drop(queue.try_dequeue_n(2).unwrap());
+        assert_eq!(
+            (queue.stats().dequeued_items, queue.stats().dequeued_bytes),
+            (2, 5)
+        );
+        queue.debug_assert_invariants();
+        drop(queue.try_dequeue_vectored().unwrap());
         let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
-        queue.try_enqueue(b3).unwrap();
-        assert_eq!(vectored.total_size(), 1);
-        assert_eq!(collect(&vectored), vec![2]);
-        // assert_eq!(vectored.total_size(), 3);
-        // assert_eq!(collect(&vectored), vec![0, 1, 2]);
+        assert_eq!(vectored.total_size(), 4);
+        assert_eq!(queue.stats().enqueued_bytes, 11);
+        drop(vectored);
+        assert_eq!(
+            (queue.stats().dequeued_items, queue.stats().dequeued_bytes),
+            (4, 11)
+        );
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn spin_limit() {
+        let queue = VectoredQueue::with_capacity_and_spin(4, 0);
+        // reserve a slot without inserting into it
+        let buffer_remain = queue.buffer_remain.fetch_sub(2, Ordering::Relaxed);
+        let (buffer_index, remain, _) = decode(buffer_remain);
         assert!(matches!(
             queue.try_dequeue_vectored(),
-            Err(DequeueError::Conflict)
+            Ok(TryDequeueResult::Pending)
         ));
+        // yielding lets the delayed insertion complete
+        queue.set_yield_limit(u32::MAX);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                queue.buffers[buffer_index].insert(remain, vec![0], 0);
+            });
+            let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+            assert_eq!(vectored.into_vec(), [[0]]);
+        });
+        assert_eq!(queue.pending_dequeue_count(), 1);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn pending_dequeue_count() {
+        let queue = VectoredQueue::with_capacity(4);
+        // reserve a slot without inserting into it
+        let buffer_remain = queue.buffer_remain.fetch_sub(2, Ordering::Relaxed);
+        let (buffer_index, remain, _) = decode(buffer_remain);
+        assert!(matches!(
+            queue.try_dequeue_vectored(),
+            Ok(TryDequeueResult::Pending)
+        ));
+        assert!(matches!(
+            queue.try_dequeue_vectored(),
+            Ok(TryDequeueResult::Pending)
+        ));
+        assert_eq!(queue.pending_dequeue_count(), 2);
+        queue.buffers[buffer_index].insert(remain, vec![0], 0);
+        assert_eq!(
+            queue
+                .try_dequeue_vectored()
+                .unwrap()
+                .vectored()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(queue.pending_dequeue_count(), 2);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn reentrant_enqueue_on_drop() {
+        struct Recycled {
+            bytes: Vec<u8>,
+            queue: Option<&'static VectoredQueue<Recycled>>,
+        }
+        impl AsRef<[u8]> for Recycled {
+            fn as_ref(&self) -> &[u8] {
+                &self.bytes
+            }
+        }
+        impl Drop for Recycled {
+            fn drop(&mut self) {
+                if let Some(queue) = self.queue {
+                    let sentinel = Recycled {
+                        bytes: vec![42],
+                        queue: None,
+                    };
+                    assert!(queue.try_enqueue(sentinel).is_ok());
+                    assert_eq!(
+                        queue.try_dequeue_vectored().unwrap_err(),
+                        DequeueError::Conflict
+                    );
+                }
+            }
+        }
+        let ptr = Box::into_raw(Box::new(VectoredQueue::with_capacity(4)));
+        let queue: &'static VectoredQueue<Recycled> = unsafe { &*ptr };
+        for i in 0..2 {
+            let item = Recycled {
+                bytes: vec![i],
+                queue: Some(queue),
+            };
+            assert!(queue.try_enqueue(item).is_ok());
+        }
+        drop(queue.try_dequeue_vectored().unwrap());
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.iter().map(|s| s[0]).collect::<Vec<_>>(), [42, 42]);
         drop(vectored);
-        queue.try_enqueue(b4).unwrap();
+        queue.debug_assert_invariants();
+        // the remaining items don't reference the queue
+        drop(unsafe { Box::from_raw(ptr) });
+    }
+
+    #[test]
+    fn capacity_history() {
+        let queue = VectoredQueue::<[u8; 1]>::with_capacity(1);
+        queue.set_capacity(1);
+        assert!(queue.capacity_history().is_empty());
+        for capacity in 2..20 {
+            queue.set_capacity(capacity);
+        }
+        queue.set_capacity(10);
+        assert_eq!(queue.capacity_history(), (4..20).collect::<Vec<_>>());
+        queue.clear_capacity_history();
+        assert!(queue.capacity_history().is_empty());
+        // growth of unbounded enqueuing
+        let queue = VectoredQueue::with_capacity(1);
+        queue.try_enqueue([0]).unwrap();
+        queue.enqueue_unbounded([1]).unwrap();
+        queue.enqueue_unbounded([2]).unwrap();
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert_eq!(queue.capacity_history(), [3]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_after_close() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.try_enqueue(vec![1]).unwrap();
+        queue.close();
         let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
-        assert_eq!(vectored.total_size(), 3);
-        assert_eq!(collect(&vectored), vec![3, 4, 5]);
+        assert_eq!(vectored.into_vec(), [vec![0], vec![1]]);
+        assert_eq!(
+            queue.try_dequeue_vectored().unwrap_err(),
+            DequeueError::Closed
+        );
+        queue.debug_assert_invariants();
+        let queue = VectoredQueue::with_capacity(2);
+        for i in 0..2 {
+            queue.try_enqueue(vec![i]).unwrap();
+        }
+        drop(queue.try_dequeue_n(1).unwrap());
+        queue.try_enqueue(vec![2]).unwrap();
+        {
+            let _priority = queue.mark_high_priority();
+            queue.try_enqueue(vec![3]).unwrap();
+        }
+        queue.enqueue_unbounded(vec![4]).unwrap();
+        queue.enqueue_unbounded(vec![5]).unwrap();
+        queue.close();
+        // pending, enqueuing and overflowing items are all dequeued before closing
+        let mut items = Vec::new();
+        let err = loop {
+            match queue.try_dequeue_vectored() {
+                Ok(res) => items.extend(res.vectored().unwrap().into_vec()),
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err, DequeueError::Closed);
+        assert_eq!(items, [[1], [3], [2], [4], [5]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn inline_threshold() {
+        let bytes = |slices: &[IoSlice]| -> Vec<u8> {
+            slices.iter().flat_map(|s| s.iter().copied()).collect()
+        };
+        let queue = VectoredQueue::builder()
+            .capacity(4)
+            .inline_threshold(2)
+            .build()
+            .unwrap();
+        let footprint = VectoredQueue::<Vec<u8>>::with_capacity(4).memory_footprint();
+        assert_eq!(
+            queue.memory_footprint(),
+            footprint + 2 * (HIGH_PRIORITY_SLOTS + 4) * 2
+        );
+        queue.try_enqueue(vec![1, 1]).unwrap();
+        queue.try_enqueue(vec![2, 2, 2]).unwrap();
+        queue.try_enqueue(vec![3]).unwrap();
+        {
+            let _guard = queue.mark_high_priority();
+            queue.try_enqueue(vec![0]).unwrap();
+            queue.try_enqueue(vec![0, 0, 0]).unwrap();
+            queue.try_enqueue(vec![0, 1]).unwrap();
+        }
+        let expected = [0, 0, 0, 0, 0, 1, 1, 1, 2, 2, 2, 3];
+        // a rolled back batch is gotten again with its inlined slices
+        let txn = queue.begin_dequeue().unwrap().unwrap();
+        assert_eq!(bytes(&txn), expected);
+        // small items are gathered from consecutive arena blocks
+        assert_eq!(unsafe { txn[5].as_ptr().offset_from(txn[3].as_ptr()) }, 4);
+        drop(txn);
+        // the trailer slot of a partial batch is restored into the arena
+        let vectored = queue.try_dequeue_n(3).unwrap().vectored().unwrap();
+        assert_eq!(bytes(vectored.full_slices()), expected[..6]);
+        drop(vectored);
+        let vectored = queue.try_dequeue_n(3).unwrap().vectored().unwrap();
+        assert_eq!(bytes(vectored.full_slices()), expected[6..]);
+        assert_eq!(
+            unsafe { vectored[2].as_ptr().offset_from(vectored[0].as_ptr()) },
+            4
+        );
+        assert_eq!(vectored.into_vec(), [vec![1, 1], vec![2, 2, 2], vec![3]]);
+        // the arena grows with the buffers
+        for i in 0..6 {
+            queue.enqueue_unbounded(vec![i; i as usize % 3]).unwrap();
+        }
+        let mut dequeued = Vec::new();
+        while let Some(vectored) = queue.try_dequeue_vectored().unwrap().vectored() {
+            dequeued.extend(bytes(vectored.full_slices()));
+        }
+        assert_eq!(dequeued, [1, 2, 2, 4, 5, 5]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn framer() {
+        let bytes = |slices: &[IoSlice]| -> Vec<u8> {
+            slices.iter().flat_map(|s| s.iter().copied()).collect()
+        };
+        let queue = VectoredQueue::with_framer(4, |bytes: &[u8]| {
+            u32::try_from(bytes.len()).unwrap().to_be_bytes()
+        });
+        queue.try_enqueue(vec![1]).unwrap();
+        queue.try_enqueue(vec![2, 2]).unwrap();
+        {
+            let _guard = queue.mark_high_priority();
+            queue.try_enqueue(vec![0]).unwrap();
+            queue.try_enqueue(vec![]).unwrap();
+        }
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.pending_bytes(), 4 * 4 + 4);
+        // each payload slice is preceded by its header slice, high priority items first
+        let expected = [
+            &[0, 0, 0, 1, 0][..],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 1, 1],
+            &[0, 0, 0, 2, 2, 2],
+        ]
+        .concat();
+        // a rolled back batch is gotten again with its headers
+        let txn = queue.begin_dequeue().unwrap().unwrap();
+        assert_eq!(txn.len(), 8);
+        assert_eq!(txn.total_size(), expected.len());
+        assert_eq!(bytes(&txn), expected);
+        drop(txn);
+        // the trailer slot of a partial batch is restored with the next header
+        let vectored = queue.try_dequeue_n(3).unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 14);
+        assert_eq!(bytes(vectored.full_slices()), expected[..14]);
+        assert_eq!(vectored.into_vec(), [vec![0], vec![], vec![1]]);
+        let vectored = queue.try_dequeue_n(3).unwrap().vectored().unwrap();
+        assert_eq!(bytes(vectored.full_slices()), expected[14..]);
+        drop(vectored);
+        assert_eq!(queue.pending_bytes(), 0);
+        // headers grow with the buffers
+        for i in 0..6 {
+            queue.enqueue_unbounded(vec![i; i as usize % 3]).unwrap();
+        }
+        let mut dequeued = Vec::new();
+        while let Some(vectored) = queue.try_dequeue_vectored().unwrap().vectored() {
+            assert_eq!(vectored.len() % 2, 0);
+            dequeued.extend(bytes(&vectored));
+        }
+        let headers = [0, 1, 2, 0, 1, 2].map(|len| [0, 0, 0, len]);
+        let payloads = [&[][..], &[1], &[2, 2], &[], &[4], &[5, 5]];
+        let expected: Vec<u8> = headers
+            .iter()
+            .zip(payloads)
+            .flat_map(|(header, payload)| header.iter().chain(payload).copied())
+            .collect();
+        assert_eq!(dequeued, expected);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn varint_framer() {
+        fn varint(bytes: &[u8]) -> Vec<u8> {
+            let (mut len, mut header) = (bytes.len(), Vec::new());
+            while len >= 0x80 {
+                header.push(len as u8 | 0x80);
+                len >>= 7;
+            }
+            header.push(len as u8);
+            header
+        }
+        let queue = VectoredQueue::builder()
+            .capacity(4)
+            .inline_threshold(2)
+            .framer(varint)
+            .build()
+            .unwrap();
+        let items = [vec![1; 300], vec![2], vec![3; 127], vec![4; 128]];
+        for item in items.clone() {
+            queue.try_enqueue(item).unwrap();
+        }
+        // pending bytes include the frame headers
+        assert_eq!(queue.pending_bytes(), 300 + 2 + 1 + 1 + 127 + 1 + 128 + 2);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 300 + 2 + 1 + 1 + 127 + 1 + 128 + 2);
+        let expected: Vec<u8> = items
+            .iter()
+            .flat_map(|item| varint(item).into_iter().chain(item.iter().copied()))
+            .collect();
+        assert!(vectored == expected[..]);
+        assert!(vectored.eq_chunks(&[
+            &[0xac, 0x02],
+            &[1; 300],
+            &[1],
+            &[2],
+            &[127],
+            &[3; 127],
+            &[0x80, 0x01],
+            &[4; 128]
+        ]));
+        drop(vectored);
+        // headers are limited to 15 bytes; the item is leaked, so it doesn't allocate
+        let queue = VectoredQueue::with_framer(1, |_: &[u8]| [0; 16]);
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| queue.try_enqueue(&b"a"[..])));
+        assert!(res.is_err());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn poison() {
+        // panic on the given call of `as_ref`
+        #[derive(Debug)]
+        // the panicking item is leaked, so it doesn't allocate
+        struct Item(&'static [u8], std::cell::Cell<usize>);
+        impl AsRef<[u8]> for Item {
+            fn as_ref(&self) -> &[u8] {
+                self.1.set(self.1.get() - 1);
+                assert_ne!(self.1.get(), 0, "panicking item");
+                self.0
+            }
+        }
+        let item = |bytes: &'static [u8], panic_on: usize| Item(bytes, panic_on.into());
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(item(b"a", usize::MAX)).unwrap();
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            queue.try_enqueue(item(b"b", 2)).ok();
+        }));
+        assert!(res.is_err() && queue.is_poisoned());
+        assert_eq!(
+            queue.try_dequeue_vectored().unwrap_err(),
+            DequeueError::Poisoned
+        );
+        assert!(matches!(
+            queue.try_enqueue(item(b"c", usize::MAX)),
+            Err(TryEnqueueError::Closed(_))
+        ));
+        assert!(queue
+            .try_enqueue_many(&mut [item(b"c", usize::MAX)].into_iter().peekable())
+            .is_err());
+        // the unfilled slot is skipped, and enqueuing resumes in it
+        queue.clear_poison().unwrap();
+        assert!(!queue.is_poisoned());
+        queue.try_enqueue(item(b"c", usize::MAX)).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored.eq_chunks(&[b"a", b"c"]));
+        let items = vectored.into_vec();
+        assert_eq!(
+            items.iter().map(|item| item.0).collect::<Vec<_>>(),
+            [b"a", b"c"]
+        );
+        assert!(queue.try_dequeue_vectored().unwrap().vectored().is_none());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn consumer_token() {
+        let queue = VectoredQueue::with_capacity(2);
+        let other = VectoredQueue::<Vec<u8>>::with_capacity(2);
+        let mut token = queue.consumer_token().unwrap();
+        assert!(queue.consumer_token().is_none());
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.try_enqueue(vec![1]).unwrap();
+        assert_eq!(
+            queue.try_dequeue_vectored().unwrap_err(),
+            DequeueError::Conflict
+        );
+        let vectored = queue
+            .try_dequeue_token(&mut token)
+            .unwrap()
+            .vectored()
+            .unwrap();
+        assert_eq!(vectored.into_vec(), [vec![0], vec![1]]);
+        queue.try_enqueue(vec![2]).unwrap();
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            other.try_dequeue_token(&mut token).ok();
+        }));
+        assert!(res.is_err());
+        // the dequeuing state is handed back to the queue with the token
+        drop(token);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(queue.consumer_token().is_none());
+        assert_eq!(vectored.into_vec(), [vec![2]]);
+        let mut token = queue.consumer_token().unwrap();
+        assert!(queue
+            .try_dequeue_token(&mut token)
+            .unwrap()
+            .vectored()
+            .is_none());
+        queue.try_enqueue(vec![3]).unwrap();
+        let vectored = queue
+            .try_dequeue_token(&mut token)
+            .unwrap()
+            .vectored()
+            .unwrap();
+        assert_eq!(vectored.into_vec(), [vec![3]]);
+        drop(token);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn static_slices() {
+        let queue = VectoredQueue::<&'static [u8]>::with_capacity(4);
+        for bytes in [&b"ab"[..], b"c", b"def"] {
+            queue.try_enqueue(bytes).unwrap();
+        }
+        let vectored = queue.try_dequeue_n(2).unwrap().vectored().unwrap();
+        assert!(vectored.eq_chunks(&[b"ab", b"c"]));
+        drop(vectored);
+        // the size of the released items is still accounted for a partial release
+        assert_eq!(queue.buffers[0].total_size(), 3);
+        queue.try_enqueue(b"gh").unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored.eq_chunks(&[b"def"]));
+        drop(vectored);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [b"gh"]);
+        assert!(queue.is_empty());
+        assert_eq!(
+            queue.buffers[0].total_size() + queue.buffers[1].total_size(),
+            0
+        );
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn half_close() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.close_enqueue();
+        assert!(queue.is_closed() && !queue.is_dequeue_closed());
+        assert!(matches!(
+            queue.try_enqueue(vec![1]),
+            Err(TryEnqueueError::Closed(_))
+        ));
+        queue.reopen_enqueue();
+        queue.try_enqueue(vec![1]).unwrap();
+        // closing dequeuing stops delivering, and rejects enqueuing, even reopened
+        queue.close_dequeue();
+        assert_eq!(
+            queue.try_dequeue_vectored().unwrap_err(),
+            DequeueError::Closed
+        );
+        assert!(queue.try_enqueue(vec![2]).is_err());
+        queue.reopen_enqueue();
+        assert!(queue.try_enqueue(vec![2]).is_err());
+        // remaining items are delivered once reopened
+        queue.reopen_dequeue();
+        assert!(!queue.is_closed());
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![0], vec![1]]);
+        // a batch dequeued before closing is still released
+        queue.try_enqueue(vec![2]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        queue.close_dequeue();
+        assert_eq!(vectored.into_vec(), [vec![2]]);
+        queue.reopen();
+        assert!(queue.try_dequeue_vectored().unwrap().vectored().is_none());
+        // both sides closed, items are not drained anymore
+        queue.try_enqueue(vec![3]).unwrap();
+        queue.close_enqueue();
+        queue.close_dequeue();
+        assert_eq!(
+            queue.try_dequeue_vectored().unwrap_err(),
+            DequeueError::Closed
+        );
+        queue.reopen_dequeue();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![3]]);
+        assert_eq!(
+            queue.try_dequeue_vectored().unwrap_err(),
+            DequeueError::Closed
+        );
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn transfer_to() {
+        let src = VectoredQueue::with_capacity(2);
+        for i in 0..5 {
+            src.enqueue_unbounded(vec![i]).unwrap();
+        }
+        // an alive batch conflicts, and is transferred once put back
+        let txn = src.begin_dequeue().unwrap().unwrap();
+        let dst = VectoredQueue::with_capacity(4);
+        assert_eq!(
+            src.transfer_to(&dst),
+            Err(TransferError::Conflict { transferred: 0 })
+        );
+        drop(txn);
+        assert_eq!(
+            src.transfer_to(&dst),
+            Err(TransferError::Full {
+                transferred: 4,
+                items: vec![vec![4]]
+            })
+        );
+        let vectored = dst.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(
+            vectored.into_vec(),
+            (0..4).map(|i| vec![i]).collect::<Vec<_>>()
+        );
+        assert!(src.is_empty());
+        src.try_enqueue(vec![5]).unwrap();
+        assert_eq!(src.transfer_to(&dst), Ok(1));
+        assert_eq!(src.transfer_to(&dst), Ok(0));
+        let vectored = dst.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![5]]);
+        src.try_enqueue(vec![6]).unwrap();
+        dst.close();
+        assert_eq!(
+            src.transfer_to(&dst),
+            Err(TransferError::Closed {
+                transferred: 0,
+                items: vec![vec![6]]
+            })
+        );
+        src.debug_assert_invariants();
+        dst.debug_assert_invariants();
+    }
+
+    #[test]
+    fn reset() {
+        let queue = VectoredQueue::with_capacity(2);
+        for i in 0..4 {
+            queue.enqueue_unbounded(vec![i]).unwrap();
+        }
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(queue.reset(), Err(DequeueError::Conflict));
+        drop(vectored);
+        queue.try_enqueue(vec![4]).unwrap();
+        let capacity = queue.capacity();
+        let footprint = queue.memory_footprint();
+        queue.close();
+        queue.reset().unwrap();
+        queue.debug_assert_invariants();
+        assert!(queue.is_empty() && !queue.is_closed());
+        assert_eq!(
+            (queue.capacity(), queue.memory_footprint()),
+            (capacity, footprint)
+        );
+        assert_eq!(queue.pending_bytes(), 0);
+        assert!(queue.capacity_history().is_empty());
+        #[cfg(feature = "metrics")]
+        assert_eq!(queue.stats(), crate::stats::QueueStats::default());
+        // the reset queue is usable again
+        queue.try_enqueue(vec![5]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![5]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn reset_drops_once() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug)]
+        struct Counted(u8);
+        impl AsRef<[u8]> for Counted {
+            fn as_ref(&self) -> &[u8] {
+                slice::from_ref(&self.0)
+            }
+        }
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let queue = VectoredQueue::with_capacity(2);
+        for i in 0..2 {
+            queue.try_enqueue(Counted(i)).unwrap();
+        }
+        // pending, high priority and overflowing items are all dropped
+        drop(queue.try_dequeue_n(1).unwrap());
+        {
+            let _priority = queue.mark_high_priority();
+            queue.try_enqueue(Counted(2)).unwrap();
+        }
+        for i in 3..6 {
+            queue.enqueue_unbounded(Counted(i)).unwrap();
+        }
+        queue.reset().unwrap();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 6);
+        for i in 0..2 {
+            queue.try_enqueue(Counted(i)).unwrap();
+        }
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.iter().map(|s| s[0]).collect::<Vec<_>>(), [0, 1]);
+        drop(vectored);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 8);
+        queue.debug_assert_invariants();
+        drop(queue);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 8);
+    }
+
+    #[test]
+    fn purge() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug)]
+        struct Counted(u8);
+        impl AsRef<[u8]> for Counted {
+            fn as_ref(&self) -> &[u8] {
+                slice::from_ref(&self.0)
+            }
+        }
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let queue = VectoredQueue::with_capacity(2);
+        for i in 0..2 {
+            queue.try_enqueue(Counted(i)).unwrap();
+        }
+        // nothing is purged while a batch is held
+        let vectored = queue.try_dequeue_n(1).unwrap().vectored().unwrap();
+        assert!(!queue.purge());
+        drop(vectored);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        {
+            let _priority = queue.mark_high_priority();
+            queue.try_enqueue(Counted(2)).unwrap();
+        }
+        for i in 3..6 {
+            queue.enqueue_unbounded(Counted(i)).unwrap();
+        }
+        queue.close_enqueue();
+        assert!(queue.purge());
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 6);
+        assert!(queue.is_empty());
+        assert_eq!(queue.pending_bytes(), 0);
+        // the closing state and the metrics are kept
+        assert!(matches!(
+            queue.try_dequeue_vectored(),
+            Err(DequeueError::Closed)
+        ));
+        #[cfg(feature = "metrics")]
+        assert_eq!(
+            (queue.stats().enqueued_items, queue.stats().dequeued_items),
+            (6, 1)
+        );
+        queue.reopen();
+        for i in 0..2 {
+            queue.try_enqueue(Counted(i)).unwrap();
+        }
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.iter().map(|s| s[0]).collect::<Vec<_>>(), [0, 1]);
+        drop(vectored);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 8);
+        queue.debug_assert_invariants();
+        drop(queue);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 8);
+    }
+
+    #[test]
+    fn try_enqueue_many() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0]).unwrap();
+        let mut items = (1..6).map(|i| vec![i]).peekable();
+        assert_eq!(queue.try_enqueue_many(&mut items), Ok(3));
+        assert_eq!(items.next(), Some(vec![4]));
+        assert_eq!(queue.try_enqueue_many(&mut items), Ok(0));
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 4);
+        assert_eq!(vectored.into_vec(), [[0], [1], [2], [3]]);
+        // without size hint, items are reserved one at a time
+        let mut filtered = items
+            .by_ref()
+            .chain((6..9).map(|i| vec![i]))
+            .filter(|item| item[0] != 7)
+            .peekable();
+        assert_eq!(queue.try_enqueue_many(&mut filtered), Ok(3));
+        assert_eq!(queue.try_enqueue_many(&mut filtered), Ok(0));
+        assert_eq!(queue.len(), 3);
+        queue.close();
+        assert!(queue
+            .try_enqueue_many(&mut [vec![6]].into_iter().peekable())
+            .is_err());
+        assert!(queue
+            .try_enqueue_many(&mut iter::empty().peekable())
+            .is_err());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_enqueue_many_short() {
+        // iterator yielding fewer items than its size hint lower bound
+        struct Short<I>(I, usize);
+        impl<I: Iterator> Iterator for Short<I> {
+            type Item = I::Item;
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next()
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.1, None)
+            }
+        }
+        let queue = VectoredQueue::with_capacity(4);
+        let mut items = Short([vec![0]].into_iter(), 3).peekable();
+        assert_eq!(queue.try_enqueue_many(&mut items), Ok(1));
+        // the unfilled slots are given back
+        assert!(!queue.is_poisoned());
+        for i in 1..4 {
+            queue.try_enqueue(vec![i]).unwrap();
+        }
+        assert!(queue.is_full());
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [[0], [1], [2], [3]]);
+        // a slot reserved since the unfilled ones prevents giving them back
+        let next = Cell::new(0);
+        let items = iter::from_fn(|| {
+            next.set(next.get() + 1);
+            match next.get() {
+                1 => Some(vec![4]),
+                _ => {
+                    queue.try_enqueue(vec![5]).unwrap();
+                    None
+                }
+            }
+        });
+        assert_eq!(
+            queue.try_enqueue_many(&mut Short(items, 2).peekable()),
+            Ok(1)
+        );
+        assert!(queue.is_poisoned());
+        assert_eq!(
+            queue.try_dequeue_vectored().unwrap_err(),
+            DequeueError::Poisoned
+        );
+        queue.clear_poison().unwrap();
+        queue.try_enqueue(vec![6]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [[4], [5], [6]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_enqueue_many_overflow() {
+        let queue = VectoredQueue::with_capacity(4);
+        let mut items = [MAX_TOTAL_SIZE, 1, 1]
+            .map(|size| vec![0; size])
+            .into_iter()
+            .peekable();
+        assert_eq!(queue.try_enqueue_many(&mut items), Ok(1));
+        assert_eq!(items.next(), Some(vec![0]));
+        // the queue is full once the maximum is reached
+        assert_eq!(queue.len(), 1);
+        assert!(queue.is_full());
+        assert!(matches!(
+            queue.try_enqueue(vec![0]),
+            Err(TryEnqueueError::Full(_))
+        ));
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn from_iter() {
+        let items = vec![vec![0], vec![1, 1], vec![2]];
+        let queue = VectoredQueue::from(items.clone());
+        assert_eq!((queue.capacity(), queue.pending_bytes()), (3, 4));
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.total_size(), 4);
+        assert_eq!(vectored.into_vec(), items);
+        queue.debug_assert_invariants();
+        // without exact size hint
+        let queue: VectoredQueue<_> = items.into_iter().filter(|i| i[0] != 1).collect();
+        assert_eq!(queue.capacity(), 2);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [vec![0], vec![2]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn memory_footprint() {
+        let slots = 4 + HIGH_PRIORITY_SLOTS;
+        let buffers_size = 2 * (slots + 2) * mem::size_of::<IoSlice>();
+        let queue = VectoredQueue::<[u8; 0]>::with_capacity(4);
+        let queue_size = mem::size_of_val(&queue);
+        assert_eq!(queue.memory_footprint(), queue_size + buffers_size);
+        let queue = VectoredQueue::<Vec<u8>>::with_capacity(4);
+        let items_size = 2 * slots * mem::size_of::<Vec<u8>>();
+        assert_eq!(
+            queue.memory_footprint(),
+            mem::size_of_val(&queue) + buffers_size + items_size
+        );
+        let empty = VectoredQueue::<Vec<u8>>::new();
+        assert_eq!(empty.memory_footprint(), mem::size_of_val(&empty));
+        queue.debug_assert_invariants();
     }
 }
@@ -0,0 +1,167 @@
+use std::{
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    sync::atomic::{self, AtomicBool, Ordering},
+};
+
+// File descriptor readable while the queue may have items, written at most once until cleared.
+pub(crate) struct Readiness {
+    read: OwnedFd,
+    // write end of the pipe, `None` for an eventfd
+    write: Option<OwnedFd>,
+    signaled: AtomicBool,
+}
+
+fn check(res: libc::c_int) -> io::Result<libc::c_int> {
+    match res {
+        -1 => Err(io::Error::last_os_error()),
+        res => Ok(res),
+    }
+}
+
+impl Readiness {
+    pub(crate) fn new() -> io::Result<Self> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Ok(fd) = check(unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) }) {
+            return Ok(Self::from_fds(unsafe { OwnedFd::from_raw_fd(fd) }, None));
+        }
+        Self::pipe()
+    }
+
+    fn pipe() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+        let [read, write] = fds.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+        for fd in [&read, &write] {
+            let fd = fd.as_raw_fd();
+            let flags = check(unsafe { libc::fcntl(fd, libc::F_GETFL) })?;
+            check(unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) })?;
+            check(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        }
+        Ok(Self::from_fds(read, Some(write)))
+    }
+
+    fn from_fds(read: OwnedFd, write: Option<OwnedFd>) -> Self {
+        Self {
+            read,
+            write,
+            signaled: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn fd(&self) -> BorrowedFd<'_> {
+        self.read.as_fd()
+    }
+
+    // Must be called after the state change to signal; the fence pairs with the one in `clear`
+    pub(crate) fn signal(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.signaled.load(Ordering::Relaxed) || self.signaled.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        // an eventfd is written with a 8-byte counter, a pipe with a single byte; the fd holds
+        // at most one write, so it cannot be full
+        let buf = 1u64.to_ne_bytes();
+        let (fd, len) = match &self.write {
+            Some(write) => (write, 1),
+            None => (&self.read, buf.len()),
+        };
+        unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr().cast(), len) };
+    }
+
+    // Clear the readiness, signaling it again if `is_empty` returns false; the fd is drained
+    // before the flag is reset, so that a concurrent signal is either drained or written after.
+    pub(crate) fn clear(&self, is_empty: impl FnOnce() -> bool) {
+        if !self.signaled.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut buf = [0u8; 8];
+        unsafe { libc::read(self.read.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        self.signaled.store(false, Ordering::Relaxed);
+        atomic::fence(Ordering::SeqCst);
+        // items enqueued while the flag was set have not been signaled
+        if !is_empty() {
+            self.signal();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::fd::{AsRawFd, BorrowedFd};
+
+    use crate::{readiness::Readiness, SyncVectoredQueue};
+
+    fn readable(fd: BorrowedFd) -> bool {
+        let mut pollfd = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_ne!(unsafe { libc::poll(&mut pollfd, 1, 0) }, -1);
+        pollfd.revents & libc::POLLIN != 0
+    }
+
+    // Number of writes pending in the fd
+    fn writes(fd: BorrowedFd, eventfd: bool) -> usize {
+        let mut buf = [0u8; 16];
+        match unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } {
+            -1 => 0,
+            // a single eventfd write adds 1 to the counter
+            8 if eventfd => u64::from_ne_bytes(buf[..8].try_into().unwrap()) as usize,
+            n => n as usize,
+        }
+    }
+
+    #[test]
+    fn signal_once() {
+        for readiness in [Readiness::new().unwrap(), Readiness::pipe().unwrap()] {
+            assert!(!readable(readiness.fd()));
+            readiness.signal();
+            readiness.signal();
+            assert!(readable(readiness.fd()));
+            readiness.clear(|| true);
+            assert!(!readable(readiness.fd()));
+            readiness.signal();
+            // still not empty once cleared
+            readiness.clear(|| false);
+            readiness.signal();
+            assert_eq!(writes(readiness.fd(), readiness.write.is_none()), 1);
+        }
+    }
+
+    #[test]
+    fn readiness_fd() {
+        let queue = SyncVectoredQueue::with_capacity(4);
+        // items enqueued before its creation make the fd readable
+        queue.try_enqueue([0]).unwrap();
+        let fd = queue.readiness_fd();
+        assert!(readable(fd));
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert!(!readable(fd));
+        // each transition from empty to non-empty fires once
+        for _ in 0..2 {
+            queue.try_enqueue([0]).unwrap();
+            queue.try_enqueue([1]).unwrap();
+            assert!(readable(fd));
+            let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+            assert!(!readable(fd));
+            // items enqueued while the batch is held make the fd readable again
+            queue.enqueue_unbounded([2]).unwrap();
+            drop(vectored);
+            assert!(readable(fd));
+            drop(queue.dequeue_vectored().unwrap());
+            assert!(!readable(fd));
+        }
+        queue.try_enqueue([0]).unwrap();
+        queue.try_enqueue([1]).unwrap();
+        let eventfd = cfg!(any(target_os = "linux", target_os = "android"));
+        assert_eq!(writes(fd, eventfd), 1);
+        // closing makes the fd readable
+        let queue = SyncVectoredQueue::<[u8; 1]>::with_capacity(1);
+        assert!(!readable(queue.readiness_fd()));
+        queue.close();
+        assert!(readable(queue.readiness_fd()));
+        queue.as_queue().debug_assert_invariants();
+    }
+}
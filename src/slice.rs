@@ -0,0 +1,152 @@
+use std::{io::IoSlice, ops::Deref};
+
+#[cfg(feature = "async")]
+use crate::r#async::{AsyncVectoredQueue, EnqueueFuture};
+use crate::{alloc::Allocator, error::TryEnqueueError, queue::VectoredQueue};
+#[cfg(feature = "sync")]
+use crate::{error::EnqueueError, sync::SyncVectoredQueue};
+
+/// `'static` bytes enqueued without copying them, e.g. an [`IoSlice<'static>`] produced by
+/// another zero-copy layer.
+///
+/// The bytes are never copied: the slice is written as is into the queue slices, and released
+/// without drop glue. The slice itself is still written into its item slot, like any item:
+/// batch slices can be replaced through [`Vectored`](crate::Vectored), e.g. by shorter-lived
+/// ones, so items moved back out of a batch, e.g. by [`into_vec`](crate::Vectored::into_vec),
+/// must be read from their slot. Skipping it would require giving up these methods for this
+/// type, which is not possible without specialization.
+#[derive(Debug, Copy, Clone)]
+#[repr(transparent)]
+pub struct StaticSlice(pub IoSlice<'static>);
+
+impl AsRef<[u8]> for StaticSlice {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Deref for StaticSlice {
+    type Target = IoSlice<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<IoSlice<'static>> for StaticSlice {
+    fn from(slice: IoSlice<'static>) -> Self {
+        Self(slice)
+    }
+}
+
+impl From<&'static [u8]> for StaticSlice {
+    fn from(bytes: &'static [u8]) -> Self {
+        Self(IoSlice::new(bytes))
+    }
+}
+
+impl<A: Allocator + Clone> VectoredQueue<StaticSlice, A> {
+    /// Shortcut for [`try_enqueue`](Self::try_enqueue) of a [`StaticSlice`].
+    pub fn try_enqueue_slice(
+        &self,
+        slice: IoSlice<'static>,
+    ) -> Result<(), TryEnqueueError<IoSlice<'static>>> {
+        self.try_enqueue(StaticSlice(slice))
+            .map_err(|err| match err {
+                TryEnqueueError::Full(item) => TryEnqueueError::Full(item.0),
+                TryEnqueueError::Closed(item) => TryEnqueueError::Closed(item.0),
+            })
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<A: Allocator + Clone> SyncVectoredQueue<StaticSlice, A> {
+    /// Shortcut for [`enqueue`](Self::enqueue) of a [`StaticSlice`].
+    pub fn enqueue_slice(
+        &self,
+        slice: IoSlice<'static>,
+    ) -> Result<(), EnqueueError<IoSlice<'static>>> {
+        self.enqueue(StaticSlice(slice))
+            .map_err(|EnqueueError(item)| EnqueueError(item.0))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<A: Allocator + Clone> AsyncVectoredQueue<StaticSlice, A> {
+    /// Shortcut for [`enqueue`](Self::enqueue) of a [`StaticSlice`].
+    pub fn enqueue_slice(&self, slice: IoSlice<'static>) -> EnqueueFuture<'_, StaticSlice, A> {
+        self.enqueue(StaticSlice(slice))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::IoSlice;
+
+    use crate::{slice::StaticSlice, VectoredQueue};
+
+    static DATA: [u8; 6] = *b"abcdef";
+
+    #[test]
+    fn zero_copy() {
+        let queue = VectoredQueue::<StaticSlice>::with_capacity(4);
+        for range in [0..2, 2..3, 3..6] {
+            queue.try_enqueue_slice(IoSlice::new(&DATA[range])).unwrap();
+        }
+        queue.try_enqueue(StaticSlice::from(&DATA[..1])).unwrap();
+        assert!(queue.try_enqueue_slice(IoSlice::new(&DATA)).is_err());
+        // the dequeued slices are the enqueued ones
+        let vectored = queue.try_dequeue_n(2).unwrap().vectored().unwrap();
+        assert_eq!(vectored[0].as_ptr(), DATA.as_ptr());
+        assert_eq!(vectored[1].as_ptr(), DATA[2..].as_ptr());
+        assert!(vectored.eq_chunks(&[b"ab", b"c"]));
+        drop(vectored);
+        // the size of the released items is still accounted for a partial release
+        assert_eq!(queue.peek_total_size(), 4);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let items = vectored.into_vec();
+        assert_eq!(
+            items.iter().map(StaticSlice::as_ref).collect::<Vec<_>>(),
+            [&b"def"[..], b"a"]
+        );
+        assert!(queue.is_empty());
+        assert_eq!(queue.pending_bytes(), 0);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn replaced_slices() {
+        let local = [0; 2];
+        let queue = VectoredQueue::<StaticSlice>::with_capacity(1);
+        queue.try_enqueue_slice(IoSlice::new(&DATA)).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        vectored[0] = IoSlice::new(&local);
+        // items are read back from their slot, not from the replaced slices
+        let items = vectored.into_vec();
+        assert_eq!(items[0].as_ptr(), DATA.as_ptr());
+        assert_eq!(items[0].len(), DATA.len());
+        queue.debug_assert_invariants();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn sync_zero_copy() {
+        let queue = crate::SyncVectoredQueue::<StaticSlice>::with_capacity(1);
+        queue.enqueue_slice(IoSlice::new(&DATA)).unwrap();
+        assert_eq!(queue.dequeue_vectored().unwrap()[0].as_ptr(), DATA.as_ptr());
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_zero_copy() {
+        use futures::executor::block_on;
+        let queue = crate::AsyncVectoredQueue::<StaticSlice>::with_capacity(1);
+        block_on(queue.enqueue_slice(IoSlice::new(&DATA))).unwrap();
+        assert_eq!(
+            block_on(queue.dequeue_vectored()).unwrap()[0].as_ptr(),
+            DATA.as_ptr()
+        );
+        queue.as_queue().debug_assert_invariants();
+    }
+}
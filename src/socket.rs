@@ -0,0 +1,147 @@
+use std::{
+    ffi::c_int,
+    io::{self, IoSlice},
+};
+
+use socket2::{SockAddr, Socket, Type};
+
+use crate::{alloc::Allocator, vectored::Vectored};
+
+fn retry(mut send: impl FnMut() -> io::Result<usize>) -> io::Result<usize> {
+    loop {
+        match send() {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    /// Send the [full slices](Self::full_slices) to `addr` with `send_to_vectored`.
+    ///
+    /// On stream sockets, partial sends are completed by subsequent calls, and the number of
+    /// written bytes is returned. On datagram sockets, the batch is sent as a single datagram,
+    /// and an error is returned if it was not sent entirely. Interrupted calls are retried.
+    pub fn send_to(&self, sock: &Socket, addr: &SockAddr, flags: c_int) -> io::Result<usize> {
+        self.send_with(sock, |slices| {
+            sock.send_to_vectored_with_flags(slices, addr, flags)
+        })
+    }
+
+    /// Same as [`send_to`](Self::send_to), but for connected sockets, with `send_vectored`.
+    pub fn send(&self, sock: &Socket, flags: c_int) -> io::Result<usize> {
+        self.send_with(sock, |slices| sock.send_vectored_with_flags(slices, flags))
+    }
+
+    fn send_with(
+        &self,
+        sock: &Socket,
+        mut send: impl FnMut(&[IoSlice<'_>]) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        let total_size: usize = self.full_slices().iter().map(|slice| slice.len()).sum();
+        let mut written = retry(|| send(self.full_slices()))?;
+        if written == total_size {
+            return Ok(written);
+        }
+        if sock.r#type()? != Type::STREAM {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("datagram truncated to {written} bytes out of {total_size}"),
+            ));
+        }
+        // the remaining slices are copied so the batch ones are left untouched
+        let mut remaining = self.full_slices().to_vec();
+        let mut slices = &mut remaining[..];
+        IoSlice::advance_slices(&mut slices, written);
+        while written < total_size {
+            let n = retry(|| send(slices))?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            IoSlice::advance_slices(&mut slices, n);
+            written += n;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{IoSlice, Read},
+        net::{SocketAddr, TcpListener, UdpSocket},
+    };
+
+    use socket2::{Domain, SockAddr, Socket, Type};
+
+    use crate::VectoredQueue;
+
+    fn filled(items: &[&[u8]]) -> VectoredQueue<Vec<u8>> {
+        let queue = VectoredQueue::with_capacity(items.len());
+        for item in items {
+            queue.try_enqueue(item.to_vec()).unwrap();
+        }
+        queue
+    }
+
+    #[test]
+    fn udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = SockAddr::from(receiver.local_addr().unwrap());
+        let sock = Socket::new(Domain::IPV4, Type::DGRAM, None).unwrap();
+        let queue = filled(&[b"hello ", b"world"]);
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        *vectored.parts_mut().0 = IoSlice::new(b"> ");
+        assert_eq!(vectored.send_to(&sock, &addr, 0).unwrap(), 13);
+        let mut buf = [0; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"> hello world");
+        // connected variant
+        sock.connect(&addr).unwrap();
+        let queue = filled(&[b"again"]);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.send(&sock, 0).unwrap(), 5);
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"again");
+        // an oversized datagram is an error, not a truncation
+        let queue = filled(&[&[0; 32_760], &[0; 32_760]]);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored.send_to(&sock, &addr, 0).is_err());
+    }
+
+    #[test]
+    fn tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let sock = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        sock.connect(&addr.into()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        let queue = filled(&[b"hello ", b"world"]);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.send(&sock, 0).unwrap(), 11);
+        // large batches may be sent partially, then completed
+        sock.set_send_buffer_size(4096).unwrap();
+        let items: Vec<Vec<u8>> = (0..4).map(|i| vec![i; 16_000]).collect();
+        let queue = VectoredQueue::with_capacity(items.len());
+        for item in &items {
+            queue.try_enqueue(item.clone()).unwrap();
+        }
+        std::thread::scope(|s| {
+            let reader = s.spawn(|| {
+                let mut buf = vec![0; 11 + 64_000];
+                peer.read_exact(&mut buf).unwrap();
+                buf
+            });
+            let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+            assert_eq!(vectored.send_to(&sock, &addr.into(), 0).unwrap(), 64_000);
+            let buf = reader.join().unwrap();
+            assert_eq!(&buf[..11], b"hello world");
+            assert_eq!(buf[11..], items.concat());
+            drop(vectored);
+            queue.debug_assert_invariants();
+        });
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(feature = "metrics")]
+use crate::primitives::{AtomicU64, Ordering};
+
+/// Cumulative counters of a queue, returned by
+/// [`VectoredQueue::stats`](crate::VectoredQueue::stats).
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct QueueStats {
+    /// Items enqueued, overflowing ones included.
+    pub enqueued_items: u64,
+    /// Bytes of the enqueued items, frame headers included, as in the
+    /// [total size](crate::Vectored::total_size) of a batch; the header of an overflowing item
+    /// is counted once it is framed.
+    pub enqueued_bytes: u64,
+    /// Items released by the consumer.
+    pub dequeued_items: u64,
+    /// Bytes of the released items, frame headers included.
+    pub dequeued_bytes: u64,
+    /// Enqueuings rejected because the queue was full.
+    pub rejected: u64,
+}
+
+// Counters are only updated with the `metrics` feature, and are zero-sized otherwise, so
+// that the hot path is not penalized.
+#[derive(Default)]
+pub(crate) struct Stats {
+    #[cfg(feature = "metrics")]
+    counters: [AtomicU64; 5],
+}
+
+#[cfg(feature = "metrics")]
+impl Stats {
+    fn add(&self, counter: usize, n: usize) {
+        self.counters[counter].fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn enqueued(&self, items: usize, bytes: usize) {
+        self.add(0, items);
+        self.add(1, bytes);
+    }
+
+    pub(crate) fn dequeued(&self, items: usize, bytes: usize) {
+        self.add(2, items);
+        self.add(3, bytes);
+    }
+
+    pub(crate) fn rejected(&self) {
+        self.add(4, 1);
+    }
+
+    pub(crate) fn snapshot(&self) -> QueueStats {
+        let [enqueued_items, enqueued_bytes, dequeued_items, dequeued_bytes, rejected] = self
+            .counters
+            .each_ref()
+            .map(|counter| counter.load(Ordering::Relaxed));
+        QueueStats {
+            enqueued_items,
+            enqueued_bytes,
+            dequeued_items,
+            dequeued_bytes,
+            rejected,
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for counter in &self.counters {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+impl Stats {
+    pub(crate) fn enqueued(&self, _items: usize, _bytes: usize) {}
+
+    pub(crate) fn dequeued(&self, _items: usize, _bytes: usize) {}
+
+    pub(crate) fn rejected(&self) {}
+}
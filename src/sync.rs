@@ -1,22 +1,72 @@
 use std::{
+    hint,
+    iter::Peekable,
+    ptr,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Condvar, Mutex,
+        atomic::{self, AtomicBool, Ordering},
+        Arc,
     },
+    thread::{self, Thread},
     time::Duration,
 };
+#[cfg(all(unix, feature = "os-notify"))]
+use std::{os::fd::BorrowedFd, sync::OnceLock};
 
+#[cfg(all(unix, feature = "os-notify"))]
+use crate::readiness::Readiness;
 use crate::{
-    error::{DequeueError, EnqueueError, TryEnqueueError},
-    queue::{TryDequeueResult, VectoredQueue},
+    alloc::{Allocator, Global},
+    deadline::{Deadline, Never},
+    error::{DequeueError, EnqueueError, TransferError, TryEnqueueError},
+    notify::{NotifyThreshold, Threshold},
+    primitives::{lock, wait_timeout, Condvar, Mutex, MutexGuard},
+    queue::{HighPriorityGuard, TryDequeueResult, VectoredQueue},
     vectored::Vectored,
 };
 
-pub struct SyncVectoredQueue<T> {
-    queue: VectoredQueue<T>,
+// Number of dequeuing attempts between two calls of the idle callback of a spinning consumer
+const SPIN_ROUND: u32 = 64;
+
+/// Decision of the idle callback of [`SyncVectoredQueue::dequeue_vectored_spin`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SpinDecision {
+    /// Keep spinning.
+    Spin,
+    /// Yield the thread, then keep spinning.
+    Yield,
+    /// Stop spinning, returning no batch.
+    GiveUp,
+}
+
+pub struct SyncVectoredQueue<T, A: Allocator + Clone = Global> {
+    queue: VectoredQueue<T, A>,
     cond_var: Condvar,
     lock: Mutex<()>,
     wait_dequeue: AtomicBool,
+    busy_spin: AtomicBool,
+    // consumer parked in `dequeue_select`
+    select_thread: Mutex<Option<Thread>>,
+    threshold: Threshold,
+    #[cfg(all(unix, feature = "os-notify"))]
+    readiness: OnceLock<Readiness>,
+}
+
+impl<T> From<Vec<T>> for SyncVectoredQueue<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn from(items: Vec<T>) -> Self {
+        Self::from_queue(items.into())
+    }
+}
+
+impl<T> FromIterator<T> for SyncVectoredQueue<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_queue(iter.into_iter().collect())
+    }
 }
 
 impl<T> Default for SyncVectoredQueue<T> {
@@ -31,51 +81,364 @@ impl<T> SyncVectoredQueue<T> {
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_queue(VectoredQueue::with_capacity(capacity))
+    }
+
+    /// See [`VectoredQueue::with_byte_capacity`].
+    pub fn with_byte_capacity(capacity: usize, max_bytes: usize) -> Self {
+        Self::from_queue(VectoredQueue::with_byte_capacity(capacity, max_bytes))
+    }
+
+    /// See [`VectoredQueue::with_max_capacity`].
+    pub fn with_max_capacity(initial: usize, max: usize) -> Self {
+        Self::from_queue(VectoredQueue::with_max_capacity(initial, max))
+    }
+
+    /// See [`VectoredQueue::with_framer`].
+    pub fn with_framer<H: AsRef<[u8]>>(
+        capacity: usize,
+        framer: impl Fn(&[u8]) -> H + Send + Sync + 'static,
+    ) -> Self {
+        Self::from_queue(VectoredQueue::with_framer(capacity, framer))
+    }
+}
+
+impl<T, A: Allocator + Clone> SyncVectoredQueue<T, A> {
+    #[cfg(feature = "allocator-api2")]
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_in(0, alloc)
+    }
+
+    #[cfg(feature = "allocator-api2")]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::from_queue(VectoredQueue::with_capacity_in(capacity, alloc))
+    }
+
+    /// Wrap a queue, keeping its items and its configuration.
+    ///
+    /// The queue carries no notification state, so the
+    /// [notify threshold](Self::set_notify_threshold) is unset.
+    pub fn from_queue(queue: VectoredQueue<T, A>) -> Self {
+        Self::from_parts(queue, Threshold::default())
+    }
+
+    pub(crate) fn from_parts(queue: VectoredQueue<T, A>, threshold: Threshold) -> Self {
         Self {
-            queue: VectoredQueue::with_capacity(capacity),
+            queue,
             cond_var: Condvar::new(),
             lock: Mutex::new(()),
             wait_dequeue: AtomicBool::new(true),
+            busy_spin: AtomicBool::new(false),
+            select_thread: Mutex::new(None),
+            threshold,
+            #[cfg(all(unix, feature = "os-notify"))]
+            readiness: OnceLock::new(),
         }
     }
+
+    /// Unwrap the queue, keeping its items; the notify threshold is dropped.
+    pub fn into_inner(self) -> VectoredQueue<T, A> {
+        self.queue
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn into_parts(self) -> (VectoredQueue<T, A>, Threshold) {
+        (self.queue, self.threshold)
+    }
+
+    /// The wrapped queue, e.g. to inspect it.
+    ///
+    /// Enqueuing through it doesn't wake a blocked consumer, and dequeuing doesn't wake
+    /// blocked producers.
+    pub fn as_queue(&self) -> &VectoredQueue<T, A> {
+        &self.queue
+    }
+
     pub fn capacity(&self) -> usize {
         self.queue.capacity()
     }
 
+    /// See [`VectoredQueue::byte_capacity`].
+    pub fn byte_capacity(&self) -> usize {
+        self.queue.byte_capacity()
+    }
+
+    /// See [`VectoredQueue::set_byte_capacity`]; producers waiting for space are woken.
+    pub fn set_byte_capacity(&self, max_bytes: usize) {
+        self.queue.set_byte_capacity(max_bytes);
+        self.notify_locked();
+    }
+
     pub fn set_capacity(&self, capacity: usize) {
         self.queue.set_capacity(capacity);
     }
 
+    /// See [`VectoredQueue::max_capacity`].
+    pub fn max_capacity(&self) -> usize {
+        self.queue.max_capacity()
+    }
+
+    /// See [`VectoredQueue::set_max_capacity`].
+    pub fn set_max_capacity(&self, max: usize) {
+        self.queue.set_max_capacity(max);
+    }
+
     pub fn len(&self) -> usize {
         self.queue.len()
     }
 
+    /// See [`VectoredQueue::pending_bytes`].
+    pub fn pending_bytes(&self) -> usize {
+        self.queue.pending_bytes()
+    }
+
+    /// See [`VectoredQueue::peek_len`].
+    pub fn peek_len(&self) -> usize {
+        self.queue.peek_len()
+    }
+
+    /// See [`VectoredQueue::peek_total_size`].
+    pub fn peek_total_size(&self) -> usize {
+        self.queue.peek_total_size()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.queue.is_empty()
     }
 
+    pub fn mark_high_priority(&self) -> HighPriorityGuard<'_> {
+        self.queue.mark_high_priority()
+    }
+
     pub fn close(&self) {
         self.queue.close();
-        self.cond_var.notify_all()
+        self.notify_closed();
+    }
+
+    pub fn close_enqueue(&self) {
+        self.queue.close_enqueue();
+        self.notify_closed();
+    }
+
+    pub fn close_dequeue(&self) {
+        self.queue.close_dequeue();
+        self.notify_closed();
+    }
+
+    fn notify_closed(&self) {
+        self.notify_locked();
+        #[cfg(all(unix, feature = "os-notify"))]
+        if let Some(readiness) = self.readiness.get() {
+            readiness.signal();
+        }
+    }
+
+    /// File descriptor readable while the queue may have items, e.g. to register it in an
+    /// `epoll` event loop; it is an `eventfd` on Linux, the read end of a pipe elsewhere, and
+    /// is created by the first call.
+    ///
+    /// It becomes readable when an item is enqueued into an empty queue, or when the queue is
+    /// closed, and is cleared by a successful dequeuing, unless items remain. Readability may
+    /// be spurious, e.g. if the items were dequeued through [`as_queue`](Self::as_queue) or
+    /// are still being inserted, so the consumer must expect empty dequeuing; the fd must be
+    /// polled, never read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file descriptor cannot be created.
+    #[cfg(all(unix, feature = "os-notify"))]
+    pub fn readiness_fd(&self) -> BorrowedFd<'_> {
+        let readiness = self
+            .readiness
+            .get_or_init(|| Readiness::new().expect("failed to create the readiness fd"));
+        // items may have been enqueued before the fd was created
+        if !self.is_empty() || self.is_closed() {
+            readiness.signal();
+        }
+        readiness.fd()
+    }
+
+    fn clear_readiness(&self) {
+        #[cfg(all(unix, feature = "os-notify"))]
+        if let Some(readiness) = self.readiness.get() {
+            readiness.clear(|| self.is_empty() && !self.is_closed());
+        }
     }
 
     pub fn is_closed(&self) -> bool {
         self.queue.is_closed()
     }
 
+    pub fn is_dequeue_closed(&self) -> bool {
+        self.queue.is_dequeue_closed()
+    }
+
     pub fn reopen(&self) {
         self.queue.reopen();
     }
+
+    pub fn reopen_enqueue(&self) {
+        self.queue.reopen_enqueue();
+    }
+
+    pub fn reopen_dequeue(&self) {
+        self.queue.reopen_dequeue();
+    }
+
+    // Waiters check their condition while holding the lock, so acquiring it before notifying
+    // ensures they are either already waiting or will see the new state.
+    fn notify_locked(&self) {
+        drop(lock(&self.lock));
+        self.cond_var.notify_all();
+        if let Some(thread) = &*lock(&self.select_thread) {
+            thread.unpark();
+        }
+    }
+
+    // Must be called while holding the lock, before checking the queue state; the fence pairs
+    // with the one in `kick`, so either the consumer sees the enqueued bytes, or the producer
+    // sees the flag and notifies.
+    //
+    // Consumers pass `dequeue`, so that they check pending items below the notification
+    // threshold at least every max delay; the deadline is not restarted by these checks.
+    fn wait_deadline<'a>(
+        &self,
+        mut guard: MutexGuard<'a, ()>,
+        deadline: &impl Deadline,
+        dequeue: bool,
+    ) -> Option<MutexGuard<'a, ()>> {
+        let config = &self.queue.config;
+        let now = config.now();
+        if deadline.has_elapsed_at(now) {
+            return None;
+        }
+        let end = now.checked_add(deadline.remaining_at(now));
+        loop {
+            let remaining = end.map_or(Duration::MAX, |end| {
+                end.saturating_duration_since(config.now())
+            });
+            let max_delay = self.threshold.max_delay().filter(|_| dequeue);
+            let wait = max_delay.map_or(remaining, |max_delay| max_delay.min(remaining));
+            // the clock may shorten the wait, in which case the deadline must be checked again
+            let timeout = config.park_timeout(wait);
+            match wait_timeout(&self.cond_var, guard, timeout) {
+                (_, true) if timeout >= remaining || deadline.has_elapsed_at(config.now()) => {
+                    return None
+                }
+                (g, true) if timeout >= wait && self.is_empty() && !self.is_closed() => guard = g,
+                (guard, _) => return Some(guard),
+            }
+        }
+    }
+
+    /// Only wake a blocked consumer once the pending items or bytes reach `threshold`, or once
+    /// the oldest pending item has waited `max_delay`, instead of on every enqueuing.
+    ///
+    /// Items below the threshold are still dequeued, as blocked consumers check the queue at
+    /// least every `max_delay`, without waiting for more items; [`kick`](Self::kick) wakes
+    /// them immediately. Closing always wakes them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_delay` is zero.
+    pub fn set_notify_threshold(&self, threshold: NotifyThreshold, max_delay: Duration) {
+        self.threshold.set(threshold, max_delay);
+    }
+
+    /// Wake a blocked consumer on every enqueuing again.
+    pub fn clear_notify_threshold(&self) {
+        self.threshold.clear();
+    }
+
+    /// Wake a blocked consumer, whatever the [notification
+    /// threshold](Self::set_notify_threshold).
+    pub fn kick(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.wait_dequeue.swap(false, Ordering::Relaxed) {
+            self.notify_locked();
+        }
+    }
+
+    /// Skip waking a blocked consumer when enqueuing, for a consumer which only dequeues with
+    /// [`dequeue_vectored_spin`](Self::dequeue_vectored_spin).
+    ///
+    /// While enabled, blocking dequeuing is only woken by closing, by [`kick`](Self::kick),
+    /// or by the maximum delay of the notification threshold.
+    pub fn set_busy_spin(&self, enabled: bool) {
+        self.busy_spin.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_busy_spin(&self) -> bool {
+        self.busy_spin.load(Ordering::Relaxed)
+    }
+
+    fn prepare_wait_dequeue(&self) {
+        self.wait_dequeue.store(true, Ordering::Relaxed);
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    /// Condition variable notified on enqueuing, dequeuing and closing.
+    ///
+    /// Use it with [`lock`](Self::lock) to wait on custom predicates. If items are enqueued
+    /// while holding the lock, [`wake_dequeue`](Self::wake_dequeue) must be called manually,
+    /// after releasing it, as notifying acquires the lock.
+    ///
+    /// With the `parking_lot` feature, it is a `parking_lot` condition variable, as is the
+    /// mutex.
+    pub fn condvar(&self) -> &Condvar {
+        &self.cond_var
+    }
+
+    /// Mutex used with [`condvar`](Self::condvar).
+    pub fn lock(&self) -> &Mutex<()> {
+        &self.lock
+    }
+
+    /// Split the queue into cloneable senders and a unique receiver, so that dequeuing
+    /// cannot conflict.
+    ///
+    /// The queue is closed when all senders, or the receiver, are dropped.
+    pub fn split(self) -> (SyncSender<T, A>, SyncReceiver<T, A>) {
+        let queue = Arc::new(self);
+        (
+            SyncSender(Arc::new(SenderGuard(queue.clone()))),
+            SyncReceiver(queue),
+        )
+    }
 }
 
-impl<T> SyncVectoredQueue<T>
+impl<T, A: Allocator + Clone> SyncVectoredQueue<T, A>
 where
     T: AsRef<[u8]>,
 {
-    fn wake_dequeue(&self) {
-        if self.wait_dequeue.swap(false, Ordering::Relaxed) {
-            self.cond_var.notify_all();
+    /// Wake a blocked consumer, if the [notification threshold](Self::set_notify_threshold)
+    /// is reached.
+    ///
+    /// The readiness fd of the `os-notify` feature, if created, is signaled whatever the
+    /// threshold.
+    pub fn wake_dequeue(&self) {
+        #[cfg(all(unix, feature = "os-notify"))]
+        if let Some(readiness) = self.readiness.get() {
+            readiness.signal();
         }
+        if !self.is_busy_spin() && self.threshold.is_reached(&self.queue) {
+            self.kick();
+        }
+    }
+
+    /// See [`VectoredQueue::drain_closed`].
+    pub fn drain_closed(&self) -> Result<Vec<T>, DequeueError> {
+        self.queue.drain_closed()
+    }
+
+    /// See [`VectoredQueue::purge`]; producers waiting for space are woken.
+    pub fn purge(&self) -> bool {
+        let purged = self.queue.purge();
+        if purged {
+            self.notify_locked();
+            self.clear_readiness();
+        }
+        purged
     }
 
     pub fn try_enqueue(&self, bytes: T) -> Result<(), TryEnqueueError<T>> {
@@ -84,30 +447,59 @@ where
         Ok(())
     }
 
+    /// See [`VectoredQueue::try_enqueue_tagged`].
+    pub fn try_enqueue_tagged(&self, bytes: T, tag: u64) -> Result<(), TryEnqueueError<T>> {
+        self.queue.try_enqueue_tagged(bytes, tag)?;
+        self.wake_dequeue();
+        Ok(())
+    }
+
+    /// See [`VectoredQueue::try_enqueue_many`].
+    pub fn try_enqueue_many<I: Iterator<Item = T>>(
+        &self,
+        items: &mut Peekable<I>,
+    ) -> Result<usize, EnqueueError<()>> {
+        let count = self.queue.try_enqueue_many(items)?;
+        if count > 0 {
+            self.wake_dequeue();
+        }
+        Ok(count)
+    }
+
+    /// See [`VectoredQueue::try_enqueue_batch`].
+    pub fn try_enqueue_batch(&self, items: Vec<T>) -> Result<(), TryEnqueueError<Vec<T>>> {
+        let notify = !items.is_empty();
+        self.queue.try_enqueue_batch(items)?;
+        if notify {
+            self.wake_dequeue();
+        }
+        Ok(())
+    }
+
     fn enqueue_wait(
         &self,
         mut bytes: T,
-        timeout: Option<Duration>,
+        deadline: impl Deadline,
     ) -> Result<(), TryEnqueueError<T>> {
         match self.try_enqueue(bytes) {
             Err(TryEnqueueError::Full(b)) => bytes = b,
             res => return res,
         };
-        let mut lock = self.lock.lock().unwrap();
+        let mut guard = lock(&self.lock);
         loop {
-            match self.try_enqueue(bytes) {
+            // waking the consumer acquires the lock, so it must be released before
+            match self.queue.try_enqueue(bytes) {
+                Ok(_) => {
+                    drop(guard);
+                    self.wake_dequeue();
+                    return Ok(());
+                }
                 Err(TryEnqueueError::Full(b)) => bytes = b,
-                res => return res,
+                Err(err) => return Err(err),
             };
-            lock = if let Some(timeout) = timeout {
-                match self.cond_var.wait_timeout(lock, timeout).unwrap() {
-                    (_, timeout_res) if timeout_res.timed_out() => {
-                        return Err(TryEnqueueError::Full(bytes))
-                    }
-                    (lock, _) => lock,
-                }
-            } else {
-                self.cond_var.wait(lock).unwrap()
+            guard = match self.wait_deadline(guard, &deadline, false) {
+                Some(guard) => guard,
+                None => return Err(TryEnqueueError::Full(bytes)),
             };
         }
     }
@@ -117,11 +509,19 @@ where
         bytes: T,
         timeout: Duration,
     ) -> Result<(), TryEnqueueError<T>> {
-        self.enqueue_wait(bytes, Some(timeout))
+        self.enqueue_wait(bytes, timeout)
+    }
+
+    pub fn enqueue_blocking_deadline(
+        &self,
+        bytes: T,
+        deadline: impl Deadline,
+    ) -> Result<(), TryEnqueueError<T>> {
+        self.enqueue_wait(bytes, deadline)
     }
 
     pub fn enqueue(&self, bytes: T) -> Result<(), EnqueueError<T>> {
-        match self.enqueue_wait(bytes, None) {
+        match self.enqueue_wait(bytes, Never) {
             Ok(_) => Ok(()),
             Err(TryEnqueueError::Closed(bytes)) => Err(EnqueueError(bytes)),
             Err(TryEnqueueError::Full(_)) => unreachable!(),
@@ -134,32 +534,34 @@ where
         Ok(())
     }
 
-    pub fn try_dequeue_vectored(&self) -> Result<TryDequeueResult<T>, DequeueError> {
-        let res = self.queue.try_dequeue_vectored()?;
+    pub fn try_dequeue_vectored(&self) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        let res = self
+            .queue
+            .try_dequeue_vectored_notify(&|| self.notify_locked())?;
         if matches!(res, TryDequeueResult::Vectored(_)) {
-            self.cond_var.notify_all();
+            self.clear_readiness();
         }
         Ok(res)
     }
 
     fn dequeue_vectored_wait(
         &self,
-        timeout: Option<Duration>,
-    ) -> Result<TryDequeueResult<T>, DequeueError> {
-        let mut lock = self.lock.lock().unwrap();
+        deadline: impl Deadline,
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        let mut guard = lock(&self.lock);
         loop {
-            self.wait_dequeue.store(true, Ordering::Relaxed);
-            let res = self.try_dequeue_vectored()?;
+            self.prepare_wait_dequeue();
+            // the lock is already held, so producers cannot miss the notification
+            let res = self.queue.try_dequeue_vectored_notify(&|| {
+                self.cond_var.notify_all();
+            })?;
             if matches!(res, TryDequeueResult::Vectored(_)) {
+                self.clear_readiness();
                 return Ok(res);
             }
-            lock = if let Some(timeout) = timeout {
-                match self.cond_var.wait_timeout(lock, timeout).unwrap() {
-                    (_, timeout_res) if timeout_res.timed_out() => return Ok(res),
-                    (lock, _) => lock,
-                }
-            } else {
-                self.cond_var.wait(lock).unwrap()
+            guard = match self.wait_deadline(guard, &deadline, true) {
+                Some(guard) => guard,
+                None => return Ok(res),
             };
         }
     }
@@ -167,11 +569,641 @@ where
     pub fn try_dequeue_vectored_timeout(
         &self,
         timeout: Duration,
-    ) -> Result<TryDequeueResult<T>, DequeueError> {
-        self.dequeue_vectored_wait(Some(timeout))
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.dequeue_vectored_wait(timeout)
+    }
+
+    pub fn dequeue_vectored_deadline(
+        &self,
+        deadline: impl Deadline,
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.dequeue_vectored_wait(deadline)
+    }
+
+    /// Dequeue a batch, waiting for it to be enqueued.
+    ///
+    /// Insertions in progress are waited for on the condition variable, as their producers
+    /// notify the consumer once inserted, instead of returning a pending result.
+    pub fn dequeue_vectored(&self) -> Result<Vectored<'_, T, A>, DequeueError> {
+        Ok(self.dequeue_vectored_wait(Never)?.vectored().unwrap())
     }
 
-    pub fn dequeue_vectored(&self) -> Result<Vectored<T>, DequeueError> {
-        Ok(self.dequeue_vectored_wait(None)?.vectored().unwrap())
+    /// Dequeue by spinning on the queue, never blocking, e.g. with a consumer pinned to a
+    /// core; see [`set_busy_spin`](Self::set_busy_spin) to spare producers notifying it.
+    ///
+    /// `idle` is called periodically while the queue is empty, with the number of its calls,
+    /// to decide whether to keep spinning; `None` is returned if it gives up. Producers
+    /// blocked on a full queue are still notified when buffers are swapped.
+    pub fn dequeue_vectored_spin(
+        &self,
+        mut idle: impl FnMut(u32) -> SpinDecision,
+    ) -> Result<Option<Vectored<'_, T, A>>, DequeueError> {
+        let mut calls: u32 = 0;
+        loop {
+            for _ in 0..SPIN_ROUND {
+                if let TryDequeueResult::Vectored(vectored) = self.try_dequeue_vectored()? {
+                    return Ok(Some(vectored));
+                }
+                hint::spin_loop();
+            }
+            calls = calls.saturating_add(1);
+            match idle(calls) {
+                SpinDecision::Spin => {}
+                SpinDecision::Yield => thread::yield_now(),
+                SpinDecision::GiveUp => return Ok(None),
+            }
+        }
+    }
+
+    /// See [`VectoredQueue::try_dequeue_with`].
+    pub fn try_dequeue_with<R>(
+        &self,
+        f: impl FnOnce(&mut Vectored<'_, T, A>) -> R,
+    ) -> Result<Option<R>, DequeueError> {
+        Ok(self
+            .try_dequeue_vectored()?
+            .vectored()
+            .map(|mut vectored| f(&mut vectored)))
+    }
+
+    /// Blocking version of [`try_dequeue_with`](Self::try_dequeue_with).
+    pub fn dequeue_with<R>(
+        &self,
+        f: impl FnOnce(&mut Vectored<'_, T, A>) -> R,
+    ) -> Result<R, DequeueError> {
+        Ok(f(&mut self.dequeue_vectored()?))
+    }
+
+    /// See [`VectoredQueue::transfer_to`]; enqueuing blocks until the destination has space,
+    /// so the error is never [`TransferError::Full`].
+    pub fn transfer_to<B: Allocator + Clone>(
+        &self,
+        dst: &SyncVectoredQueue<T, B>,
+    ) -> Result<usize, TransferError<T>> {
+        assert!(!ptr::addr_eq(self, dst), "cannot transfer into itself");
+        let mut transferred = 0;
+        let notify_enqueue = || self.notify_locked();
+        while let Some(items) = self.queue.dequeue_transfer(&notify_enqueue, transferred)? {
+            let mut items = items.into_iter();
+            while let Some(bytes) = items.next() {
+                if let Err(EnqueueError(bytes)) = dst.enqueue(bytes) {
+                    let items = [bytes].into_iter().chain(items).collect();
+                    return Err(TransferError::Closed { transferred, items });
+                }
+                transferred += 1;
+            }
+        }
+        Ok(transferred)
+    }
+
+    /// Dequeue the first of the queues having items, blocking until one has, and return its
+    /// index with the batch.
+    ///
+    /// Queues are checked in order, so the first ones have strict priority and may starve
+    /// the others. Closed queues are skipped, until they are all closed. The thread is parked
+    /// while waiting, and unparked by the notification of any of the queues.
+    pub fn dequeue_select<'a, const N: usize>(
+        queues: [&'a Self; N],
+    ) -> Result<(usize, Vectored<'a, T, A>), DequeueError> {
+        for queue in queues {
+            *lock(&queue.select_thread) = Some(thread::current());
+        }
+        let res = loop {
+            let mut closed = 0;
+            let mut res = None;
+            for (i, queue) in queues.iter().enumerate() {
+                // like blocking dequeuing, the flag must be set before checking the queue
+                queue.prepare_wait_dequeue();
+                match queue.try_dequeue_vectored() {
+                    Ok(TryDequeueResult::Vectored(vectored)) => {
+                        res = Some(Ok((i, vectored)));
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(DequeueError::Closed) => closed += 1,
+                    Err(err) => {
+                        res = Some(Err(err));
+                        break;
+                    }
+                }
+            }
+            match res {
+                Some(res) => break res,
+                None if closed == N => break Err(DequeueError::Closed),
+                // pending items below the notification thresholds are checked periodically
+                None => match queues.iter().filter_map(|q| q.threshold.max_delay()).min() {
+                    Some(max_delay) => thread::park_timeout(max_delay),
+                    None => thread::park(),
+                },
+            }
+        };
+        for queue in queues {
+            *lock(&queue.select_thread) = None;
+        }
+        res
+    }
+
+    /// Block until the queue is not empty or closed, without dequeuing.
+    pub fn wait_nonempty(&self) {
+        let mut guard = lock(&self.lock);
+        loop {
+            self.prepare_wait_dequeue();
+            if !self.is_empty() || self.is_closed() {
+                return;
+            }
+            // waiting without deadline never times out
+            guard = self.wait_deadline(guard, &Never, true).unwrap();
+        }
+    }
+}
+
+/// Create a [`SyncVectoredQueue`] with the given capacity, split into a sender and a
+/// receiver, see [`SyncVectoredQueue::split`].
+pub fn sync_channel<T>(capacity: usize) -> (SyncSender<T>, SyncReceiver<T>) {
+    SyncVectoredQueue::with_capacity(capacity).split()
+}
+
+struct SenderGuard<T, A: Allocator + Clone>(Arc<SyncVectoredQueue<T, A>>);
+
+impl<T, A: Allocator + Clone> Drop for SenderGuard<T, A> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Enqueuing handle returned by [`SyncVectoredQueue::split`].
+pub struct SyncSender<T, A: Allocator + Clone = Global>(Arc<SenderGuard<T, A>>);
+
+impl<T, A: Allocator + Clone> Clone for SyncSender<T, A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T, A: Allocator + Clone> SyncSender<T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn try_enqueue(&self, bytes: T) -> Result<(), TryEnqueueError<T>> {
+        self.0 .0.try_enqueue(bytes)
+    }
+
+    /// See [`SyncVectoredQueue::try_enqueue_batch`].
+    pub fn try_enqueue_batch(&self, items: Vec<T>) -> Result<(), TryEnqueueError<Vec<T>>> {
+        self.0 .0.try_enqueue_batch(items)
+    }
+
+    pub fn enqueue(&self, bytes: T) -> Result<(), EnqueueError<T>> {
+        self.0 .0.enqueue(bytes)
+    }
+
+    pub fn enqueue_unbounded(&self, bytes: T) -> Result<(), EnqueueError<T>> {
+        self.0 .0.enqueue_unbounded(bytes)
+    }
+}
+
+/// Dequeuing handle returned by [`SyncVectoredQueue::split`].
+///
+/// Dequeuing borrows it mutably, so it never returns [`DequeueError::Conflict`].
+pub struct SyncReceiver<T, A: Allocator + Clone = Global>(Arc<SyncVectoredQueue<T, A>>);
+
+impl<T, A: Allocator + Clone> Drop for SyncReceiver<T, A> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+impl<T, A: Allocator + Clone> SyncReceiver<T, A> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+}
+
+impl<T, A: Allocator + Clone> SyncReceiver<T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn try_dequeue(&mut self) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.0.try_dequeue_vectored()
+    }
+
+    /// See [`SyncVectoredQueue::try_dequeue_vectored_timeout`].
+    pub fn try_dequeue_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<TryDequeueResult<'_, T, A>, DequeueError> {
+        self.0.try_dequeue_vectored_timeout(timeout)
+    }
+
+    pub fn dequeue(&mut self) -> Result<Vectored<'_, T, A>, DequeueError> {
+        self.0.dequeue_vectored()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::Ordering,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        buffer::MAX_TOTAL_SIZE,
+        clock::{Clock, MockClock},
+        deadline::{Deadline, Never},
+        error::{DequeueError, TransferError, TryEnqueueError},
+        notify::NotifyThreshold,
+        queue::{TryDequeueResult, VectoredQueue},
+        sync::{sync_channel, SpinDecision, SyncVectoredQueue},
+    };
+
+    #[test]
+    fn ping_pong() {
+        const ROUNDS: usize = 10000;
+        let ping = SyncVectoredQueue::with_capacity(1);
+        let pong = SyncVectoredQueue::with_capacity(1);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..ROUNDS {
+                    drop(ping.dequeue_vectored().unwrap());
+                    pong.try_enqueue([0]).unwrap();
+                }
+            });
+            for _ in 0..ROUNDS {
+                ping.try_enqueue([0]).unwrap();
+                drop(pong.dequeue_vectored().unwrap());
+            }
+        });
+        ping.as_queue().debug_assert_invariants();
+        pong.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn enqueue_while_batch_held() {
+        let queue = SyncVectoredQueue::with_capacity(1);
+        queue.try_enqueue([0]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        queue.try_enqueue([1]).unwrap();
+        thread::scope(|s| {
+            let producer = s.spawn(|| queue.enqueue([2]).unwrap());
+            thread::sleep(Duration::from_millis(50));
+            assert!(!producer.is_finished());
+            drop(vectored);
+            let vectored = queue.dequeue_vectored().unwrap();
+            assert_eq!(&*vectored[0], &[1]);
+            producer.join().unwrap();
+        });
+        assert_eq!(&*queue.dequeue_vectored().unwrap()[0], &[2]);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn deadline() {
+        let queue = SyncVectoredQueue::with_capacity(1);
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert!(matches!(
+            queue.dequeue_vectored_deadline(deadline),
+            Ok(TryDequeueResult::Empty)
+        ));
+        assert!(deadline.has_elapsed());
+        queue.try_enqueue([0]).unwrap();
+        assert!(matches!(
+            queue.enqueue_blocking_deadline([1], Duration::from_millis(10)),
+            Err(TryEnqueueError::Full(_))
+        ));
+        assert!(matches!(
+            queue.dequeue_vectored_deadline(Never),
+            Ok(TryDequeueResult::Vectored(_))
+        ));
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn mock_clock_deadline() {
+        let clock = MockClock::new();
+        let queue = VectoredQueue::<[u8; 1]>::builder()
+            .capacity(1)
+            .clock(clock.clone())
+            .build_sync()
+            .unwrap();
+        let deadline = clock.now() + Duration::from_secs(3600);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| queue.dequeue_vectored_deadline(deadline).unwrap());
+            thread::sleep(Duration::from_millis(10));
+            assert!(!consumer.is_finished());
+            clock.advance(Duration::from_secs(3600));
+            assert!(matches!(consumer.join().unwrap(), TryDequeueResult::Empty));
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn enqueue_unbounded_overflow_wakes() {
+        let queue = SyncVectoredQueue::with_capacity(1);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| queue.dequeue_vectored().unwrap().into_vec());
+            thread::sleep(Duration::from_millis(10));
+            // exceeding the maximum total size, the bytes are kept in the overflow
+            queue
+                .enqueue_unbounded(vec![0; MAX_TOTAL_SIZE + 1])
+                .unwrap();
+            assert_eq!(consumer.join().unwrap()[0].len(), MAX_TOTAL_SIZE + 1);
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_select() {
+        let control = SyncVectoredQueue::with_capacity(2);
+        let data = SyncVectoredQueue::with_capacity(2);
+        data.try_enqueue([0]).unwrap();
+        control.try_enqueue([1]).unwrap();
+        // the first queue has priority
+        let (index, vectored) = SyncVectoredQueue::dequeue_select([&control, &data]).unwrap();
+        assert_eq!((index, vectored.into_vec()), (0, vec![[1]]));
+        let (index, vectored) = SyncVectoredQueue::dequeue_select([&control, &data]).unwrap();
+        assert_eq!((index, vectored.into_vec()), (1, vec![[0]]));
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                data.try_enqueue([2]).unwrap();
+            });
+            let (index, _) = SyncVectoredQueue::dequeue_select([&control, &data]).unwrap();
+            assert_eq!(index, 1);
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                control.close();
+                data.close();
+            });
+            assert_eq!(
+                SyncVectoredQueue::dequeue_select([&control, &data]).unwrap_err(),
+                DequeueError::Closed
+            );
+        });
+        control.as_queue().debug_assert_invariants();
+        data.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn from_iter() {
+        let queue: SyncVectoredQueue<_> = (0..3).map(|i| [i]).collect();
+        assert_eq!(
+            queue.dequeue_vectored().unwrap().into_vec(),
+            [[0], [1], [2]]
+        );
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_with() {
+        let queue = SyncVectoredQueue::with_capacity(1);
+        thread::scope(|s| {
+            s.spawn(|| queue.enqueue([0]).unwrap());
+            assert_eq!(queue.dequeue_with(|v| v.total_size()), Ok(1));
+        });
+        assert_eq!(queue.try_dequeue_with(|v| v.len()), Ok(None));
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn wait_nonempty() {
+        let queue = SyncVectoredQueue::with_capacity(4);
+        thread::scope(|s| {
+            s.spawn(|| queue.try_enqueue([0]).unwrap());
+            queue.wait_nonempty();
+            assert_eq!(queue.len(), 1);
+            drop(queue.try_dequeue_vectored().unwrap().vectored().unwrap());
+            s.spawn(|| queue.close());
+            queue.wait_nonempty();
+            assert!(queue.is_closed());
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn from_queue() {
+        let queue = VectoredQueue::with_capacity(2);
+        queue.try_enqueue([0]).unwrap();
+        queue.enqueue_unbounded([1]).unwrap();
+        queue.enqueue_unbounded([2]).unwrap();
+        let queue = SyncVectoredQueue::from_queue(queue);
+        assert_eq!(queue.as_queue().len(), 3);
+        assert_eq!(queue.dequeue_vectored().unwrap().into_vec(), [[0], [1]]);
+        let queue = queue.into_inner();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.into_vec(), [[2]]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn transfer_to() {
+        let src = SyncVectoredQueue::with_capacity(2);
+        for i in 0..5 {
+            src.enqueue_unbounded([i]).unwrap();
+        }
+        let dst = SyncVectoredQueue::with_capacity(2);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut items = Vec::new();
+                while items.len() < 5 {
+                    items.extend(dst.dequeue_vectored().unwrap().into_vec());
+                }
+                items
+            });
+            assert_eq!(src.transfer_to(&dst), Ok(5));
+            assert_eq!(
+                consumer.join().unwrap(),
+                (0..5).map(|i| [i]).collect::<Vec<_>>()
+            );
+        });
+        src.try_enqueue([5]).unwrap();
+        src.try_enqueue([6]).unwrap();
+        dst.close();
+        let err = src.transfer_to(&dst).unwrap_err();
+        assert_eq!(err.transferred(), 0);
+        assert!(matches!(err, TransferError::Closed { items, .. } if items == [[5], [6]]));
+        src.as_queue().debug_assert_invariants();
+        dst.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn close_dequeue() {
+        let queue = SyncVectoredQueue::with_capacity(1);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| queue.dequeue_vectored().map(|v| v.len()));
+            s.spawn(|| queue.close_dequeue());
+            assert_eq!(consumer.join().unwrap(), Err(DequeueError::Closed));
+        });
+        queue.reopen();
+        queue.try_enqueue([0]).unwrap();
+        thread::scope(|s| {
+            // the full queue blocks the producer until it fails fast
+            let producer = s.spawn(|| queue.enqueue([1]));
+            s.spawn(|| queue.close_dequeue());
+            assert_eq!(producer.join().unwrap().unwrap_err().0, [1]);
+        });
+        assert_eq!(queue.dequeue_vectored().unwrap_err(), DequeueError::Closed);
+        queue.reopen_dequeue();
+        assert_eq!(queue.dequeue_vectored().unwrap().len(), 1);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_spin() {
+        let queue = SyncVectoredQueue::with_capacity(4);
+        let mut calls = Vec::new();
+        let res = queue.dequeue_vectored_spin(|n| {
+            calls.push(n);
+            if n < 3 {
+                SpinDecision::Spin
+            } else {
+                SpinDecision::GiveUp
+            }
+        });
+        assert!(res.unwrap().is_none());
+        assert_eq!(calls, [1, 2, 3]);
+        queue.set_busy_spin(true);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..4 {
+                    queue.enqueue([i]).unwrap();
+                }
+                queue.close();
+            });
+            let mut items = Vec::new();
+            loop {
+                match queue.dequeue_vectored_spin(|_| SpinDecision::Yield) {
+                    Ok(vectored) => items.extend(vectored.unwrap().into_vec()),
+                    Err(err) => break assert_eq!(err, DequeueError::Closed),
+                }
+            }
+            assert_eq!(items, [[0], [1], [2], [3]]);
+        });
+        // producers don't notify in busy spin mode, so the waiting flag is never reset
+        queue.reopen();
+        queue.try_enqueue([4]).unwrap();
+        assert!(queue.wait_dequeue.load(Ordering::Relaxed));
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn enqueue_byte_capacity() {
+        let queue = SyncVectoredQueue::with_byte_capacity(8, 4);
+        queue.try_enqueue(vec![0; 4]).unwrap();
+        thread::scope(|s| {
+            // the producer is woken once the buffers are swapped
+            let producer = s.spawn(|| queue.enqueue(vec![1; 2]));
+            thread::sleep(Duration::from_millis(50));
+            assert!(!producer.is_finished());
+            assert_eq!(queue.dequeue_vectored().unwrap().into_vec(), [[0; 4]]);
+            producer.join().unwrap().unwrap();
+        });
+        assert_eq!(queue.pending_bytes(), 2);
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn try_enqueue_many() {
+        let queue = SyncVectoredQueue::with_capacity(2);
+        thread::scope(|s| {
+            let consumer = s.spawn(|| queue.dequeue_vectored().unwrap().into_vec());
+            let mut items = [[0], [1], [2]].into_iter().peekable();
+            assert_eq!(queue.try_enqueue_many(&mut items), Ok(2));
+            assert_eq!(consumer.join().unwrap(), [[0], [1]]);
+            assert_eq!(items.next(), Some([2]));
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn notify_threshold() {
+        let queue = SyncVectoredQueue::with_capacity(8);
+        queue.set_notify_threshold(NotifyThreshold::Items(2), Duration::from_secs(3600));
+        // the consumer holds the lock until it waits
+        let wait_blocked = || {
+            while !queue.wait_dequeue.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            drop(crate::primitives::lock(&queue.lock));
+        };
+        thread::scope(|s| {
+            // only reaching the threshold wakes the consumer
+            let consumer = s.spawn(|| queue.dequeue_vectored().unwrap().len());
+            wait_blocked();
+            queue.try_enqueue([0]).unwrap();
+            queue.try_enqueue([1]).unwrap();
+            assert_eq!(consumer.join().unwrap(), 2);
+            // or kicking it
+            let consumer = s.spawn(|| queue.dequeue_vectored().unwrap().len());
+            wait_blocked();
+            queue.try_enqueue([0]).unwrap();
+            queue.kick();
+            assert_eq!(consumer.join().unwrap(), 1);
+        });
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn notify_threshold_liveness() {
+        let queue = SyncVectoredQueue::with_capacity(8);
+        queue.set_notify_threshold(NotifyThreshold::Bytes(1024), Duration::from_millis(10));
+        thread::scope(|s| {
+            // items below the threshold are dequeued after the max delay
+            let consumer = s.spawn(|| queue.dequeue_vectored().unwrap().len());
+            queue.try_enqueue([0]).unwrap();
+            assert_eq!(consumer.join().unwrap(), 1);
+        });
+        // periodic checks of an empty queue do not restart the timeout
+        let start = Instant::now();
+        let res = queue.try_dequeue_vectored_timeout(Duration::from_millis(50));
+        assert!(matches!(res, Ok(TryDequeueResult::Empty)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        queue.as_queue().debug_assert_invariants();
+    }
+
+    #[test]
+    fn channel() {
+        let (sender, mut receiver) = sync_channel(4);
+        let mut items = Vec::new();
+        thread::scope(|s| {
+            for i in 0..4u8 {
+                let sender = sender.clone();
+                s.spawn(move || {
+                    for j in 0..100u8 {
+                        sender.enqueue([i, j]).unwrap();
+                    }
+                });
+            }
+            // the blocked receiver is woken once the last sender is dropped
+            drop(sender);
+            let err = loop {
+                match receiver.dequeue() {
+                    Ok(vectored) => items.extend(vectored.into_vec()),
+                    Err(err) => break err,
+                }
+            };
+            assert_eq!(err, DequeueError::Closed);
+        });
+        items.sort();
+        items.dedup();
+        assert_eq!(items.len(), 400);
+        // enqueuing fails once the receiver is dropped
+        let (sender, receiver) = sync_channel(4);
+        sender.try_enqueue([0]).unwrap();
+        drop(receiver);
+        assert!(matches!(
+            sender.try_enqueue([1]),
+            Err(TryEnqueueError::Closed(_))
+        ));
+        sender.0 .0.as_queue().debug_assert_invariants();
     }
 }
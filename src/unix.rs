@@ -0,0 +1,205 @@
+use std::{
+    io::{self, IoSlice},
+    mem,
+    os::fd::RawFd,
+    ptr,
+};
+
+use crate::{alloc::Allocator, vectored::Vectored};
+
+/// Ancillary data sent with [`Vectored::sendmsg`].
+#[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
+pub enum ControlMessage<'a> {
+    /// File descriptors passed to the peer of a Unix domain socket (`SCM_RIGHTS`).
+    ScmRights(&'a [RawFd]),
+}
+
+impl ControlMessage<'_> {
+    fn data(&self) -> (libc::c_int, libc::c_int, &[u8]) {
+        match self {
+            Self::ScmRights(fds) => {
+                let data = unsafe {
+                    std::slice::from_raw_parts(fds.as_ptr().cast(), mem::size_of_val(*fds))
+                };
+                (libc::SOL_SOCKET, libc::SCM_RIGHTS, data)
+            }
+        }
+    }
+}
+
+// Encode control messages in a buffer aligned for `cmsghdr`.
+fn encode_cmsgs(cmsgs: &[ControlMessage]) -> Vec<u64> {
+    let space: usize = cmsgs
+        .iter()
+        .map(|cmsg| unsafe { libc::CMSG_SPACE(cmsg.data().2.len() as _) } as usize)
+        .sum();
+    let mut buffer = vec![0u64; space.div_ceil(mem::size_of::<u64>())];
+    if cmsgs.is_empty() {
+        return buffer;
+    }
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_control = buffer.as_mut_ptr().cast();
+    msg.msg_controllen = space as _;
+    let mut header = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    for cmsg in cmsgs {
+        let (level, ty, data) = cmsg.data();
+        unsafe {
+            (*header).cmsg_level = level;
+            (*header).cmsg_type = ty;
+            (*header).cmsg_len = libc::CMSG_LEN(data.len() as _) as _;
+            ptr::copy_nonoverlapping(data.as_ptr(), libc::CMSG_DATA(header), data.len());
+            header = libc::CMSG_NXTHDR(&msg, header);
+        }
+    }
+    buffer
+}
+
+fn sendmsg_retry(
+    fd: RawFd,
+    slices: &[IoSlice<'_>],
+    control: &mut [u64],
+    controllen: usize,
+) -> io::Result<usize> {
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    // `IoSlice` is guaranteed to be ABI compatible with `iovec`
+    msg.msg_iov = slices.as_ptr() as *mut libc::iovec;
+    msg.msg_iovlen = slices.len() as _;
+    if controllen > 0 {
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = controllen as _;
+    }
+    loop {
+        match unsafe { libc::sendmsg(fd, &msg, 0) } {
+            -1 => match io::Error::last_os_error() {
+                err if err.kind() == io::ErrorKind::Interrupted => continue,
+                err => return Err(err),
+            },
+            n => return Ok(n as usize),
+        }
+    }
+}
+
+fn send_all(fd: RawFd, mut bytes: &[u8]) -> io::Result<usize> {
+    let total_size = bytes.len();
+    while !bytes.is_empty() {
+        match unsafe { libc::send(fd, bytes.as_ptr().cast(), bytes.len(), 0) } {
+            -1 => match io::Error::last_os_error() {
+                err if err.kind() == io::ErrorKind::Interrupted => continue,
+                err => return Err(err),
+            },
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => bytes = &bytes[n as usize..],
+        }
+    }
+    Ok(total_size)
+}
+
+impl<T, A: Allocator + Clone> Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    /// Write the [full slices](Self::full_slices) to the socket `fd` with `sendmsg`, along with
+    /// the given control messages, e.g. file descriptors to pass.
+    ///
+    /// Partial sends are completed by subsequent calls, control messages being only sent with
+    /// the first one; interrupted calls are retried. The number of written bytes is returned.
+    /// Without control messages, a [contiguous](Self::as_contiguous) batch is written with
+    /// `send`.
+    pub fn sendmsg(&self, fd: RawFd, cmsgs: &[ControlMessage]) -> io::Result<usize> {
+        if let (Some(payload), []) = (self.as_contiguous(), cmsgs) {
+            return send_all(fd, payload);
+        }
+        let total_size: usize = self.full_slices().iter().map(|slice| slice.len()).sum();
+        let mut control = encode_cmsgs(cmsgs);
+        let controllen = mem::size_of_val(&control[..]);
+        let mut written = sendmsg_retry(fd, self.full_slices(), &mut control, controllen)?;
+        if written == total_size {
+            return Ok(written);
+        }
+        // the remaining slices are copied so the batch ones are left untouched
+        let mut remaining = self.full_slices().to_vec();
+        let mut slices = &mut remaining[..];
+        IoSlice::advance_slices(&mut slices, written);
+        while written < total_size {
+            let n = sendmsg_retry(fd, slices, &mut [], 0)?;
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            IoSlice::advance_slices(&mut slices, n);
+            written += n;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::File,
+        io::{Read, Write},
+        mem,
+        os::{
+            fd::{AsRawFd, FromRawFd, RawFd},
+            unix::net::UnixStream,
+        },
+    };
+
+    use crate::{unix::ControlMessage, VectoredQueue};
+
+    fn recv_fd(socket: &UnixStream, buf: &mut [u8]) -> (usize, RawFd) {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        let mut control = [0u64; 8];
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        let n = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+        assert!(n > 0);
+        let header = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        assert!(!header.is_null());
+        assert_eq!(unsafe { (*header).cmsg_type }, libc::SCM_RIGHTS);
+        let fd = unsafe { libc::CMSG_DATA(header).cast::<RawFd>().read_unaligned() };
+        (n as usize, fd)
+    }
+
+    #[test]
+    fn send_contiguous() {
+        let (sender, mut receiver) = UnixStream::pair().unwrap();
+        let queue = VectoredQueue::with_capacity(1);
+        queue.try_enqueue(b"hello".to_vec()).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.sendmsg(sender.as_raw_fd(), &[]).unwrap(), 5);
+        let mut buf = [0; 5];
+        receiver.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn sendmsg() {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+        let (passed, mut peer) = UnixStream::pair().unwrap();
+        let queue = VectoredQueue::with_capacity(2);
+        queue.try_enqueue(b"hello ".to_vec()).unwrap();
+        queue.try_enqueue(b"world".to_vec()).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let cmsgs = [ControlMessage::ScmRights(&[passed.as_raw_fd()])];
+        assert_eq!(vectored.sendmsg(sender.as_raw_fd(), &cmsgs).unwrap(), 11);
+        let mut buf = [0; 16];
+        let (n, fd) = recv_fd(&receiver, &mut buf);
+        assert_eq!(&buf[..n], b"hello world");
+        // the received descriptor refers to the passed socket
+        unsafe { File::from_raw_fd(fd) }.write_all(b"!").unwrap();
+        let mut byte = [0];
+        peer.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"!");
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+}
@@ -1,104 +1,776 @@
 use std::{
+    cmp,
     collections::Bound,
     fmt,
-    io::IoSlice,
+    io::{self, IoSlice, Write},
+    marker::PhantomData,
     mem,
     ops::{Deref, DerefMut, RangeBounds},
+    ptr::NonNull,
+    slice,
 };
 
-use crate::queue::VectoredQueue;
+use crate::{
+    alloc::{Allocator, Global},
+    queue::VectoredQueue,
+};
 
-pub struct Vectored<'a, T>
+pub struct Vectored<'a, T, A: Allocator + Clone = Global>
 where
     T: AsRef<[u8]>,
 {
-    pub(crate) queue: &'a VectoredQueue<T>,
+    pub(crate) queue: &'a VectoredQueue<T, A>,
     pub(crate) buffer_index: usize,
-    pub(crate) slices: &'a mut [IoSlice<'a>],
+    // number of dequeued items, to be released, whatever the slices have been split
+    pub(crate) items: usize,
+    // slots of the buffer, which are also written by the queue when the batch is released, so
+    // they are not borrowed for the whole lifetime of the batch
+    pub(crate) slices: NonNull<[IoSlice<'a>]>,
+    pub(crate) marker: PhantomData<&'a mut [IoSlice<'a>]>,
+    // numbers of header and trailer slots surrounding the payload slots
+    pub(crate) reserved_slots: (usize, usize),
     pub(crate) total_size: usize,
+    pub(crate) framed: [Option<(usize, IoSlice<'a>)>; 2],
+    pub(crate) split: bool,
+    // dequeuing state of the consumer token, if dequeued with it
+    pub(crate) token: Option<&'a mut usize>,
 }
 
-impl<T> fmt::Debug for Vectored<'_, T>
+impl<T, A: Allocator + Clone> fmt::Debug for Vectored<'_, T, A>
 where
     T: AsRef<[u8]>,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Vectored")
-            .field("slices", &self.slices)
+            .field("slices", &self.full_slices())
             .field("total_size", &self.total_size)
             .finish()
     }
 }
 
-impl<'a, T> Deref for Vectored<'a, T>
+unsafe impl<T, A: Allocator + Clone> Send for Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+    VectoredQueue<T, A>: Sync,
+{
+}
+
+unsafe impl<T, A: Allocator + Clone> Sync for Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+    VectoredQueue<T, A>: Sync,
+{
+}
+
+impl<'a, T, A: Allocator + Clone> Deref for Vectored<'a, T, A>
 where
     T: AsRef<[u8]>,
 {
     type Target = [IoSlice<'a>];
     fn deref(&self) -> &Self::Target {
-        &self.slices[1..self.slices.len() - 1]
+        let (header_slots, trailer_slots) = self.reserved_slots;
+        let slices = self.full_slices();
+        &slices[header_slots..slices.len() - trailer_slots]
     }
 }
 
-impl<'a, T> DerefMut for Vectored<'a, T>
+impl<'a, T, A: Allocator + Clone> DerefMut for Vectored<'a, T, A>
 where
     T: AsRef<[u8]>,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        let slices_len = self.slices.len();
-        &mut self.slices[1..slices_len - 1]
+        let (header_slots, trailer_slots) = self.reserved_slots;
+        let slices = self.full_slices_mut();
+        let slices_len = slices.len();
+        &mut slices[header_slots..slices_len - trailer_slots]
     }
 }
 
-impl<'a, T> Vectored<'a, T>
+/// Numbers of items and bytes of a batch sharing a tag, see [`Vectored::tag_summary`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TagSummary {
+    pub tag: u64,
+    pub items: usize,
+    pub bytes: usize,
+}
+
+impl<'a, T, A: Allocator + Clone> Vectored<'a, T, A>
 where
     T: AsRef<[u8]>,
 {
+    /// Total size of the slices, which never exceeds `isize::MAX`.
+    ///
+    /// Enqueuing is rejected as full when it would make the total size exceed this maximum.
     pub fn total_size(&self) -> usize {
         self.total_size
     }
 
-    pub fn frame<'b>(
-        &mut self,
+    /// Items of the batch with their tags, see
+    /// [`VectoredQueue::try_enqueue_tagged`]; the payload slices may have been modified since.
+    pub fn items_with_tags(&self) -> impl Iterator<Item = (&T, u64)> + '_ {
+        self.queue.gotten_items(self.buffer_index, self.items)
+    }
+
+    /// Numbers of items and bytes of the batch per tag, in order of first appearance, e.g.
+    /// for per-stream flow control.
+    pub fn tag_summary(&self) -> Vec<TagSummary> {
+        let mut summary = Vec::<TagSummary>::new();
+        for (item, tag) in self.items_with_tags() {
+            let bytes = item.as_ref().len();
+            match summary.iter_mut().find(|summary| summary.tag == tag) {
+                Some(summary) => {
+                    summary.items += 1;
+                    summary.bytes += bytes;
+                }
+                None => summary.push(TagSummary {
+                    tag,
+                    items: 1,
+                    bytes,
+                }),
+            }
+        }
+        summary
+    }
+
+    /// Release the batch, returning its [tag summary](Self::tag_summary).
+    pub fn release_with_summary(self) -> Vec<TagSummary> {
+        self.tag_summary()
+    }
+
+    /// Feed each payload slice, in order, into a streaming encoder, e.g.
+    /// `flate2::write::DeflateEncoder`.
+    ///
+    /// It avoids concatenating the batch into an intermediate buffer before compression.
+    pub fn compress_into(&self, encoder: &mut impl Write) -> io::Result<()> {
+        for slice in self.iter() {
+            encoder.write_all(slice)?;
+        }
+        Ok(())
+    }
+
+    /// Write the [full slices](Self::full_slices) with a single `write_vectored` call,
+    /// retried if interrupted, and consume the written bytes, which are returned.
+    ///
+    /// Written header and trailer slots are shrunk, and the payload is
+    /// [advanced](Self::advance). Writers without vectored writes implement `write_vectored`
+    /// by writing the first non-empty slice.
+    pub fn write_to(&mut self, writer: &mut impl Write) -> io::Result<usize> {
+        let written = loop {
+            match writer.write_vectored(self.full_slices()) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                res => break res?,
+            }
+        };
+        self.advance_full(written);
+        Ok(written)
+    }
+
+    /// Write the whole [full slices](Self::full_slices) with [`write_to`](Self::write_to),
+    /// returning the number of written bytes.
+    ///
+    /// On error, e.g. [`io::ErrorKind::WouldBlock`] for a non-blocking socket, the written
+    /// bytes have been consumed, so writing can be resumed with the same call; a writer
+    /// accepting no bytes fails with [`io::ErrorKind::WriteZero`].
+    pub fn write_all_to(&mut self, writer: &mut impl Write) -> io::Result<usize> {
+        let mut written = 0;
+        while self.full_size() > 0 {
+            match self.write_to(writer)? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => written += n,
+            }
+        }
+        Ok(written)
+    }
+
+    // Consume `n` bytes of the full slices
+    pub(crate) fn advance_full(&mut self, n: usize) {
+        let (header_slots, trailer_slots) = self.reserved_slots;
+        let n = shrink_slots(&mut self.full_slices_mut()[..header_slots], n);
+        let payload = cmp::min(n, self.total_size);
+        self.advance(payload);
+        let slices = self.full_slices_mut();
+        let len = slices.len();
+        let n = shrink_slots(&mut slices[len - trailer_slots..], n - payload);
+        assert_eq!(n, 0, "advancing past the end of the batch");
+    }
+
+    pub(crate) fn full_size(&self) -> usize {
+        self.full_slices().iter().map(|slice| slice.len()).sum()
+    }
+
+    /// Build slices alternating payload slices with the delimiter, each payload slice being
+    /// followed by one, e.g. for newline-delimited messages.
+    ///
+    /// Stored payloads are not modified; the result only borrows them.
+    pub fn interleave_delimiter(&self, delim: &'static [u8]) -> InterleavedVectored<'_> {
+        let slices = self
+            .iter()
+            .flat_map(|slice| [*slice, IoSlice::new(delim)])
+            .collect();
+        InterleavedVectored {
+            slices,
+            total_size: self.total_size + self.len() * delim.len(),
+        }
+    }
+
+    /// Split off a view of the first `n` bytes of the payload, or all of it if `n` exceeds the
+    /// total size.
+    ///
+    /// The view ends with a truncated slice if `n` falls inside an item, the rest of which is
+    /// kept at the head of the remaining payload, once the view is dropped. The header slots,
+    /// if set, precede the remaining payload; items are still released with the batch.
+    pub fn split_to(&mut self, n: usize) -> VectoredSlice<'_> {
+        self.restore_framed();
+        self.split = true;
+        let n = cmp::min(n, self.total_size);
+        let (mut whole, mut whole_size) = (0, 0);
+        while whole < self.len() && whole_size + self[whole].len() <= n {
+            whole_size += self[whole].len();
+            whole += 1;
+        }
+        let header_slots = self.reserved_slots.0;
+        let truncated = n - whole_size;
+        let suffix = (truncated > 0).then(|| {
+            let slot = &mut self.full_slices_mut()[header_slots + whole];
+            // the slice borrows the batch, not the slot
+            let slice: &'a [u8] = unsafe { slice::from_raw_parts(slot.as_ptr(), slot.len()) };
+            *slot = IoSlice::new(&slice[..truncated]);
+            IoSlice::new(&slice[truncated..])
+        });
+        let view_end = header_slots + whole + suffix.is_some() as usize;
+        let slices = self.slices;
+        // the last whole slots become the header slots of the remaining payload
+        self.slices = sub_slice(slices, whole, slices.len());
+        self.total_size -= n;
+        VectoredSlice {
+            slices: unsafe { sub_slice(slices, 0, view_end).as_mut() },
+            header_slots,
+            shift: whole,
+            suffix,
+            total_size: n,
+        }
+    }
+
+    /// Consume the first `n` bytes of the payload, e.g. after a partial `write_vectored`,
+    /// like [`IoSlice::advance_slices`].
+    ///
+    /// Consumed slices are removed, and a partially consumed one is shrunk, as with
+    /// [`split_to`](Self::split_to); items are still released with the batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` exceeds the total size.
+    pub fn advance(&mut self, n: usize) {
+        assert!(n <= self.total_size, "advancing past the end of the batch");
+        drop(self.split_to(n));
+    }
+
+    /// Fuse the payload slices which are contiguous parts of `backing`, e.g. slices of a
+    /// single buffer, and return the resulting number of payload slices.
+    ///
+    /// Fused slices are taken from `backing`, so slices of other allocations are never fused,
+    /// even if adjacent in memory. The total size is unchanged, and the items are still
+    /// released with the batch, but the slices no longer match the item boundaries. The
+    /// trailer slots follow the fused payload, and a leaked frame is restored beforehand.
+    pub fn merge_contiguous(&mut self, backing: &'a [u8]) -> usize {
+        self.restore_framed();
+        let (header_slots, trailer_slots) = self.reserved_slots;
+        // offsets of the slice bounds in `backing`, if it is a part of it
+        let bounds = |slice: &[u8]| {
+            let start = (slice.as_ptr() as usize).checked_sub(backing.as_ptr() as usize)?;
+            let end = start + slice.len();
+            (end <= backing.len()).then_some((start, end))
+        };
+        let payload = &mut **self;
+        let mut merged: usize = 0;
+        let mut last = None;
+        for i in 0..payload.len() {
+            let next = payload[i];
+            match (last, bounds(&next)) {
+                (Some((start, prev_end)), Some((next_start, end))) if prev_end == next_start => {
+                    payload[merged - 1] = IoSlice::new(&backing[start..end]);
+                    last = Some((start, end));
+                }
+                (_, next_bounds) => {
+                    payload[merged] = next;
+                    merged += 1;
+                    last = next_bounds;
+                }
+            }
+        }
+        let end = header_slots + self.len();
+        self.full_slices_mut()
+            .copy_within(end..end + trailer_slots, header_slots + merged);
+        self.slices = sub_slice(self.slices, 0, header_slots + merged + trailer_slots);
+        merged
+    }
+
+    /// Split the batch into its header slot, its payload slots and its trailer slot.
+    ///
+    /// Header and trailer slots are empty unless set by the caller; they are sent with the
+    /// payload when writing [`full_slices`](Self::full_slices). Replacing payload slots is
+    /// allowed, as long as replacing slices outlive the batch.
+    ///
+    /// With several [reserved slots](crate::VectoredQueueBuilder::reserved_slots), the ones
+    /// adjacent to the payload are returned, see [`reserved_mut`](Self::reserved_mut).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue has no header or no trailer slot.
+    pub fn parts_mut(&mut self) -> (&mut IoSlice<'a>, &mut [IoSlice<'a>], &mut IoSlice<'a>) {
+        let (headers, payload, trailers) = self.split_reserved();
+        let header = headers.last_mut().expect("no header slot");
+        let trailer = trailers.first_mut().expect("no trailer slot");
+        (header, payload, trailer)
+    }
+
+    /// All the header slots and trailer slots of the batch, e.g. to install several headers
+    /// without concatenating them.
+    pub fn reserved_mut(&mut self) -> (&mut [IoSlice<'a>], &mut [IoSlice<'a>]) {
+        let (headers, _, trailers) = self.split_reserved();
+        (headers, trailers)
+    }
+
+    fn split_reserved(&mut self) -> (&mut [IoSlice<'a>], &mut [IoSlice<'a>], &mut [IoSlice<'a>]) {
+        let (header_slots, trailer_slots) = self.reserved_slots;
+        let (headers, slices) = self.full_slices_mut().split_at_mut(header_slots);
+        let (payload, trailers) = slices.split_at_mut(slices.len() - trailer_slots);
+        (headers, payload, trailers)
+    }
+
+    /// The payload slice of a batch of a single item, e.g. to write it with `write` instead of
+    /// `write_vectored`.
+    ///
+    /// `None` is returned if a header or trailer slot is set, if a leaked frame is still
+    /// installed, or once the batch has been [split](Self::split_to).
+    pub fn as_contiguous(&self) -> Option<&[u8]> {
+        match &**self {
+            [payload]
+                if self
+                    .full_slices()
+                    .iter()
+                    .map(|slice| slice.len())
+                    .sum::<usize>()
+                    == payload.len()
+                    && self.framed.iter().all(Option::is_none)
+                    && !self.split =>
+            {
+                Some(payload)
+            }
+            _ => None,
+        }
+    }
+
+    /// Payload slots surrounded by header and trailer slots.
+    pub fn full_slices(&self) -> &[IoSlice<'a>] {
+        unsafe { self.slices.as_ref() }
+    }
+
+    /// Mutable version of [`full_slices`](Self::full_slices).
+    pub fn full_slices_mut(&mut self) -> &mut [IoSlice<'a>] {
+        unsafe { self.slices.as_mut() }
+    }
+
+    /// Frame a range of the payload slices with a header and a trailer, installed in the
+    /// slots right before and after the range, and restored when the frame is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, or if a header or a trailer is given without a
+    /// slot before or after the range, which happens only for the whole payload of a queue
+    /// without [reserved slots](crate::VectoredQueueBuilder::reserved_slots).
+    ///
+    /// # Examples
+    ///
+    /// The frame borrows the batch, so the batch cannot be released while it is alive:
+    ///
+    /// ```compile_fail
+    /// use std::io::IoSlice;
+    ///
+    /// use vectored_queue::VectoredQueue;
+    ///
+    /// let queue = VectoredQueue::with_capacity(1);
+    /// queue.try_enqueue(b"payload".to_vec()).unwrap();
+    /// let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+    /// let frame = vectored.frame(.., Some(IoSlice::new(b"<")), Some(IoSlice::new(b">")));
+    /// drop(vectored);
+    /// assert!(frame == b"<payload>"[..]);
+    /// ```
+    pub fn frame<'s>(
+        &'s mut self,
         range: impl RangeBounds<usize>,
-        mut header: Option<IoSlice<'b>>,
-        mut trailer: Option<IoSlice<'b>>,
-    ) -> VectoredFrame<'b> {
-        let mut start = match range.start_bound() {
+        mut header: Option<IoSlice<'s>>,
+        mut trailer: Option<IoSlice<'s>>,
+    ) -> VectoredFrame<'s> {
+        let payload_start = match range.start_bound() {
             Bound::Included(&n) => n,
             Bound::Excluded(&n) => n + 1,
             Bound::Unbounded => 0,
         };
-        let mut end = match range.end_bound() {
-            Bound::Included(&n) => n + 2,
-            Bound::Excluded(&n) => n + 1,
-            Bound::Unbounded => self.slices.len(),
+        let payload_end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(
+            payload_start <= payload_end && payload_end <= self.len(),
+            "frame range out of bounds"
+        );
+        let slice_len = |slice: &Option<IoSlice>| slice.as_ref().map_or(0, |slice| slice.len());
+        let payload_size = if (payload_start, payload_end) == (0, self.len()) {
+            self.total_size
+        } else {
+            self[payload_start..payload_end]
+                .iter()
+                .map(|slice| slice.len())
+                .sum()
         };
-        let slices: &'b mut [IoSlice<'b>] = unsafe { mem::transmute(&mut *(self.slices)) };
+        let total_size = payload_size + slice_len(&header) + slice_len(&trailer);
+        // indexes in the full slices
+        let mut start = payload_start + self.reserved_slots.0;
+        let mut end = payload_end + self.reserved_slots.0;
+        if header.is_some() {
+            start = start
+                .checked_sub(1)
+                .expect("no slot before the framed range");
+        }
+        if trailer.is_some() {
+            assert!(end < self.slices.len(), "no slot after the framed range");
+            end += 1;
+        }
+        // a previous frame may have been leaked without restoring its slots
+        self.restore_framed();
+        if header.is_some() {
+            self.framed[0] = Some((start, self.full_slices()[start]));
+        }
+        if trailer.is_some() {
+            self.framed[1] = Some((end - 1, self.full_slices()[end - 1]));
+        }
+        // the swapped slots are restored before the batch is released
+        let slices: &'s mut [IoSlice<'s>] =
+            unsafe { slice::from_raw_parts_mut(self.slices.as_ptr().cast(), self.slices.len()) };
         if let Some(ref mut header) = header {
             mem::swap(header, &mut slices[start]);
-        } else {
-            start += 1;
         }
         if let Some(ref mut trailer) = trailer {
             mem::swap(trailer, &mut slices[end - 1]);
-        } else {
-            end -= 1;
         }
         VectoredFrame {
             slices: &mut slices[start..end],
             header,
             trailer,
+            total_size,
+        }
+    }
+
+    /// Split the payload into consecutive frames of at most `max_slices` slices and, if given,
+    /// `max_bytes` bytes, e.g. to respect `IOV_MAX` or a record size limit.
+    ///
+    /// Slices are never split: a slice larger than `max_bytes` is framed alone. Limits only
+    /// apply to the payload, so a per-chunk header must be accounted for by the caller.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_slices` is zero.
+    pub fn chunks(
+        &mut self,
+        max_slices: usize,
+        max_bytes: Option<usize>,
+    ) -> VectoredChunks<'_, 'a, T, A> {
+        assert_ne!(max_slices, 0, "maximum slices must not be zero");
+        VectoredChunks {
+            vectored: self,
+            max_slices,
+            max_bytes: max_bytes.unwrap_or(usize::MAX),
+            next: 0,
+        }
+    }
+
+    /// Take ownership of the dequeued items, releasing the batch.
+    pub fn into_vec(self) -> Vec<T> {
+        let mut this = mem::ManuallyDrop::new(self);
+        let token = this.token.take();
+        this.queue
+            .release_into_vec(this.buffer_index, this.items, token)
+    }
+
+    // Leave the batch in the queue, to be dequeued again
+    fn rollback(self) {
+        let mut this = mem::ManuallyDrop::new(self);
+        this.restore_framed();
+        let token = this.token.take();
+        this.queue.rollback(this.buffer_index, this.items, token);
+    }
+
+    fn restore_framed(&mut self) {
+        let slices = unsafe { self.slices.as_mut() };
+        for (index, slice) in self.framed.iter_mut().filter_map(Option::take) {
+            slices[index] = slice;
         }
     }
 }
 
-impl<'a, T> Drop for Vectored<'a, T>
+/// Take ownership of the dequeued items, see [`Vectored::into_vec`].
+impl<T, A: Allocator + Clone> IntoIterator for Vectored<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> Drop for Vectored<'a, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn drop(&mut self) {
+        self.restore_framed();
+        let token = self.token.take();
+        self.queue.release(self.buffer_index, self.items, token);
+    }
+}
+
+// Consume at most `n` bytes of the slots, returning the remaining ones
+fn shrink_slots(slots: &mut [IoSlice], mut n: usize) -> usize {
+    for slot in slots {
+        let len = cmp::min(slot.len(), n);
+        slot.advance(len);
+        n -= len;
+    }
+    n
+}
+
+// Sub-slice of the given range, without borrowing the slice
+fn sub_slice<T>(slice: NonNull<[T]>, start: usize, end: usize) -> NonNull<[T]> {
+    assert!(start <= end && end <= slice.len());
+    let ptr = unsafe { slice.cast::<T>().add(start) };
+    NonNull::slice_from_raw_parts(ptr, end - start)
+}
+
+/// Dequeued batch which is only released on [`commit`](Self::commit), and left in the queue
+/// otherwise.
+pub struct DequeueTxn<'a, T, A: Allocator + Clone = Global>
+where
+    T: AsRef<[u8]>,
+{
+    pub(crate) vectored: mem::ManuallyDrop<Vectored<'a, T, A>>,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for DequeueTxn<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DequeueTxn").field(&*self.vectored).finish()
+    }
+}
+
+impl<T, A: Allocator + Clone> DequeueTxn<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn total_size(&self) -> usize {
+        self.vectored.total_size()
+    }
+
+    /// Release the batch, as dropping a [`Vectored`] does.
+    pub fn commit(self) {
+        let mut this = mem::ManuallyDrop::new(self);
+        unsafe { mem::ManuallyDrop::drop(&mut this.vectored) };
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> Deref for DequeueTxn<'a, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    type Target = [IoSlice<'a>];
+    fn deref(&self) -> &Self::Target {
+        &self.vectored
+    }
+}
+
+impl<T, A: Allocator + Clone> Drop for DequeueTxn<'_, T, A>
 where
     T: AsRef<[u8]>,
 {
     fn drop(&mut self) {
-        self.queue.release(self.buffer_index, self.slices.len() - 2);
+        unsafe { mem::ManuallyDrop::take(&mut self.vectored) }.rollback();
+    }
+}
+
+/// Dequeued batch, released when dropped, whose slices are copied into a caller-provided
+/// buffer instead of being lent from the queue.
+pub struct DequeueTicket<'a, T, A: Allocator + Clone = Global>
+where
+    T: AsRef<[u8]>,
+{
+    pub(crate) vectored: Vectored<'a, T, A>,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for DequeueTicket<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DequeueTicket")
+            .field(&self.vectored)
+            .finish()
+    }
+}
+
+impl<T, A: Allocator + Clone> DequeueTicket<'_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn total_size(&self) -> usize {
+        self.vectored.total_size()
+    }
+
+    /// Number of payload slices.
+    pub fn len(&self) -> usize {
+        self.vectored.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectored.is_empty()
+    }
+
+    /// Append the payload slices to `out`, which borrows the ticket, so they cannot outlive
+    /// the batch; other slices can be freely added around them.
+    pub fn copy_into<'t>(&'t self, out: &mut Vec<IoSlice<'t>>) {
+        out.extend_from_slice(&self.vectored);
+    }
+}
+
+/// Dequeued batch, released when dropped, whose slices are copied into a caller-provided
+/// buffer, see [`VectoredQueue::try_dequeue_into`].
+///
+/// The buffer is truncated back to its original length when dropped, so the payload slices
+/// cannot outlive the batch; for the same reason, they are only lent for the borrow of
+/// [`slices`](Self::slices).
+pub struct DequeueInto<'a, 'v, T, A: Allocator + Clone = Global>
+where
+    T: AsRef<[u8]>,
+{
+    pub(crate) ticket: DequeueTicket<'a, T, A>,
+    pub(crate) out: &'v mut Vec<IoSlice<'a>>,
+    // length of the buffer before the payload slices
+    pub(crate) start: usize,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for DequeueInto<'_, '_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DequeueInto")
+            .field("ticket", &self.ticket)
+            .field("out", &self.out)
+            .finish()
+    }
+}
+
+impl<'a, T, A: Allocator + Clone> DequeueInto<'a, '_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    pub fn ticket(&self) -> &DequeueTicket<'a, T, A> {
+        &self.ticket
+    }
+
+    /// Slices of the buffer, i.e. the ones already present, the payload slices, then the
+    /// pushed ones.
+    pub fn slices(&self) -> &[IoSlice<'_>] {
+        self.out
+    }
+
+    /// Append a slice after the payload ones, e.g. a trailer.
+    pub fn push(&mut self, slice: IoSlice<'a>) {
+        self.out.push(slice);
+    }
+}
+
+impl<T, A: Allocator + Clone> Drop for DequeueInto<'_, '_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn drop(&mut self) {
+        self.out.truncate(self.start);
+    }
+}
+
+/// Slices built by [`Vectored::interleave_delimiter`].
+#[derive(Debug)]
+pub struct InterleavedVectored<'a> {
+    slices: Vec<IoSlice<'a>>,
+    total_size: usize,
+}
+
+impl InterleavedVectored<'_> {
+    /// Total size of the slices, delimiters included.
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+}
+
+impl<'a> Deref for InterleavedVectored<'a> {
+    type Target = [IoSlice<'a>];
+    fn deref(&self) -> &Self::Target {
+        &self.slices
+    }
+}
+
+/// First bytes of a batch, split by [`Vectored::split_to`].
+pub struct VectoredSlice<'a> {
+    // the view is preceded by the header slots, moved by `shift` slots on drop to precede
+    // the remaining payload
+    slices: &'a mut [IoSlice<'a>],
+    header_slots: usize,
+    shift: usize,
+    // slot shared with the remaining payload, restored on drop
+    suffix: Option<IoSlice<'a>>,
+    total_size: usize,
+}
+
+impl fmt::Debug for VectoredSlice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VectoredSlice")
+            .field("slices", &&**self)
+            .field("total_size", &self.total_size)
+            .finish()
+    }
+}
+
+impl VectoredSlice<'_> {
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+}
+
+impl<'a> Deref for VectoredSlice<'a> {
+    type Target = [IoSlice<'a>];
+    fn deref(&self) -> &Self::Target {
+        &self.slices[self.header_slots..]
+    }
+}
+
+impl Drop for VectoredSlice<'_> {
+    fn drop(&mut self) {
+        if let Some(suffix) = self.suffix {
+            let last = self.slices.len() - 1;
+            self.slices[last] = suffix;
+        }
+        if self.shift > 0 {
+            // moved backward, as the header slots may overlap their new position
+            for i in (0..self.header_slots).rev() {
+                self.slices[i + self.shift] = self.slices[i];
+            }
+        }
     }
 }
 
@@ -106,6 +778,7 @@ pub struct VectoredFrame<'a> {
     slices: &'a mut [IoSlice<'a>],
     header: Option<IoSlice<'a>>,
     trailer: Option<IoSlice<'a>>,
+    total_size: usize,
 }
 
 impl fmt::Debug for VectoredFrame<'_> {
@@ -114,6 +787,20 @@ impl fmt::Debug for VectoredFrame<'_> {
     }
 }
 
+impl VectoredFrame<'_> {
+    /// Total size of the slices, header and trailer included, computed when framing.
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    /// Number of slices between the header and the trailer, if any.
+    pub fn payload_slice_count(&self) -> usize {
+        // header and trailer fields hold the swapped out slots, so they are set only when the
+        // frame has a header or a trailer
+        self.slices.len() - self.header.is_some() as usize - self.trailer.is_some() as usize
+    }
+}
+
 impl<'a> Deref for VectoredFrame<'a> {
     type Target = [IoSlice<'a>];
     fn deref(&self) -> &Self::Target {
@@ -137,3 +824,702 @@ impl<'a> Drop for VectoredFrame<'a> {
         }
     }
 }
+
+/// Frames of a batch, returned by [`Vectored::chunks`].
+///
+/// Each frame borrows the batch, so frames are yielded one at a time by
+/// [`next_frame`](Self::next_frame) instead of an iterator.
+pub struct VectoredChunks<'v, 'a, T, A: Allocator + Clone = Global>
+where
+    T: AsRef<[u8]>,
+{
+    vectored: &'v mut Vectored<'a, T, A>,
+    max_slices: usize,
+    max_bytes: usize,
+    // index of the first payload slice of the next chunk
+    next: usize,
+}
+
+impl<T, A: Allocator + Clone> fmt::Debug for VectoredChunks<'_, '_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VectoredChunks")
+            .field("max_slices", &self.max_slices)
+            .field("max_bytes", &self.max_bytes)
+            .field("next", &self.next)
+            .finish()
+    }
+}
+
+impl<T, A: Allocator + Clone> VectoredChunks<'_, '_, T, A>
+where
+    T: AsRef<[u8]>,
+{
+    // Payload range of the next chunk, with its size
+    fn next_chunk(&mut self) -> Option<(usize, usize, usize)> {
+        let (start, len) = (self.next, self.vectored.len());
+        if start == len {
+            return None;
+        }
+        let (mut end, mut size) = (start, 0);
+        while end < len && end - start < self.max_slices {
+            let slice_len = self.vectored[end].len();
+            if end > start && size + slice_len > self.max_bytes {
+                break;
+            }
+            size += slice_len;
+            end += 1;
+        }
+        self.next = end;
+        Some((start, end, size))
+    }
+
+    /// Frame the next chunk, `None` once the payload is exhausted.
+    pub fn next_frame(&mut self) -> Option<VectoredFrame<'_>> {
+        let (start, end, _) = self.next_chunk()?;
+        Some(self.vectored.frame(start..end, None, None))
+    }
+
+    /// Frame the next chunk with a header built from its number of slices and its size, e.g.
+    /// a length prefix.
+    ///
+    /// The header replaces the slot preceding the chunk, as with [`Vectored::frame`].
+    pub fn next_frame_with_header<'s>(
+        &'s mut self,
+        header: impl FnOnce(usize, usize) -> IoSlice<'s>,
+    ) -> Option<VectoredFrame<'s>> {
+        let (start, end, size) = self.next_chunk()?;
+        let header = header(end - start, size);
+        Some(self.vectored.frame(start..end, Some(header), None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{self, IoSlice, Write},
+        mem,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{queue::VectoredQueue, vectored::TagSummary};
+
+    #[test]
+    fn into_iter_drops_once() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Debug)]
+        struct Pooled(Box<[u8]>);
+        impl AsRef<[u8]> for Pooled {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+        impl Drop for Pooled {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let queue = VectoredQueue::with_capacity(4);
+        let enqueue = || (0..3).for_each(|i| queue.try_enqueue(Pooled([i].into())).unwrap());
+        enqueue();
+        drop(queue.try_dequeue_vectored().unwrap());
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 3);
+        enqueue();
+        // a partial batch is taken too
+        let first: Vec<_> = queue
+            .try_dequeue_n(2)
+            .unwrap()
+            .vectored()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 3);
+        assert_eq!(
+            first.iter().map(|item| item.0[0]).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        let rest = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let rest: Vec<_> = rest.into_iter().collect();
+        assert_eq!(rest[0].0[..], [2]);
+        assert!(queue.is_empty());
+        drop((first, rest));
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 6);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn leaked_frame() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let header = vec![1];
+        let trailer = vec![2];
+        let frame = vectored.frame(
+            ..,
+            Some(IoSlice::new(&header)),
+            Some(IoSlice::new(&trailer)),
+        );
+        assert_eq!(frame.len(), 3);
+        assert_eq!(frame.payload_slice_count(), 1);
+        mem::forget(frame);
+        drop(vectored);
+        queue.try_enqueue(vec![3]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored.full_slices()[0].is_empty());
+        assert!(vectored.full_slices()[2].is_empty());
+        assert_eq!(&*vectored[0], &[3]);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn frame_total_size() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        queue.try_enqueue(vec![2, 3, 4]).unwrap();
+        queue.try_enqueue(vec![5]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let frame = vectored.frame(.., Some(IoSlice::new(b"<<")), Some(IoSlice::new(b">")));
+        assert_eq!(frame.total_size(), 9);
+        drop(frame);
+        // the header replaces the first item outside of the range
+        let frame = vectored.frame(1..2, Some(IoSlice::new(b"<<")), None);
+        assert_eq!(frame.total_size(), 5);
+        drop(frame);
+        assert_eq!(vectored.frame(.., None, None).total_size(), 6);
+        assert_eq!(
+            vectored
+                .frame(2.., None, Some(IoSlice::new(b">")))
+                .total_size(),
+            2
+        );
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn chunks() {
+        let queue = VectoredQueue::with_capacity(8);
+        for item in [&b"ab"[..], b"cde", b"f", b"ghijk", b"l"] {
+            queue.try_enqueue(item.to_vec()).unwrap();
+        }
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let mut chunks = vectored.chunks(2, Some(4));
+        let mut frames = Vec::new();
+        while let Some(frame) = chunks.next_frame() {
+            frames.push((frame.payload_slice_count(), frame.total_size()));
+        }
+        // a slice larger than the maximum bytes is framed alone
+        assert_eq!(frames, [(1, 2), (2, 4), (1, 5), (1, 1)]);
+        let mut chunks = vectored.chunks(3, None);
+        assert!(chunks.next_frame().unwrap() == b"abcdef"[..]);
+        assert!(chunks.next_frame().unwrap() == b"ghijkl"[..]);
+        assert!(chunks.next_frame().is_none());
+        // length-prefixed chunks, headers replacing the slot preceding each chunk
+        const DIGITS: &[u8] = b"0123456789";
+        let mut chunks = vectored.chunks(2, Some(4));
+        let mut framed = Vec::<u8>::new();
+        while let Some(frame) =
+            chunks.next_frame_with_header(|_, size| IoSlice::new(&DIGITS[size..size + 1]))
+        {
+            framed.extend(frame.iter().flat_map(|slice| slice.iter()));
+        }
+        assert_eq!(framed, b"2ab4cdef5ghijk1l");
+        // slots are restored once the frames are dropped
+        assert!(vectored == b"abcdefghijkl"[..]);
+        assert!(vectored.eq_chunks(&[b"ab", b"cde", b"f", b"ghijk", b"l"]));
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn compress_into() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        queue.try_enqueue(vec![2]).unwrap();
+        let header = vec![42];
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        *vectored.parts_mut().0 = IoSlice::new(&header);
+        let mut encoder = Vec::new();
+        vectored.compress_into(&mut encoder).unwrap();
+        assert_eq!(encoder, vec![0, 1, 2]);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn interleave_delimiter() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        queue.try_enqueue(vec![2]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let interleaved = vectored.interleave_delimiter(b"\n");
+        assert_eq!(interleaved.len(), 4);
+        assert_eq!(interleaved.total_size(), 5);
+        let bytes: Vec<u8> = interleaved.iter().flat_map(|s| s.iter().copied()).collect();
+        assert_eq!(bytes, [0, 1, b'\n', 2, b'\n']);
+        assert_eq!(vectored.len(), 2);
+        drop(interleaved);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn as_contiguous() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.as_contiguous(), Some(&[0, 1][..]));
+        *vectored.parts_mut().0 = IoSlice::new(&[42]);
+        assert_eq!(vectored.as_contiguous(), None);
+        *vectored.parts_mut().0 = IoSlice::new(&[]);
+        drop(vectored.split_to(1));
+        assert_eq!(vectored.as_contiguous(), None);
+        drop(vectored);
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.try_enqueue(vec![1]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(vectored.as_contiguous(), None);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_ticket() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        queue.try_enqueue(vec![2]).unwrap();
+        let ticket = queue.try_dequeue_ticket().unwrap().unwrap();
+        assert_eq!((ticket.len(), ticket.total_size()), (2, 3));
+        let mut out = vec![IoSlice::new(b"header")];
+        ticket.copy_into(&mut out);
+        out.push(IoSlice::new(b"trailer"));
+        let slices: Vec<&[u8]> = out.iter().map(|slice| &**slice).collect();
+        assert_eq!(slices, [&b"header"[..], &[0, 1], &[2], b"trailer"]);
+        drop(out);
+        drop(ticket);
+        assert!(queue.is_empty());
+        assert!(queue.try_dequeue_ticket().unwrap().is_none());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn dequeue_into() {
+        let queue = VectoredQueue::with_capacity(4);
+        let mut out = Vec::new();
+        assert!(queue.try_dequeue_into(&mut out).unwrap().is_none());
+        for _ in 0..2 {
+            queue.try_enqueue(vec![0, 1]).unwrap();
+            queue.try_enqueue(vec![2]).unwrap();
+            out.push(IoSlice::new(b"header"));
+            let mut into = queue.try_dequeue_into(&mut out).unwrap().unwrap();
+            assert_eq!((into.ticket().len(), into.ticket().total_size()), (2, 3));
+            into.push(IoSlice::new(b"trailer"));
+            let slices: Vec<&[u8]> = into.slices().iter().map(|slice| &**slice).collect();
+            assert_eq!(slices, [&b"header"[..], &[0, 1], &[2], b"trailer"]);
+            drop(into);
+            // the buffer is reused, with its original slices
+            assert_eq!(out.len(), 1);
+            assert!(queue.is_empty());
+            out.clear();
+        }
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn split_to() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        queue.try_enqueue(vec![2, 3, 4]).unwrap();
+        queue.try_enqueue(vec![5]).unwrap();
+        let header = vec![42];
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        *vectored.parts_mut().0 = IoSlice::new(&header);
+        let collect = |slices: &[IoSlice]| -> Vec<u8> {
+            slices.iter().flat_map(|s| s.iter().copied()).collect()
+        };
+        let first = vectored.split_to(3);
+        assert_eq!(first.total_size(), 3);
+        assert_eq!(collect(&first), [0, 1, 2]);
+        drop(first);
+        assert_eq!(vectored.total_size(), 3);
+        assert_eq!(collect(&vectored), [3, 4, 5]);
+        assert_eq!(collect(vectored.full_slices()), [42, 3, 4, 5]);
+        let second = vectored.split_to(2);
+        assert_eq!(collect(&second), [3, 4]);
+        drop(second);
+        let third = vectored.split_to(10);
+        assert_eq!((third.len(), third.total_size()), (1, 1));
+        drop(third);
+        assert!(vectored.is_empty());
+        assert_eq!(vectored.total_size(), 0);
+        drop(vectored);
+        queue.try_enqueue(vec![6]).unwrap();
+        assert_eq!(
+            queue
+                .try_dequeue_vectored()
+                .unwrap()
+                .vectored()
+                .unwrap()
+                .len(),
+            1
+        );
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn advance() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        queue.try_enqueue(vec![2, 3, 4]).unwrap();
+        queue.try_enqueue(vec![5]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        vectored.advance(0);
+        assert!(vectored.eq_chunks(&[&[0, 1], &[2, 3, 4], &[5]]));
+        vectored.advance(3);
+        assert!(vectored.eq_chunks(&[&[3, 4], &[5]]));
+        assert_eq!(vectored.total_size(), 3);
+        // a frame is installed around the remaining payload
+        let frame = vectored.frame(1.., Some(IoSlice::new(b"<")), None);
+        assert!(frame == b"<\x05"[..]);
+        drop(frame);
+        vectored.advance(3);
+        assert!(vectored.is_empty());
+        assert_eq!(vectored.total_size(), 0);
+        drop(vectored);
+        queue.try_enqueue(vec![6]).unwrap();
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored.eq_chunks(&[&[6]]));
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    // Writer accepting at most `max` bytes per call, failing once after `fail_after` bytes
+    struct ShortWriter {
+        written: Vec<u8>,
+        max: usize,
+        fail_after: Option<(usize, io::ErrorKind)>,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if let Some((offset, kind)) = self.fail_after {
+                if self.written.len() >= offset {
+                    self.fail_after = None;
+                    return Err(kind.into());
+                }
+            }
+            let len = buf.len().min(self.max);
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_to() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1, 2]).unwrap();
+        queue.try_enqueue(vec![3]).unwrap();
+        queue.try_enqueue(vec![4, 5]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let mut writer = ShortWriter {
+            written: Vec::new(),
+            max: 2,
+            fail_after: Some((0, io::ErrorKind::Interrupted)),
+        };
+        assert_eq!(vectored.write_to(&mut writer).unwrap(), 2);
+        assert!(vectored.eq_chunks(&[&[2], &[3], &[4, 5]]));
+        assert_eq!(vectored.write_all_to(&mut writer).unwrap(), 4);
+        assert_eq!(writer.written, [0, 1, 2, 3, 4, 5]);
+        assert!(vectored.is_empty());
+        drop(vectored);
+        // header and trailer slots are written with the payload
+        queue.try_enqueue(vec![0, 1, 2]).unwrap();
+        queue.try_enqueue(vec![3]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let (header, _, trailer) = vectored.parts_mut();
+        (*header, *trailer) = (IoSlice::new(b"<<<"), IoSlice::new(b">>"));
+        writer.written.clear();
+        assert_eq!(vectored.write_to(&mut writer).unwrap(), 2);
+        assert!(vectored.full_slices()[0][..] == *b"<");
+        assert_eq!(vectored.write_all_to(&mut writer).unwrap(), 7);
+        assert_eq!(writer.written, b"<<<\x00\x01\x02\x03>>");
+        assert_eq!(vectored.full_size(), 0);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn write_all_to_error() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0, 1, 2]).unwrap();
+        queue.try_enqueue(vec![3, 4]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let mut writer = ShortWriter {
+            written: Vec::new(),
+            max: 2,
+            fail_after: Some((3, io::ErrorKind::WouldBlock)),
+        };
+        let err = vectored.write_all_to(&mut writer).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(vectored.total_size(), 2);
+        // writing is resumed where it stopped
+        vectored.write_all_to(&mut writer).unwrap();
+        assert_eq!(writer.written, [0, 1, 2, 3, 4]);
+        let mut writer = ShortWriter {
+            written: Vec::new(),
+            max: 0,
+            fail_after: None,
+        };
+        drop(vectored);
+        queue.try_enqueue(vec![0]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let err = vectored.write_all_to(&mut writer).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn advance_item() {
+        let queue = VectoredQueue::builder()
+            .capacity(3)
+            .reserved_slots(1, 1)
+            .build()
+            .unwrap();
+        queue.try_enqueue(vec![0, 1]).unwrap();
+        queue.try_enqueue(vec![2]).unwrap();
+        queue.try_enqueue(vec![3, 4]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let (header, _, trailer) = vectored.parts_mut();
+        (*header, *trailer) = (IoSlice::new(b"<"), IoSlice::new(b">"));
+        vectored.advance(2);
+        assert_eq!(vectored.total_size(), 3);
+        assert!(vectored.eq_chunks(&[&[2], &[3, 4]]));
+        let full_slices: Vec<&[u8]> = vectored.full_slices().iter().map(|s| &**s).collect();
+        assert_eq!(full_slices, [&b"<"[..], &[2], &[3, 4], b">"]);
+        drop(vectored);
+        assert!(queue.is_empty());
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "advancing past the end of the batch")]
+    fn advance_past_end() {
+        let queue = VectoredQueue::with_capacity(1);
+        queue.try_enqueue(vec![0]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        vectored.advance(2);
+    }
+
+    #[test]
+    fn reserved_slots() {
+        let bytes = |slices: &[IoSlice]| -> Vec<u8> {
+            slices.iter().flat_map(|s| s.iter().copied()).collect()
+        };
+        let marks: Vec<[u8; 1]> = (0..6).map(|i| [100 + i]).collect();
+        for (header_slots, trailer_slots) in (0..4).flat_map(|h| (0..4).map(move |t| (h, t))) {
+            let queue = VectoredQueue::builder()
+                .capacity(4)
+                .reserved_slots(header_slots, trailer_slots)
+                .build()
+                .unwrap();
+            let reserved = header_slots + trailer_slots;
+            queue.try_enqueue(vec![1, 1]).unwrap();
+            queue.try_enqueue(vec![2]).unwrap();
+            {
+                let _guard = queue.mark_high_priority();
+                queue.try_enqueue(vec![0]).unwrap();
+            }
+            let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+            assert_eq!(vectored.full_slices().len(), reserved + 3);
+            assert_eq!(bytes(vectored.full_slices()), [0, 1, 1, 2]);
+            assert_eq!(bytes(&vectored), [0, 1, 1, 2]);
+            // install every reserved slot
+            let (headers, trailers) = vectored.reserved_mut();
+            assert_eq!(
+                (headers.len(), trailers.len()),
+                (header_slots, trailer_slots)
+            );
+            for (slot, mark) in headers.iter_mut().chain(trailers).zip(&marks) {
+                *slot = IoSlice::new(mark);
+            }
+            let marked = |range: std::ops::Range<usize>| marks[range].iter().map(|m| m[0]);
+            let expected: Vec<u8> = marked(0..header_slots)
+                .chain([0, 1, 1, 2])
+                .chain(marked(header_slots..reserved))
+                .collect();
+            assert_eq!(bytes(vectored.full_slices()), expected);
+            // a bounded frame takes the slots of its neighbours, restored on drop
+            let frame = vectored.frame(1..2, Some(IoSlice::new(&[10])), Some(IoSlice::new(&[20])));
+            assert_eq!(bytes(&frame), [10, 1, 1, 20]);
+            assert_eq!(frame.payload_slice_count(), 1);
+            drop(frame);
+            let frame = vectored.frame(..=1, None, None);
+            assert_eq!(bytes(&frame), [0, 1, 1]);
+            drop(frame);
+            if header_slots > 0 && trailer_slots > 0 {
+                let frame =
+                    vectored.frame(.., Some(IoSlice::new(&[10])), Some(IoSlice::new(&[20])));
+                assert_eq!(bytes(&frame), [10, 0, 1, 1, 2, 20]);
+                drop(frame);
+                assert_eq!(vectored.parts_mut().0[0], marks[header_slots - 1][0]);
+            }
+            assert_eq!(bytes(vectored.full_slices()), expected);
+            // the header slots are moved before the remaining payload
+            let first = vectored.split_to(2);
+            assert_eq!(bytes(&first), [0, 1]);
+            drop(first);
+            assert_eq!(vectored.full_slices().len(), reserved + 2);
+            let expected: Vec<u8> = marked(0..header_slots)
+                .chain([1, 2])
+                .chain(marked(header_slots..reserved))
+                .collect();
+            assert_eq!(bytes(vectored.full_slices()), expected);
+            drop(vectored);
+            // reserved slots are reset for the next batches, including partial ones
+            for i in 0..3 {
+                queue.try_enqueue(vec![i]).unwrap();
+            }
+            for i in 0..3 {
+                let mut vectored = queue.try_dequeue_n(1).unwrap().vectored().unwrap();
+                assert_eq!(vectored.full_slices().len(), reserved + 1);
+                assert_eq!(bytes(vectored.full_slices()), [i]);
+                assert_eq!(vectored.as_contiguous(), Some(&[i][..]));
+                for slot in vectored.full_slices_mut() {
+                    *slot = IoSlice::new(&marks[0]);
+                }
+            }
+            assert!(queue.is_empty());
+            queue.debug_assert_invariants();
+        }
+    }
+
+    #[test]
+    fn tag_summary() {
+        let queue = VectoredQueue::builder()
+            .capacity(8)
+            .item_tags()
+            .build()
+            .unwrap();
+        queue.try_enqueue_tagged(vec![0; 3], 1).unwrap();
+        queue.try_enqueue_tagged(vec![0; 5], 2).unwrap();
+        queue.try_enqueue(vec![0; 2]).unwrap();
+        queue.try_enqueue_tagged(vec![0; 4], 1).unwrap();
+        {
+            let _guard = queue.mark_high_priority();
+            queue.try_enqueue_tagged(vec![0; 1], 2).unwrap();
+        }
+        let mut vectored = queue.try_dequeue_n(3).unwrap().vectored().unwrap();
+        // tags are those of the items, whatever the slices become
+        vectored[0] = IoSlice::new(b"");
+        let tags: Vec<_> = vectored.items_with_tags().map(|(_, tag)| tag).collect();
+        assert_eq!(tags, [2, 1, 2]);
+        assert_eq!(
+            vectored.release_with_summary(),
+            [
+                TagSummary {
+                    tag: 2,
+                    items: 2,
+                    bytes: 6
+                },
+                TagSummary {
+                    tag: 1,
+                    items: 1,
+                    bytes: 3
+                },
+            ]
+        );
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(
+            vectored.tag_summary(),
+            [
+                TagSummary {
+                    tag: 0,
+                    items: 1,
+                    bytes: 2
+                },
+                TagSummary {
+                    tag: 1,
+                    items: 1,
+                    bytes: 4
+                },
+            ]
+        );
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "item tags are not enabled")]
+    fn tags_disabled() {
+        let queue = VectoredQueue::with_capacity(1);
+        queue.try_enqueue_tagged(vec![0], 1).unwrap();
+    }
+
+    #[test]
+    fn merge_contiguous() {
+        static DATA: [u8; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+        let bytes = |slices: &[IoSlice]| -> Vec<u8> {
+            slices.iter().flat_map(|s| s.iter().copied()).collect()
+        };
+        let queue = VectoredQueue::with_capacity(8);
+        for range in [0..2, 2..5, 6..7, 7..8, 3..4] {
+            queue.try_enqueue(&DATA[range]).unwrap();
+        }
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        vectored.reserved_mut().1[0] = IoSlice::new(b"!");
+        assert_eq!(vectored.merge_contiguous(&DATA), 3);
+        assert_eq!(vectored.len(), 3);
+        assert_eq!(vectored.total_size(), 8);
+        assert_eq!(
+            bytes(vectored.full_slices()),
+            [0, 1, 2, 3, 4, 6, 7, 3, b'!']
+        );
+        assert_eq!(vectored.into_vec().len(), 5);
+        // a partial batch leaves the next items untouched
+        for range in [0..2, 2..4, 4..6] {
+            queue.try_enqueue(&DATA[range]).unwrap();
+        }
+        let mut vectored = queue.try_dequeue_n(2).unwrap().vectored().unwrap();
+        // slices outside the backing are not fused
+        assert_eq!(vectored.merge_contiguous(&DATA[..3]), 2);
+        assert_eq!(vectored.merge_contiguous(&DATA[..4]), 1);
+        assert_eq!(bytes(vectored.full_slices()), [0, 1, 2, 3]);
+        drop(vectored);
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert_eq!(bytes(vectored.full_slices()), [4, 5]);
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+
+    #[test]
+    fn parts_mut() {
+        let queue = VectoredQueue::with_capacity(4);
+        queue.try_enqueue(vec![0]).unwrap();
+        queue.try_enqueue(vec![1]).unwrap();
+        let header = vec![2];
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        let (header_slot, payload, trailer_slot) = vectored.parts_mut();
+        assert_eq!(payload.len(), 2);
+        assert!(header_slot.is_empty() && trailer_slot.is_empty());
+        *header_slot = IoSlice::new(&header);
+        let bytes: Vec<u8> = vectored
+            .full_slices()
+            .iter()
+            .flat_map(|s| s.iter().copied())
+            .collect();
+        assert_eq!(bytes, vec![2, 0, 1]);
+        drop(vectored);
+        queue.try_enqueue(vec![3]).unwrap();
+        let mut vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        assert!(vectored.parts_mut().0.is_empty());
+        drop(vectored);
+        queue.debug_assert_invariants();
+    }
+}
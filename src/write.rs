@@ -0,0 +1,98 @@
+use std::io::{self, Write};
+
+#[cfg(feature = "async")]
+use crate::r#async::AsyncVectoredQueue;
+#[cfg(feature = "sync")]
+use crate::sync::SyncVectoredQueue;
+use crate::{alloc::Allocator, error::EnqueueError, queue::VectoredQueue};
+
+fn broken_pipe<T>(_: EnqueueError<T>) -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, EnqueueError(()))
+}
+
+/// Each write enqueues the written bytes as an item, with
+/// [`enqueue_unbounded`](VectoredQueue::enqueue_unbounded), failing with
+/// [`io::ErrorKind::BrokenPipe`] if it is rejected; flushing does nothing.
+impl<T, A: Allocator + Clone> Write for &VectoredQueue<T, A>
+where
+    T: for<'a> From<&'a [u8]> + AsRef<[u8]>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.enqueue_unbounded(T::from(buf)).map_err(broken_pipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// See the implementation for [`VectoredQueue`]; the consumer is woken as with
+/// [`enqueue_unbounded`](SyncVectoredQueue::enqueue_unbounded).
+#[cfg(feature = "sync")]
+impl<T, A: Allocator + Clone> Write for &SyncVectoredQueue<T, A>
+where
+    T: for<'a> From<&'a [u8]> + AsRef<[u8]>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.enqueue_unbounded(T::from(buf)).map_err(broken_pipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// See the implementation for [`VectoredQueue`]; writing never waits, as enqueuing is
+/// unbounded.
+#[cfg(feature = "async")]
+impl<T, A: Allocator + Clone> Write for &AsyncVectoredQueue<T, A>
+where
+    T: for<'a> From<&'a [u8]> + AsRef<[u8]>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.enqueue_unbounded(T::from(buf)).map_err(broken_pipe)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Write};
+
+    use crate::VectoredQueue;
+
+    #[test]
+    fn serialize() {
+        let queue = VectoredQueue::<Vec<u8>>::with_capacity(2);
+        let value = serde_json::json!({ "id": 42, "tags": ["a", "b"] });
+        serde_json::to_writer(&queue, &value).unwrap();
+        let mut bytes = Vec::new();
+        while let Some(vectored) = queue.try_dequeue_vectored().unwrap().vectored() {
+            bytes.extend(vectored.iter().flat_map(|slice| slice.iter()));
+        }
+        let parsed: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(parsed, value);
+        queue.close();
+        let err = (&queue).write(b"{}").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        (&queue).flush().unwrap();
+        queue.debug_assert_invariants();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn sync_write() {
+        let queue = crate::SyncVectoredQueue::<Vec<u8>>::with_capacity(4);
+        write!(&queue, "{}-{}", 1, 2).unwrap();
+        let vectored = queue.dequeue_vectored().unwrap();
+        assert!(vectored == b"1-2"[..]);
+        drop(vectored);
+        queue.as_queue().debug_assert_invariants();
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use vectored_queue::{AllocError, Allocator, VectoredQueue};
+
+thread_local! {
+    static GLOBAL_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// Count the global allocations of the current thread, as tests run concurrently.
+struct CountingGlobal;
+
+unsafe impl GlobalAlloc for CountingGlobal {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = GLOBAL_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingGlobal = CountingGlobal;
+
+fn global_allocations() -> usize {
+    GLOBAL_ALLOCATIONS.with(Cell::get)
+}
+
+#[derive(Clone, Copy)]
+struct CountingAlloc<'a>(&'a AtomicUsize);
+
+unsafe impl Allocator for CountingAlloc<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        let ptr = if layout.size() == 0 {
+            layout.align() as *mut u8
+        } else {
+            unsafe { System.alloc(layout) }
+        };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            System.dealloc(ptr.as_ptr(), layout);
+        }
+    }
+}
+
+#[test]
+fn no_global_allocation() {
+    let allocations = AtomicUsize::new(0);
+    let before = global_allocations();
+    let queue = VectoredQueue::with_capacity_in(2, CountingAlloc(&allocations));
+    for i in 0..8 {
+        queue.enqueue_unbounded([i]).unwrap();
+    }
+    // overflowing items are moved into grown buffers
+    queue.set_capacity(8);
+    let mut dequeued = 0;
+    while dequeued < 8 {
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        dequeued += vectored.len();
+    }
+    assert!(queue.is_empty());
+    drop(queue);
+    assert_eq!(global_allocations(), before);
+    assert!(allocations.load(Ordering::Relaxed) > 0);
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn sync_no_global_allocation() {
+    let allocations = AtomicUsize::new(0);
+    let before = global_allocations();
+    let queue = vectored_queue::SyncVectoredQueue::with_capacity_in(2, CountingAlloc(&allocations));
+    for i in 0..4 {
+        queue.enqueue_unbounded([i]).unwrap();
+    }
+    let mut dequeued = 0;
+    while dequeued < 4 {
+        dequeued += queue.dequeue_vectored().unwrap().len();
+    }
+    drop(queue);
+    assert_eq!(global_allocations(), before);
+    assert!(allocations.load(Ordering::Relaxed) > 0);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_no_global_allocation() {
+    let allocations = AtomicUsize::new(0);
+    let before = global_allocations();
+    let queue =
+        vectored_queue::AsyncVectoredQueue::with_capacity_in(2, CountingAlloc(&allocations));
+    for i in 0..4 {
+        queue.enqueue_unbounded([i]).unwrap();
+    }
+    let mut dequeued = 0;
+    while dequeued < 4 {
+        let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+        dequeued += vectored.len();
+    }
+    drop(queue);
+    assert_eq!(global_allocations(), before);
+    assert!(allocations.load(Ordering::Relaxed) > 0);
+}
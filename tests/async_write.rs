@@ -0,0 +1,54 @@
+use std::pin::pin;
+
+use futures::{executor::block_on, future::join, poll};
+use tokio::io::{duplex, AsyncReadExt};
+use vectored_queue::AsyncVectoredQueue;
+
+#[test]
+fn dequeue_write_all() {
+    let queue = AsyncVectoredQueue::<Box<[u8]>>::with_capacity(4);
+    // the duplex buffer is smaller than the batch, so it is written in several parts
+    let (mut writer, mut reader) = duplex(3);
+    let frames: [&[u8]; 3] = [b"hello", b" ", b"world"];
+    for frame in frames {
+        queue.try_enqueue(frame.into()).unwrap();
+    }
+    queue.close();
+    let write = async {
+        let mut written = 0;
+        loop {
+            match queue.dequeue_write_all(&mut writer).await.unwrap() {
+                0 => break,
+                n => written += n,
+            }
+        }
+        drop(writer);
+        written
+    };
+    let read = async {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        buf
+    };
+    let (written, read) = block_on(join(write, read));
+    assert_eq!(written, 11);
+    assert_eq!(read, b"hello world");
+}
+
+#[test]
+fn dequeue_write_all_cancelled() {
+    let queue = AsyncVectoredQueue::<Box<[u8]>>::with_capacity(4);
+    let (mut writer, mut reader) = duplex(2);
+    queue.try_enqueue([0, 1].into()).unwrap();
+    queue.try_enqueue([2, 3].into()).unwrap();
+    block_on(async {
+        let mut write = pin!(queue.dequeue_write_all(&mut writer));
+        assert!(poll!(write.as_mut()).is_pending());
+    });
+    let mut buf = [0; 2];
+    block_on(reader.read_exact(&mut buf)).unwrap();
+    assert_eq!(buf, [0, 1]);
+    // the unsent batch is left in the queue, whole
+    let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+    assert!(vectored.eq_chunks(&[&[0, 1], &[2, 3]]));
+}
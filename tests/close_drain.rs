@@ -0,0 +1,52 @@
+use vectored_queue::error::DequeueError;
+
+const ITEMS: u8 = 5;
+
+#[test]
+fn close_drain() {
+    let queue = vectored_queue::VectoredQueue::with_capacity(ITEMS.into());
+    for i in 0..ITEMS {
+        queue.try_enqueue([i]).unwrap();
+    }
+    queue.close();
+    let vectored = queue.try_dequeue_vectored().unwrap().vectored().unwrap();
+    assert_eq!(vectored.len(), ITEMS.into());
+    drop(vectored);
+    assert_eq!(
+        queue.try_dequeue_vectored().unwrap_err(),
+        DequeueError::Closed
+    );
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn sync_close_drain() {
+    let queue = vectored_queue::SyncVectoredQueue::with_capacity(ITEMS.into());
+    for i in 0..ITEMS {
+        queue.try_enqueue([i]).unwrap();
+    }
+    queue.close();
+    let vectored = queue.dequeue_vectored().unwrap();
+    assert_eq!(vectored.len(), ITEMS.into());
+    drop(vectored);
+    assert_eq!(queue.dequeue_vectored().unwrap_err(), DequeueError::Closed);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_close_drain() {
+    use futures::executor::block_on;
+
+    let queue = vectored_queue::AsyncVectoredQueue::with_capacity(ITEMS.into());
+    for i in 0..ITEMS {
+        queue.try_enqueue([i]).unwrap();
+    }
+    queue.close();
+    let vectored = block_on(queue.dequeue_vectored()).unwrap();
+    assert_eq!(vectored.len(), ITEMS.into());
+    drop(vectored);
+    assert_eq!(
+        block_on(queue.dequeue_vectored()).unwrap_err(),
+        DequeueError::Closed
+    );
+}
@@ -0,0 +1,43 @@
+#![cfg(loom)]
+use loom::{sync::Arc, thread};
+use vectored_queue::{TryDequeueResult, VectoredQueue};
+
+fn drain(queue: &VectoredQueue<[u8; 1]>) -> usize {
+    let mut dequeued = 0;
+    loop {
+        match queue.try_dequeue_vectored() {
+            Ok(TryDequeueResult::Vectored(vectored)) => dequeued += vectored.len(),
+            Ok(TryDequeueResult::Pending) => thread::yield_now(),
+            Ok(TryDequeueResult::Empty) | Err(_) => return dequeued,
+        }
+    }
+}
+
+#[test]
+fn enqueue_unbounded_reopen_dequeue() {
+    loom::model(|| {
+        let queue = Arc::new(VectoredQueue::with_capacity(1));
+        let producer = thread::spawn({
+            let queue = queue.clone();
+            move || {
+                (0..2)
+                    .filter(|&i| queue.enqueue_unbounded([i]).is_ok())
+                    .count()
+            }
+        });
+        let closer = thread::spawn({
+            let queue = queue.clone();
+            move || {
+                queue.close();
+                queue.reopen();
+            }
+        });
+        let mut dequeued = 0;
+        if let Ok(TryDequeueResult::Vectored(vectored)) = queue.try_dequeue_vectored() {
+            dequeued += vectored.len();
+        }
+        let enqueued = producer.join().unwrap();
+        closer.join().unwrap();
+        assert_eq!(dequeued + drain(&queue), enqueued);
+    });
+}